@@ -0,0 +1,149 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rust_rest::utils::rate_limiter::{MemoryStore, Quota, RateLimitStore};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::runtime::Runtime;
+use tokio::sync::Mutex;
+
+/// The store the sharded `MemoryStore` replaced: one lock around a log of
+/// request times per key.
+struct SlidingLogStore {
+    attempts: Mutex<HashMap<String, Vec<Instant>>>,
+}
+
+impl SlidingLogStore {
+    fn new() -> Self {
+        Self { attempts: Mutex::new(HashMap::new()) }
+    }
+
+    async fn check(&self, key: &str, quota: &Quota) -> bool {
+        let now = Instant::now();
+        let window = Duration::from_millis(quota.window_ms());
+
+        let mut attempts = self.attempts.lock().await;
+        let attempt_times = attempts.entry(key.to_string()).or_default();
+        attempt_times.retain(|&time| now.duration_since(time) < window);
+
+        let allowed = attempt_times.len() < quota.max_requests;
+        if allowed {
+            attempt_times.push(now);
+        }
+        allowed
+    }
+}
+
+const KEYS: usize = 10_000;
+const TASKS: usize = 8;
+const CHECKS_PER_TASK: usize = 1_000;
+
+fn keys() -> Vec<String> {
+    (0..KEYS).map(|i| format!("bench:{}", i)).collect()
+}
+
+fn single_key(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let quota = Quota::new(100, 1);
+    let sharded = MemoryStore::new(KEYS);
+    let sliding = SlidingLogStore::new();
+
+    let mut group = c.benchmark_group("single_key");
+    group.bench_function("sharded", |b| {
+        b.to_async(&rt).iter(|| async { sharded.check("bench", &quota).await.unwrap() })
+    });
+    group.bench_function("sliding_log", |b| {
+        b.to_async(&rt).iter(|| async { sliding.check("bench", &quota).await })
+    });
+    group.finish();
+}
+
+fn many_keys(c: &mut Criterion) {
+    let rt = Runtime::new().unwrap();
+    let quota = Quota::new(100, 60);
+    let keys = keys();
+    let sharded = MemoryStore::new(KEYS);
+    let sliding = SlidingLogStore::new();
+
+    let mut group = c.benchmark_group("many_keys");
+    group.bench_function("sharded", |b| {
+        let mut i = 0;
+        b.to_async(&rt).iter(|| {
+            i = (i + 1) % KEYS;
+            let key = &keys[i];
+            let store = &sharded;
+            let quota = &quota;
+            async move { store.check(key, quota).await.unwrap() }
+        })
+    });
+    group.bench_function("sliding_log", |b| {
+        let mut i = 0;
+        b.to_async(&rt).iter(|| {
+            i = (i + 1) % KEYS;
+            let key = &keys[i];
+            let store = &sliding;
+            let quota = &quota;
+            async move { store.check(key, quota).await }
+        })
+    });
+    group.finish();
+}
+
+/// Several tasks checking distinct keys at once, where the single lock of the
+/// old store serialises every request.
+fn contended(c: &mut Criterion) {
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(TASKS)
+        .build()
+        .unwrap();
+    let quota = Quota::new(100, 60);
+    let keys = Arc::new(keys());
+    let sharded = Arc::new(MemoryStore::new(KEYS));
+    let sliding = Arc::new(SlidingLogStore::new());
+
+    let mut group = c.benchmark_group("contended");
+    group.throughput(Throughput::Elements((TASKS * CHECKS_PER_TASK) as u64));
+    group.bench_function(BenchmarkId::new("sharded", TASKS), |b| {
+        b.to_async(&rt).iter(|| {
+            let tasks: Vec<_> = (0..TASKS)
+                .map(|task| {
+                    let (store, keys, quota) = (sharded.clone(), keys.clone(), quota);
+                    tokio::spawn(async move {
+                        for i in 0..CHECKS_PER_TASK {
+                            let key = &keys[(task * CHECKS_PER_TASK + i) % KEYS];
+                            store.check(key, &quota).await.unwrap();
+                        }
+                    })
+                })
+                .collect();
+            async move {
+                for task in tasks {
+                    task.await.unwrap();
+                }
+            }
+        })
+    });
+    group.bench_function(BenchmarkId::new("sliding_log", TASKS), |b| {
+        b.to_async(&rt).iter(|| {
+            let tasks: Vec<_> = (0..TASKS)
+                .map(|task| {
+                    let (store, keys, quota) = (sliding.clone(), keys.clone(), quota);
+                    tokio::spawn(async move {
+                        for i in 0..CHECKS_PER_TASK {
+                            let key = &keys[(task * CHECKS_PER_TASK + i) % KEYS];
+                            store.check(key, &quota).await;
+                        }
+                    })
+                })
+                .collect();
+            async move {
+                for task in tasks {
+                    task.await.unwrap();
+                }
+            }
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, single_key, many_keys, contended);
+criterion_main!(benches);
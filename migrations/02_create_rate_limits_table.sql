@@ -0,0 +1,7 @@
+-- GCRA state for RATE_LIMIT_BACKEND=postgres; tat_ms is in epoch milliseconds.
+CREATE TABLE rate_limits (
+    key TEXT PRIMARY KEY,
+    tat_ms BIGINT NOT NULL
+);
+
+CREATE INDEX idx_rate_limits_tat_ms ON rate_limits(tat_ms);
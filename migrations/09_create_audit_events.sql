@@ -0,0 +1,16 @@
+-- Record of security-relevant actions. `actor_id` is who acted and
+-- `subject_id` the user acted upon; they differ for admin actions and
+-- impersonation. No foreign keys, so events outlive the users they name.
+CREATE TABLE audit_events (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    actor_id UUID,
+    subject_id UUID,
+    action VARCHAR(64) NOT NULL,
+    details JSONB NOT NULL DEFAULT '{}',
+    ip_address VARCHAR(45),
+    user_agent TEXT,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_audit_events_subject_id ON audit_events(subject_id, created_at);
+CREATE INDEX idx_audit_events_actor_id ON audit_events(actor_id, created_at);
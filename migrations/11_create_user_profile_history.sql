@@ -0,0 +1,13 @@
+-- Previous and new values of the profile fields changed by each update,
+-- as {"phone": {"before": ..., "after": ...}}. `actor_id` is who made the
+-- change: the user, or an admin impersonating them.
+CREATE TABLE user_profile_history (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    actor_id UUID,
+    changes JSONB NOT NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX idx_user_profile_history_user_id ON user_profile_history(user_id, created_at);
+CREATE INDEX idx_user_profile_history_created_at ON user_profile_history(created_at);
@@ -0,0 +1,21 @@
+-- Set when the user has shown they receive mail at the address, e.g. by
+-- registering through an emailed invitation. Cleared when the email changes.
+ALTER TABLE users ADD COLUMN email_verified_at TIMESTAMP WITH TIME ZONE;
+
+-- Invitations to join an organization. Only the hash of the emailed token is
+-- stored. An email has at most one pending invitation per organization;
+-- inviting it again replaces the old one, whose token then stops working.
+CREATE TABLE org_invitations (
+    id UUID PRIMARY KEY DEFAULT uuid_generate_v4(),
+    org_id UUID NOT NULL REFERENCES organizations(id) ON DELETE CASCADE,
+    email VARCHAR(255) NOT NULL,
+    role VARCHAR(20) NOT NULL CHECK (role IN ('owner', 'admin', 'member')),
+    token_hash VARCHAR(64) NOT NULL UNIQUE,
+    invited_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    expires_at TIMESTAMP WITH TIME ZONE NOT NULL,
+    accepted_at TIMESTAMP WITH TIME ZONE,
+    accepted_by UUID REFERENCES users(id) ON DELETE SET NULL,
+    created_at TIMESTAMP WITH TIME ZONE NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE UNIQUE INDEX idx_org_invitations_pending ON org_invitations(org_id, email) WHERE accepted_at IS NULL;
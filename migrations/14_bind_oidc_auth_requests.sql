@@ -0,0 +1,6 @@
+-- Ties each in-flight authorization request to the browser that started it,
+-- through a hash of a random value kept in an HttpOnly cookie. Without it a
+-- link URL started on one account could be completed by someone else.
+-- Requests still in flight have no binding and are dropped.
+DELETE FROM oidc_auth_requests;
+ALTER TABLE oidc_auth_requests ADD COLUMN browser_binding_hash VARCHAR(64) NOT NULL;
@@ -0,0 +1,4 @@
+-- A link is stored for every request, also for emails without an account, so
+-- both take the same database work and response times do not reveal which
+-- emails are registered. Those links have no user and are never sent.
+ALTER TABLE magic_links ALTER COLUMN user_id DROP NOT NULL;
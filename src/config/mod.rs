@@ -5,4 +5,492 @@ pub fn get_port() -> u16 {
         .unwrap_or_else(|_| "8080".to_string())
         .parse()
         .expect("PORT must be a number")
-}
\ No newline at end of file
+}
+
+pub fn get_service_name() -> String {
+    env::var("OTEL_SERVICE_NAME").unwrap_or_else(|_| env!("CARGO_PKG_NAME").to_string())
+}
+
+/// One of `otlp`, `stdout` or `none`.
+pub fn get_traces_exporter() -> String {
+    env::var("OTEL_TRACES_EXPORTER")
+        .unwrap_or_else(|_| "none".to_string())
+        .to_lowercase()
+}
+
+pub fn get_otlp_endpoint() -> String {
+    env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+        .unwrap_or_else(|_| "http://localhost:4318/v1/traces".to_string())
+}
+
+pub fn get_traces_filter() -> String {
+    env::var("OTEL_TRACES_FILTER").unwrap_or_else(|_| "info".to_string())
+}
+
+/// One of `common`, `combined` or `json`.
+pub fn get_access_log_format() -> String {
+    env::var("ACCESS_LOG_FORMAT").unwrap_or_else(|_| "combined".to_string())
+}
+
+/// Comma-separated path prefixes that are left out of the access log.
+pub fn get_access_log_exclude() -> Vec<String> {
+    env::var("ACCESS_LOG_EXCLUDE")
+        .unwrap_or_else(|_| "/health,/metrics".to_string())
+        .split(',')
+        .map(|path| path.trim().trim_end_matches('/').to_string())
+        .filter(|path| !path.is_empty())
+        .collect()
+}
+
+pub fn get_access_log_slow_ms() -> u64 {
+    env::var("ACCESS_LOG_SLOW_MS")
+        .unwrap_or_else(|_| "1000".to_string())
+        .parse()
+        .expect("ACCESS_LOG_SLOW_MS must be a number")
+}
+
+/// Reads `CORS_<SCOPE>_<KEY>`, e.g. `CORS_API_ALLOWED_ORIGINS`.
+pub fn get_cors_setting(scope: &str, key: &str) -> Option<String> {
+    env::var(format!("CORS_{}_{}", scope, key)).ok()
+}
+
+/// Reads `SECURITY_<KEY>`, e.g. `SECURITY_CSP`. `off` disables the header.
+pub fn get_security_header(key: &str) -> Option<String> {
+    env::var(format!("SECURITY_{}", key)).ok()
+}
+
+/// Comma-separated path prefixes whose responses are sent with `Cache-Control: no-store`.
+pub fn get_security_no_store_paths() -> Vec<String> {
+    env::var("SECURITY_NO_STORE_PATHS")
+        .unwrap_or_else(|_| "/api/auth,/api/users".to_string())
+        .split(',')
+        .map(|path| path.trim().to_string())
+        .filter(|path| !path.is_empty())
+        .collect()
+}
+
+/// Reads `RATE_LIMIT_<SCOPE>` as `<max_requests>/<window_secs>`, e.g. `100/60`.
+/// The bare `RATE_LIMIT` variable is the per-minute limit for the `API` scope.
+pub fn get_rate_limit(scope: &str) -> Option<(usize, u64)> {
+    if let Ok(value) = env::var(format!("RATE_LIMIT_{}", scope)) {
+        let (max, window) = value.split_once('/')?;
+        return Some((max.trim().parse().ok()?, window.trim().parse().ok()?));
+    }
+    if scope == "API" {
+        return env::var("RATE_LIMIT").ok()?.parse().ok().map(|max| (max, 60));
+    }
+    None
+}
+
+/// Comma-separated IPs or CIDR ranges of reverse proxies allowed to set `X-Forwarded-For`.
+pub fn get_trusted_proxies() -> Vec<String> {
+    env::var("TRUSTED_PROXIES")
+        .unwrap_or_default()
+        .split(',')
+        .map(|entry| entry.trim().to_string())
+        .filter(|entry| !entry.is_empty())
+        .collect()
+}
+
+/// Where rate limit state lives: `memory` (per replica), `postgres` or `redis`.
+pub fn get_rate_limit_backend() -> String {
+    env::var("RATE_LIMIT_BACKEND")
+        .unwrap_or_else(|_| "memory".to_string())
+        .to_lowercase()
+}
+
+pub fn get_redis_url() -> String {
+    env::var("REDIS_URL").unwrap_or_else(|_| "redis://127.0.0.1:6379".to_string())
+}
+
+/// Upper bound on keys tracked by the in-memory rate limit store.
+pub fn get_rate_limit_max_keys() -> usize {
+    env::var("RATE_LIMIT_MAX_KEYS")
+        .unwrap_or_else(|_| "100000".to_string())
+        .parse()
+        .expect("RATE_LIMIT_MAX_KEYS must be a number")
+}
+
+/// Seconds between sweeps of idle rate limit keys; must be at least 1.
+pub fn get_rate_limit_sweep_secs() -> u64 {
+    env::var("RATE_LIMIT_SWEEP_SECS")
+        .unwrap_or_else(|_| "60".to_string())
+        .parse()
+        .ok()
+        .filter(|secs| *secs > 0)
+        .expect("RATE_LIMIT_SWEEP_SECS must be a positive number")
+}
+
+/// Seconds between sweeps of stale login lockouts; must be at least 1.
+pub fn get_lockout_sweep_secs() -> u64 {
+    env::var("LOCKOUT_SWEEP_SECS")
+        .unwrap_or_else(|_| "3600".to_string())
+        .parse()
+        .ok()
+        .filter(|secs| *secs > 0)
+        .expect("LOCKOUT_SWEEP_SECS must be a positive number")
+}
+
+/// Failed logins for one email before it is locked.
+pub fn get_lockout_threshold() -> i32 {
+    env::var("LOCKOUT_THRESHOLD")
+        .unwrap_or_else(|_| "5".to_string())
+        .parse()
+        .expect("LOCKOUT_THRESHOLD must be a number")
+}
+
+/// Length of the first lockout; each further lockout doubles it.
+pub fn get_lockout_base_secs() -> i64 {
+    env::var("LOCKOUT_BASE_SECS")
+        .unwrap_or_else(|_| "60".to_string())
+        .parse()
+        .expect("LOCKOUT_BASE_SECS must be a number")
+}
+
+pub fn get_lockout_max_secs() -> i64 {
+    env::var("LOCKOUT_MAX_SECS")
+        .unwrap_or_else(|_| "86400".to_string())
+        .parse()
+        .expect("LOCKOUT_MAX_SECS must be a number")
+}
+
+pub fn get_unlock_token_ttl_mins() -> i64 {
+    env::var("UNLOCK_TOKEN_TTL_MINS")
+        .unwrap_or_else(|_| "60".to_string())
+        .parse()
+        .expect("UNLOCK_TOKEN_TTL_MINS must be a number")
+}
+
+/// Lifetime of sign-in links sent by email.
+pub fn get_magic_link_ttl_mins() -> i64 {
+    env::var("MAGIC_LINK_TTL_MINS")
+        .unwrap_or_else(|_| "15".to_string())
+        .parse()
+        .expect("MAGIC_LINK_TTL_MINS must be a number")
+}
+
+/// Page the sign-in link opens, with the token appended as `?token=`. It
+/// should hand the token to the app that asked for the link, which
+/// exchanges it at `POST /api/auth/magic-link/consume`.
+pub fn get_magic_link_url() -> String {
+    env::var("MAGIC_LINK_URL").unwrap_or_else(|_| format!("{}/magic-link", get_public_url()))
+}
+
+/// Base URL used to build links in emails.
+pub fn get_public_url() -> String {
+    env::var("PUBLIC_URL")
+        .unwrap_or_else(|_| format!("http://localhost:{}", get_port()))
+        .trim_end_matches('/')
+        .to_string()
+}
+
+/// One of `log` (write emails to the log) or `http` (POST them to `MAILER_HTTP_URL`).
+pub fn get_mailer() -> String {
+    env::var("MAILER")
+        .unwrap_or_else(|_| "log".to_string())
+        .to_lowercase()
+}
+
+pub fn get_mailer_http_url() -> Option<String> {
+    env::var("MAILER_HTTP_URL").ok()
+}
+
+pub fn get_mailer_http_token() -> Option<String> {
+    env::var("MAILER_HTTP_TOKEN").ok()
+}
+
+pub fn get_mail_from() -> String {
+    env::var("MAIL_FROM").unwrap_or_else(|_| "no-reply@localhost".to_string())
+}
+
+/// `explicit` reports "Email already exists" on registration; `uniform`
+/// answers every registration the same way and emails the existing owner.
+pub fn get_registration_mode() -> String {
+    env::var("REGISTRATION_MODE")
+        .unwrap_or_else(|_| "explicit".to_string())
+        .to_lowercase()
+}
+
+/// Scheme for new password hashes: `argon2id` or `bcrypt`. Stored hashes of
+/// either scheme are always accepted.
+pub fn get_password_hash_algorithm() -> String {
+    env::var("PASSWORD_HASH_ALGORITHM")
+        .unwrap_or_else(|_| "argon2id".to_string())
+        .to_lowercase()
+}
+
+/// Argon2id memory cost; the default follows the OWASP recommendation.
+pub fn get_argon2_memory_kib() -> u32 {
+    env::var("ARGON2_MEMORY_KIB")
+        .unwrap_or_else(|_| "19456".to_string())
+        .parse()
+        .expect("ARGON2_MEMORY_KIB must be a number")
+}
+
+pub fn get_argon2_iterations() -> u32 {
+    env::var("ARGON2_ITERATIONS")
+        .unwrap_or_else(|_| "2".to_string())
+        .parse()
+        .expect("ARGON2_ITERATIONS must be a number")
+}
+
+pub fn get_argon2_parallelism() -> u32 {
+    env::var("ARGON2_PARALLELISM")
+        .unwrap_or_else(|_| "1".to_string())
+        .parse()
+        .expect("ARGON2_PARALLELISM must be a number")
+}
+
+/// Server-side secret mixed into Argon2id hashes; kept out of the database.
+pub fn get_password_pepper() -> Option<String> {
+    env::var("PASSWORD_PEPPER").ok().filter(|pepper| !pepper.is_empty())
+}
+
+pub fn get_bcrypt_cost() -> u32 {
+    env::var("BCRYPT_COST")
+        .unwrap_or_else(|_| bcrypt::DEFAULT_COST.to_string())
+        .parse()
+        .expect("BCRYPT_COST must be a number")
+}
+
+/// Password hashes computed at once; defaults to the number of CPUs.
+pub fn get_password_hash_concurrency() -> usize {
+    env::var("PASSWORD_HASH_CONCURRENCY")
+        .ok()
+        .map(|value| value.parse().expect("PASSWORD_HASH_CONCURRENCY must be a number"))
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(4, |n| n.get()))
+}
+
+/// Password hashes allowed to wait for a slot before requests get a 503.
+pub fn get_password_hash_queue() -> usize {
+    env::var("PASSWORD_HASH_QUEUE")
+        .unwrap_or_else(|_| "64".to_string())
+        .parse()
+        .expect("PASSWORD_HASH_QUEUE must be a number")
+}
+
+pub fn get_password_min_length() -> usize {
+    env::var("PASSWORD_MIN_LENGTH")
+        .unwrap_or_else(|_| "8".to_string())
+        .parse()
+        .expect("PASSWORD_MIN_LENGTH must be a number")
+}
+
+pub fn get_password_max_length() -> usize {
+    env::var("PASSWORD_MAX_LENGTH")
+        .unwrap_or_else(|_| "128".to_string())
+        .parse()
+        .expect("PASSWORD_MAX_LENGTH must be a number")
+}
+
+/// Comma-separated classes a password must contain: `lower`, `upper`,
+/// `letter`, `digit` and `symbol`.
+pub fn get_password_required_classes() -> Vec<String> {
+    env::var("PASSWORD_REQUIRED_CLASSES")
+        .unwrap_or_else(|_| "letter,digit".to_string())
+        .split(',')
+        .map(|class| class.trim().to_string())
+        .filter(|class| !class.is_empty())
+        .collect()
+}
+
+pub fn get_password_reject_personal_info() -> bool {
+    env::var("PASSWORD_REJECT_PERSONAL_INFO")
+        .map(|value| value != "false" && value != "0")
+        .unwrap_or(true)
+}
+
+/// Minimum zxcvbn score (0-4); 0 disables the check.
+pub fn get_password_min_strength() -> u8 {
+    env::var("PASSWORD_MIN_STRENGTH")
+        .unwrap_or_else(|_| "3".to_string())
+        .parse()
+        .expect("PASSWORD_MIN_STRENGTH must be a number between 0 and 4")
+}
+
+/// Path of a file of SHA-1 prefixes of breached passwords.
+pub fn get_password_breached_list() -> Option<String> {
+    env::var("PASSWORD_BREACHED_LIST").ok().filter(|path| !path.is_empty())
+}
+
+pub fn get_jwt_secret() -> Option<String> {
+    env::var("JWT_SECRET").ok().filter(|secret| !secret.is_empty())
+}
+
+/// One of `HS256`, `RS256`, `ES256` or `EdDSA`.
+pub fn get_jwt_algorithm() -> String {
+    env::var("JWT_ALGORITHM")
+        .unwrap_or_else(|_| "HS256".to_string())
+        .to_uppercase()
+}
+
+/// PEM private key used to sign tokens with an asymmetric algorithm.
+pub fn get_jwt_private_key_file() -> Option<String> {
+    env::var("JWT_PRIVATE_KEY_FILE").ok().filter(|path| !path.is_empty())
+}
+
+/// `kid` of the signing key; defaults to its RFC 7638 thumbprint.
+pub fn get_jwt_key_id() -> Option<String> {
+    env::var("JWT_KEY_ID").ok().filter(|kid| !kid.is_empty())
+}
+
+/// Comma-separated `[kid=]path` entries of extra PEM public keys whose
+/// tokens are accepted, e.g. the previous key during a rotation.
+pub fn get_jwt_public_key_files() -> Vec<String> {
+    env::var("JWT_PUBLIC_KEY_FILES")
+        .unwrap_or_default()
+        .split(',')
+        .map(|entry| entry.trim().to_string())
+        .filter(|entry| !entry.is_empty())
+        .collect()
+}
+
+/// `iss` put in issued tokens and required of verified ones.
+pub fn get_jwt_issuer() -> Option<String> {
+    env::var("JWT_ISSUER").ok().filter(|issuer| !issuer.is_empty())
+}
+
+/// `aud` put in issued tokens and required of verified ones.
+pub fn get_jwt_audience() -> Option<String> {
+    env::var("JWT_AUDIENCE").ok().filter(|audience| !audience.is_empty())
+}
+
+/// Clock skew tolerated when checking `exp` and `nbf`.
+pub fn get_jwt_leeway_secs() -> u64 {
+    env::var("JWT_LEEWAY_SECS")
+        .unwrap_or_else(|_| "60".to_string())
+        .parse()
+        .expect("JWT_LEEWAY_SECS must be a number")
+}
+
+pub fn get_jwt_access_token_ttl_mins() -> i64 {
+    env::var("JWT_ACCESS_TOKEN_TTL_MINS")
+        .unwrap_or_else(|_| "1440".to_string())
+        .parse()
+        .expect("JWT_ACCESS_TOKEN_TTL_MINS must be a number")
+}
+
+/// Lifetime of the tokens admins get to act as another user; kept short
+/// since they bypass that user's credentials.
+pub fn get_impersonation_token_ttl_mins() -> i64 {
+    env::var("IMPERSONATION_TOKEN_TTL_MINS")
+        .unwrap_or_else(|_| "15".to_string())
+        .parse()
+        .expect("IMPERSONATION_TOKEN_TTL_MINS must be a number")
+}
+
+pub fn get_api_key_max_per_user() -> i64 {
+    env::var("API_KEY_MAX_PER_USER")
+        .unwrap_or_else(|_| "10".to_string())
+        .parse()
+        .expect("API_KEY_MAX_PER_USER must be a number")
+}
+
+/// Comma-separated names of the OpenID Connect providers users can sign in
+/// with; each is configured by `OIDC_<NAME>_*`.
+pub fn get_oidc_providers() -> Vec<String> {
+    env::var("OIDC_PROVIDERS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|name| name.trim().to_lowercase())
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+/// Reads `OIDC_<PROVIDER>_<KEY>`, e.g. `OIDC_GOOGLE_CLIENT_ID`.
+pub fn get_oidc_provider_setting(provider: &str, key: &str) -> Option<String> {
+    env::var(format!("OIDC_{}_{}", provider.to_uppercase(), key))
+        .ok()
+        .filter(|value| !value.is_empty())
+}
+
+/// How long a user has to complete sign-in at the provider.
+pub fn get_oidc_auth_request_ttl_mins() -> i64 {
+    env::var("OIDC_AUTH_REQUEST_TTL_MINS")
+        .unwrap_or_else(|_| "10".to_string())
+        .parse()
+        .expect("OIDC_AUTH_REQUEST_TTL_MINS must be a number")
+}
+
+/// How often a session's last-seen time is updated while it is in use.
+pub fn get_session_touch_interval_secs() -> i64 {
+    env::var("SESSION_TOUCH_INTERVAL_SECS")
+        .unwrap_or_else(|_| "60".to_string())
+        .parse()
+        .expect("SESSION_TOUCH_INTERVAL_SECS must be a number")
+}
+
+/// Days profile changes are kept; 0 keeps them forever.
+pub fn get_profile_history_retention_days() -> i64 {
+    env::var("PROFILE_HISTORY_RETENTION_DAYS")
+        .unwrap_or_else(|_| "365".to_string())
+        .parse()
+        .expect("PROFILE_HISTORY_RETENTION_DAYS must be a number")
+}
+
+/// Seconds between prunes of expired profile history; must be at least 1.
+pub fn get_profile_history_prune_secs() -> u64 {
+    env::var("PROFILE_HISTORY_PRUNE_SECS")
+        .unwrap_or_else(|_| "3600".to_string())
+        .parse()
+        .ok()
+        .filter(|secs| *secs > 0)
+        .expect("PROFILE_HISTORY_PRUNE_SECS must be a positive number")
+}
+
+/// Lifetime of organization invitations when the inviter does not pick one.
+pub fn get_org_invitation_ttl_days() -> i64 {
+    env::var("ORG_INVITATION_TTL_DAYS")
+        .unwrap_or_else(|_| "7".to_string())
+        .parse()
+        .expect("ORG_INVITATION_TTL_DAYS must be a number")
+}
+
+/// Page an invitation email links to, with the token appended as `?token=`.
+/// It accepts at `POST /api/orgs/invitations/accept` for signed-in users,
+/// or registers at `POST /api/auth/register/invitation`.
+pub fn get_org_invitation_url() -> String {
+    env::var("ORG_INVITATION_URL").unwrap_or_else(|_| format!("{}/invitations", get_public_url()))
+}
+
+/// WebAuthn relying party ID: the domain passkeys are bound to (default: the
+/// host of `PUBLIC_URL`).
+pub fn get_webauthn_rp_id() -> String {
+    env::var("WEBAUTHN_RP_ID").unwrap_or_else(|_| {
+        reqwest::Url::parse(&get_public_url())
+            .ok()
+            .and_then(|url| url.host_str().map(String::from))
+            .unwrap_or_else(|| "localhost".to_string())
+    })
+}
+
+/// Name shown by the browser when creating a passkey.
+pub fn get_webauthn_rp_name() -> String {
+    env::var("WEBAUTHN_RP_NAME").unwrap_or_else(|_| "rust_rest".to_string())
+}
+
+/// Comma-separated origins WebAuthn ceremonies may come from (default: `PUBLIC_URL`).
+pub fn get_webauthn_origins() -> Vec<String> {
+    env::var("WEBAUTHN_ORIGINS")
+        .unwrap_or_else(|_| get_public_url())
+        .split(',')
+        .map(|origin| origin.trim().trim_end_matches('/').to_string())
+        .filter(|origin| !origin.is_empty())
+        .collect()
+}
+
+/// Time a user has to answer a WebAuthn challenge.
+pub fn get_webauthn_challenge_ttl_secs() -> i64 {
+    env::var("WEBAUTHN_CHALLENGE_TTL_SECS")
+        .unwrap_or_else(|_| "300".to_string())
+        .parse()
+        .expect("WEBAUTHN_CHALLENGE_TTL_SECS must be a number")
+}
+
+/// Passkeys a user may register.
+pub fn get_webauthn_max_credentials_per_user() -> i64 {
+    env::var("WEBAUTHN_MAX_CREDENTIALS_PER_USER")
+        .unwrap_or_else(|_| "10".to_string())
+        .parse()
+        .expect("WEBAUTHN_MAX_CREDENTIALS_PER_USER must be a number")
+}
@@ -0,0 +1,102 @@
+use actix_web::{get, post, web, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+use log::{error, warn};
+use validator::Validate;
+use crate::domains::admin::service::{impersonate_user, unlock_user, user_profile_history};
+use crate::domains::user::entity::ProfileHistoryQuery;
+use crate::utils::extractors::{AuthUser, RequestContext};
+use crate::utils::error::{field_errors, AppError};
+use crate::utils::response::{Response, ResponseBuilder};
+
+#[derive(Deserialize, Validate)]
+pub struct ImpersonateRequest {
+    /// Why the admin needs to act as the user, kept in the audit log.
+    #[validate(length(min = 1, max = 500, message = "A reason of at most 500 characters is required"))]
+    pub reason: String,
+}
+
+#[post("/users/{id}/unlock")]
+pub async fn handle_unlock_user(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    context: RequestContext,
+) -> Result<HttpResponse, AppError> {
+    match unlock_user(pool.get_ref(), &user, path.into_inner(), &context).await {
+        Ok(()) => Ok(Response::ok(json!({ "message": "Account unlocked" }))),
+        Err(AppError::ForbiddenError(e)) => Ok(Response::forbidden(&e)),
+        Err(AppError::ValidationError(e)) => {
+            warn!("Validation error: {}", e);
+            Ok(Response::bad_request(&e))
+        },
+        Err(AppError::NotFoundError(e)) => {
+            warn!("User not found: {}", e);
+            Ok(Response::not_found(&e))
+        },
+        Err(e) => {
+            error!("Unexpected error while unlocking user: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
+
+#[post("/users/{id}/impersonate")]
+pub async fn handle_impersonate_user(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    context: RequestContext,
+    req: web::Json<ImpersonateRequest>,
+) -> Result<HttpResponse, AppError> {
+    if let Err(errors) = req.validate() {
+        warn!("Validation failed: {:?}", errors);
+        return Ok(Response::bad_request_with_data("Validation failed", field_errors(&errors)));
+    }
+
+    match impersonate_user(pool.get_ref(), &user, path.into_inner(), &req.reason, &context).await {
+        Ok((token, expires_at)) => Ok(Response::ok(json!({ "token": token, "expires_at": expires_at }))),
+        Err(AppError::ForbiddenError(e)) => Ok(Response::forbidden(&e)),
+        Err(AppError::ValidationError(e)) => {
+            warn!("Validation error: {}", e);
+            Ok(Response::bad_request(&e))
+        },
+        Err(AppError::NotFoundError(e)) => {
+            warn!("User not found: {}", e);
+            Ok(Response::not_found(&e))
+        },
+        Err(e) => {
+            error!("Unexpected error while impersonating user: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
+
+#[get("/users/{id}/profile-history")]
+pub async fn handle_user_profile_history(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+    query: web::Query<ProfileHistoryQuery>,
+) -> Result<HttpResponse, AppError> {
+    user.require_full_access()?;
+    if let Err(errors) = query.validate() {
+        warn!("Validation failed: {:?}", errors);
+        return Ok(Response::bad_request_with_data("Validation failed", field_errors(&errors)));
+    }
+
+    match user_profile_history(pool.get_ref(), &user, path.into_inner(), &query).await {
+        Ok(page) => Ok(Response::ok(page)),
+        Err(AppError::ForbiddenError(e)) => Ok(Response::forbidden(&e)),
+        Err(AppError::ValidationError(e)) => {
+            warn!("Validation error: {}", e);
+            Ok(Response::bad_request(&e))
+        },
+        Err(e) => {
+            error!("Unexpected error while fetching profile history: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
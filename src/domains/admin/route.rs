@@ -0,0 +1,15 @@
+use actix_web::web;
+use super::controller;
+use crate::domains::audit::route as audit_routes;
+use crate::utils::middleware::auth::AuthMiddleware;
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/admin")
+            .wrap(AuthMiddleware::new())
+            .service(controller::handle_unlock_user)
+            .service(controller::handle_impersonate_user)
+            .service(controller::handle_user_profile_history)
+            .configure(audit_routes::configure_admin)
+    );
+}
@@ -0,0 +1,112 @@
+use chrono::{DateTime, Duration, Utc};
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+use log::{info, warn};
+use tracing::instrument;
+use crate::config;
+use crate::domains::audit::entity::{NewAuditEvent, ACTION_ACCOUNT_UNLOCK, ACTION_IMPERSONATION_START};
+use crate::domains::audit::service as audit;
+use crate::domains::auth::service::unlock_account;
+use crate::domains::user::entity::{ProfileHistoryPage, ProfileHistoryQuery};
+use crate::domains::user::repository::find_user_role;
+use crate::domains::user::service::profile_history;
+use crate::utils::auth::{self, Claims};
+use crate::utils::error::AppError;
+use crate::utils::extractors::{AuthUser, RequestContext};
+
+pub const ADMIN_ROLE: &str = "admin";
+
+/// Fails unless `user` is an admin. Tokens without the admin role are turned
+/// away without a query; for the rest the role is re-read from the database
+/// so revoking it takes effect before their token expires.
+pub async fn require_admin(pool: &PgPool, user: &AuthUser) -> Result<(), AppError> {
+    let is_admin = user.roles.iter().any(|role| role == ADMIN_ROLE)
+        && find_user_role(pool, &user.id).await
+            .map_err(|e| AppError::internal(format!("Database error: {}", e)))?
+            .as_deref() == Some(ADMIN_ROLE);
+    if !is_admin {
+        warn!("Non-admin user {} attempted an admin action", user.id);
+        return Err(AppError::forbidden("Admin access required"));
+    }
+
+    Ok(())
+}
+
+#[instrument(name = "admin.unlock_user", skip(pool, admin, context), fields(admin.id = %admin.id))]
+pub async fn unlock_user(pool: &PgPool, admin: &AuthUser, user_id: Uuid, context: &RequestContext) -> Result<(), AppError> {
+    require_admin(pool, admin).await?;
+    unlock_account(pool, &user_id).await?;
+    info!(
+        "Admin {} unlocked user {} (session {})",
+        admin.id, user_id, admin.session_id.map_or_else(|| "-".to_string(), |id| id.to_string())
+    );
+    let event = NewAuditEvent::new(ACTION_ACCOUNT_UNLOCK, context)
+        .actor(admin.id)
+        .subject(user_id);
+    audit::record_or_log(pool, event).await;
+    Ok(())
+}
+
+/// Issues a short-lived token to act as `user_id`, for reproducing what a
+/// user sees. The token names the admin in its `act` claim, is bound to the
+/// admin's session and cannot be used for sensitive account actions. The
+/// start is audited before the token is handed out.
+#[instrument(name = "admin.impersonate_user", skip(pool, admin, reason, context), fields(admin.id = %admin.id))]
+pub async fn impersonate_user(
+    pool: &PgPool,
+    admin: &AuthUser,
+    user_id: Uuid,
+    reason: &str,
+    context: &RequestContext,
+) -> Result<(String, DateTime<Utc>), AppError> {
+    admin.require_full_access()?;
+    require_admin(pool, admin).await?;
+    if user_id == admin.id {
+        return Err(AppError::validation("You cannot impersonate yourself"));
+    }
+    let reason = reason.trim();
+    if reason.is_empty() {
+        return Err(AppError::validation("A reason is required"));
+    }
+    // Tokens without a session could not be signed out early
+    let session_id = admin.session_id
+        .ok_or_else(|| AppError::forbidden("Sign in again to impersonate users"))?;
+
+    let role = find_user_role(pool, &user_id).await
+        .map_err(|e| AppError::internal(format!("Database error: {}", e)))?
+        .ok_or_else(|| AppError::not_found(format!("User not found for ID: {}", user_id)))?;
+    if role == ADMIN_ROLE {
+        warn!("Admin {} attempted to impersonate admin {}", admin.id, user_id);
+        return Err(AppError::forbidden("Admins cannot be impersonated"));
+    }
+
+    let expires_at = Utc::now() + Duration::minutes(config::get_impersonation_token_ttl_mins());
+    let mut claims = Claims::new(user_id)
+        .with_roles(vec![role])
+        .with_session(session_id)
+        .with_actor(admin.id);
+    claims.exp = expires_at.timestamp();
+    let token = auth::generate_token(&claims)?;
+
+    let event = NewAuditEvent::new(ACTION_IMPERSONATION_START, context)
+        .actor(admin.id)
+        .subject(user_id)
+        .details(json!({ "reason": reason, "expires_at": expires_at }));
+    audit::record(pool, event).await?;
+
+    info!("Admin {} started impersonating user {} until {}", admin.id, user_id, expires_at);
+    Ok((token, expires_at))
+}
+
+/// A user's profile changes, kept for deleted users too.
+#[instrument(name = "admin.user_profile_history", skip(pool, admin, query), fields(admin.id = %admin.id))]
+pub async fn user_profile_history(
+    pool: &PgPool,
+    admin: &AuthUser,
+    user_id: Uuid,
+    query: &ProfileHistoryQuery,
+) -> Result<ProfileHistoryPage, AppError> {
+    require_admin(pool, admin).await?;
+    profile_history(pool, &user_id, query).await
+}
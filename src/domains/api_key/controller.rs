@@ -0,0 +1,74 @@
+use actix_web::{delete, get, post, web, HttpResponse};
+use log::{error, warn};
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+use validator::Validate;
+use crate::domains::api_key::entity::CreateApiKeyRequest;
+use crate::domains::api_key::service::{create_api_key, list_api_keys, revoke_api_key};
+use crate::utils::error::{field_errors, AppError};
+use crate::utils::extractors::AuthUser;
+use crate::utils::response::{Response, ResponseBuilder};
+
+#[post("")]
+pub async fn handle_create_api_key(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+    request: web::Json<CreateApiKeyRequest>,
+) -> Result<HttpResponse, AppError> {
+    user.require_full_access()?;
+
+    if let Err(errors) = request.validate() {
+        warn!("Validation failed: {:?}", errors);
+        return Ok(Response::bad_request_with_data("Validation failed", field_errors(&errors)));
+    }
+
+    match create_api_key(pool.get_ref(), &user.id, &request).await {
+        Ok(api_key) => Ok(Response::created(api_key)),
+        Err(AppError::ValidationError(e)) => {
+            warn!("Validation error: {}", e);
+            Ok(Response::bad_request(&e))
+        },
+        Err(e) => {
+            error!("Unexpected error while creating API key: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
+
+#[get("")]
+pub async fn handle_list_api_keys(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    user.require_full_access()?;
+
+    match list_api_keys(pool.get_ref(), &user.id).await {
+        Ok(api_keys) => Ok(Response::ok(api_keys)),
+        Err(e) => {
+            error!("Unexpected error while listing API keys: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
+
+#[delete("/{id}")]
+pub async fn handle_revoke_api_key(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    user.require_full_access()?;
+
+    match revoke_api_key(pool.get_ref(), &user.id, &path.into_inner()).await {
+        Ok(()) => Ok(Response::ok(json!({ "message": "API key revoked" }))),
+        Err(AppError::NotFoundError(e)) => {
+            warn!("API key not found: {}", e);
+            Ok(Response::not_found(&e))
+        },
+        Err(e) => {
+            error!("Unexpected error while revoking API key: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
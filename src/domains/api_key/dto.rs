@@ -0,0 +1,35 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+use crate::domains::api_key::entity::ApiKey;
+
+#[derive(Serialize)]
+pub struct ApiKeyResponse {
+    pub id: Uuid,
+    pub name: String,
+    pub prefix: String,
+    pub scopes: Vec<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Returned once, on creation: the only time the key itself is shown.
+#[derive(Serialize)]
+pub struct CreatedApiKeyResponse {
+    #[serde(flatten)]
+    pub api_key: ApiKeyResponse,
+    pub key: String,
+}
+
+pub fn create_api_key_response(api_key: ApiKey) -> ApiKeyResponse {
+    ApiKeyResponse {
+        id: api_key.id,
+        name: api_key.name,
+        prefix: api_key.prefix,
+        scopes: api_key.scopes,
+        expires_at: api_key.expires_at,
+        last_used_at: api_key.last_used_at,
+        created_at: api_key.created_at,
+    }
+}
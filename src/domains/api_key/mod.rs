@@ -0,0 +1,6 @@
+pub mod controller;
+pub mod dto;
+pub mod entity;
+pub mod repository;
+pub mod route;
+pub mod service;
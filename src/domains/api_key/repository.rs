@@ -0,0 +1,107 @@
+use sqlx::PgPool;
+use uuid::Uuid;
+use tracing::instrument;
+use crate::domains::api_key::entity::ApiKey;
+
+#[instrument(name = "db.create_api_key", skip_all, fields(db.system = "postgresql", db.operation = "INSERT"))]
+pub async fn create_api_key(pool: &PgPool, api_key: &ApiKey, key_hash: &str) -> Result<ApiKey, String> {
+    sqlx::query_as!(
+        ApiKey,
+        r#"
+        INSERT INTO api_keys (id, user_id, name, prefix, key_hash, scopes, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING id, user_id, name, prefix, scopes, expires_at, last_used_at, created_at
+        "#,
+        api_key.id,
+        api_key.user_id,
+        api_key.name,
+        api_key.prefix,
+        key_hash,
+        &api_key.scopes,
+        api_key.expires_at
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+/// Keys of a user that have not been revoked, newest first.
+#[instrument(name = "db.list_api_keys", skip_all, fields(db.system = "postgresql", db.operation = "SELECT"))]
+pub async fn list_api_keys(pool: &PgPool, user_id: &Uuid) -> Result<Vec<ApiKey>, String> {
+    sqlx::query_as!(
+        ApiKey,
+        r#"
+        SELECT id, user_id, name, prefix, scopes, expires_at, last_used_at, created_at
+        FROM api_keys
+        WHERE user_id = $1 AND revoked_at IS NULL
+        ORDER BY created_at DESC
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+#[instrument(name = "db.count_api_keys", skip_all, fields(db.system = "postgresql", db.operation = "SELECT"))]
+pub async fn count_api_keys(pool: &PgPool, user_id: &Uuid) -> Result<i64, String> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM api_keys
+        WHERE user_id = $1 AND revoked_at IS NULL
+        "#,
+        user_id
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+/// Revokes one of the user's keys; returns whether there was one to revoke.
+#[instrument(name = "db.revoke_api_key", skip_all, fields(db.system = "postgresql", db.operation = "UPDATE"))]
+pub async fn revoke_api_key(pool: &PgPool, user_id: &Uuid, id: &Uuid) -> Result<bool, String> {
+    sqlx::query!(
+        r#"
+        UPDATE api_keys
+        SET revoked_at = NOW()
+        WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL
+        "#,
+        id,
+        user_id
+    )
+    .execute(pool)
+    .await
+    .map(|result| result.rows_affected() > 0)
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+/// Finds the unrevoked, unexpired key with this hash whose owner still exists.
+#[instrument(name = "db.find_active_api_key", skip_all, fields(db.system = "postgresql", db.operation = "SELECT"))]
+pub async fn find_active_api_key(pool: &PgPool, key_hash: &str) -> Result<Option<ApiKey>, String> {
+    sqlx::query_as!(
+        ApiKey,
+        r#"
+        SELECT k.id, k.user_id, k.name, k.prefix, k.scopes, k.expires_at, k.last_used_at, k.created_at
+        FROM api_keys k
+        JOIN users u ON u.id = k.user_id
+        WHERE k.key_hash = $1
+            AND k.revoked_at IS NULL
+            AND (k.expires_at IS NULL OR k.expires_at > NOW())
+            AND u.deleted_at IS NULL
+        "#,
+        key_hash
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+#[instrument(name = "db.touch_api_key", skip_all, fields(db.system = "postgresql", db.operation = "UPDATE"))]
+pub async fn touch_api_key(pool: &PgPool, id: &Uuid) -> Result<(), String> {
+    sqlx::query!("UPDATE api_keys SET last_used_at = NOW() WHERE id = $1", id)
+        .execute(pool)
+        .await
+        .map(|_| ())
+        .map_err(|e| format!("Database error: {}", e))
+}
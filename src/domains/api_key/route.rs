@@ -0,0 +1,12 @@
+use actix_web::web;
+use super::controller;
+
+/// Mounted inside the `/users` scope, which provides authentication.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/api-keys")
+            .service(controller::handle_create_api_key)
+            .service(controller::handle_list_api_keys)
+            .service(controller::handle_revoke_api_key)
+    );
+}
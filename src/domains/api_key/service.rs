@@ -0,0 +1,120 @@
+use chrono::{Duration, Utc};
+use log::{info, warn};
+use sqlx::PgPool;
+use tracing::instrument;
+use uuid::Uuid;
+use crate::config;
+use crate::domains::api_key::dto::{create_api_key_response, ApiKeyResponse, CreatedApiKeyResponse};
+use crate::domains::api_key::entity::{ApiKey, CreateApiKeyRequest};
+use crate::domains::api_key::repository::{
+    count_api_keys, create_api_key as insert_api_key, find_active_api_key, list_api_keys as find_api_keys,
+    revoke_api_key as mark_api_key_revoked, touch_api_key,
+};
+use crate::utils::auth::{self, Claims, GRANTABLE_SCOPES};
+use crate::utils::error::AppError;
+
+/// Marks a string as one of our API keys, so leaked keys are easy to spot.
+const KEY_PREFIX: &str = "rk_";
+/// Characters of the secret kept in the stored prefix.
+const PREFIX_CHARS: usize = 8;
+
+#[instrument(name = "api_key.create_api_key", skip(pool, request))]
+pub async fn create_api_key(
+    pool: &PgPool,
+    user_id: &Uuid,
+    request: &CreateApiKeyRequest
+) -> Result<CreatedApiKeyResponse, AppError> {
+    if let Some(scope) = request.scopes.iter().find(|scope| !GRANTABLE_SCOPES.contains(&scope.as_str())) {
+        return Err(AppError::validation(format!(
+            "Unknown scope '{}'. Valid scopes: {}",
+            scope,
+            GRANTABLE_SCOPES.join(", ")
+        )));
+    }
+    if request.expires_at.is_some_and(|expires_at| expires_at <= Utc::now()) {
+        return Err(AppError::validation("Expiry must be in the future"));
+    }
+
+    let max_keys = config::get_api_key_max_per_user();
+    let existing = count_api_keys(pool, user_id).await
+        .map_err(|e| AppError::DatabaseError(sqlx::Error::Protocol(e)))?;
+    if existing >= max_keys {
+        return Err(AppError::validation(format!(
+            "You can have at most {} API keys; revoke one first",
+            max_keys
+        )));
+    }
+
+    let (secret, _) = auth::generate_secret_token();
+    let key = format!("{}{}", KEY_PREFIX, secret);
+    let mut scopes = request.scopes.clone();
+    scopes.sort();
+    scopes.dedup();
+
+    let api_key = ApiKey {
+        id: Uuid::new_v4(),
+        user_id: *user_id,
+        name: request.name.trim().to_string(),
+        prefix: key[..KEY_PREFIX.len() + PREFIX_CHARS].to_string(),
+        scopes,
+        expires_at: request.expires_at,
+        last_used_at: None,
+        created_at: Utc::now(),
+    };
+
+    let api_key = insert_api_key(pool, &api_key, &auth::hash_secret_token(&key)).await
+        .map_err(|e| AppError::DatabaseError(sqlx::Error::Protocol(e)))?;
+    info!("Created API key {} for user {}", api_key.prefix, user_id);
+
+    Ok(CreatedApiKeyResponse { api_key: create_api_key_response(api_key), key })
+}
+
+#[instrument(name = "api_key.list_api_keys", skip(pool))]
+pub async fn list_api_keys(pool: &PgPool, user_id: &Uuid) -> Result<Vec<ApiKeyResponse>, AppError> {
+    let api_keys = find_api_keys(pool, user_id).await
+        .map_err(|e| AppError::DatabaseError(sqlx::Error::Protocol(e)))?;
+
+    Ok(api_keys.into_iter().map(create_api_key_response).collect())
+}
+
+#[instrument(name = "api_key.revoke_api_key", skip(pool))]
+pub async fn revoke_api_key(pool: &PgPool, user_id: &Uuid, id: &Uuid) -> Result<(), AppError> {
+    let revoked = mark_api_key_revoked(pool, user_id, id).await
+        .map_err(|e| AppError::DatabaseError(sqlx::Error::Protocol(e)))?;
+    if !revoked {
+        return Err(AppError::not_found(format!("API key not found for ID: {}", id)));
+    }
+
+    info!("Revoked API key {} for user {}", id, user_id);
+    Ok(())
+}
+
+/// Resolves an API key to claims for its owner, limited to the key's
+/// scopes. The key's id is used as the `jti`.
+#[instrument(name = "api_key.authenticate", skip_all, fields(api_key.id = tracing::field::Empty))]
+pub async fn authenticate(pool: &PgPool, key: &str) -> Result<Claims, AppError> {
+    if !key.starts_with(KEY_PREFIX) {
+        return Err(AppError::authentication("Invalid API key"));
+    }
+
+    let api_key = find_active_api_key(pool, &auth::hash_secret_token(key)).await
+        .map_err(|e| AppError::internal(format!("Database error: {}", e)))?
+        .ok_or_else(|| AppError::authentication("Invalid API key"))?;
+    tracing::Span::current().record("api_key.id", tracing::field::display(api_key.id));
+
+    // Recording every request would turn reads into writes; a minute is
+    // precise enough to tell which keys are in use.
+    let stale = api_key.last_used_at.is_none_or(|last_used| last_used < Utc::now() - Duration::minutes(1));
+    if stale {
+        if let Err(e) = touch_api_key(pool, &api_key.id).await {
+            warn!("Could not record use of API key {}: {}", api_key.prefix, e);
+        }
+    }
+
+    let mut claims = Claims::new(api_key.user_id).with_scopes(api_key.scopes);
+    claims.jti = api_key.id.to_string();
+    if let Some(expires_at) = api_key.expires_at {
+        claims.exp = expires_at.timestamp();
+    }
+    Ok(claims)
+}
@@ -0,0 +1,58 @@
+use actix_web::{get, web, HttpResponse};
+use log::{error, warn};
+use sqlx::PgPool;
+use validator::Validate;
+use crate::domains::audit::entity::{AuditEventQuery, SecurityActivityQuery};
+use crate::domains::audit::service::{list_events, security_activity};
+use crate::utils::error::{field_errors, AppError};
+use crate::utils::extractors::AuthUser;
+use crate::utils::response::{Response, ResponseBuilder};
+
+/// The audit log, for admins.
+#[get("/audit-events")]
+pub async fn handle_list_audit_events(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+    query: web::Query<AuditEventQuery>,
+) -> Result<HttpResponse, AppError> {
+    user.require_full_access()?;
+    if let Err(errors) = query.validate() {
+        warn!("Validation failed: {:?}", errors);
+        return Ok(Response::bad_request_with_data("Validation failed", field_errors(&errors)));
+    }
+
+    match list_events(pool.get_ref(), &user, &query).await {
+        Ok(page) => Ok(Response::ok(page)),
+        Err(AppError::ForbiddenError(e)) => Ok(Response::forbidden(&e)),
+        Err(AppError::ValidationError(e)) => {
+            warn!("Validation error: {}", e);
+            Ok(Response::bad_request(&e))
+        },
+        Err(e) => {
+            error!("Unexpected error while listing audit events: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
+
+/// The caller's recent sign-ins and account changes.
+#[get("/security-activity")]
+pub async fn handle_security_activity(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+    query: web::Query<SecurityActivityQuery>,
+) -> Result<HttpResponse, AppError> {
+    user.require_full_access()?;
+    if let Err(errors) = query.validate() {
+        warn!("Validation failed: {:?}", errors);
+        return Ok(Response::bad_request_with_data("Validation failed", field_errors(&errors)));
+    }
+
+    match security_activity(pool.get_ref(), &user.id, query.limit).await {
+        Ok(events) => Ok(Response::ok(events)),
+        Err(e) => {
+            error!("Unexpected error while fetching security activity: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use uuid::Uuid;
+use validator::Validate;
+use crate::utils::extractors::RequestContext;
+
+pub const ACTION_REGISTER: &str = "account.register";
+pub const ACTION_LOGIN_SUCCESS: &str = "login.success";
+pub const ACTION_LOGIN_FAILURE: &str = "login.failure";
+/// Details name the fields that changed, not their values.
+pub const ACTION_PROFILE_UPDATE: &str = "profile.update";
+pub const ACTION_PASSWORD_CHANGE: &str = "password.change";
+pub const ACTION_ACCOUNT_DELETE: &str = "account.delete";
+pub const ACTION_IMPERSONATION_START: &str = "impersonation.start";
+/// A request made with an impersonation token.
+pub const ACTION_IMPERSONATION_REQUEST: &str = "impersonation.request";
+/// An admin lifting a login lockout.
+pub const ACTION_ACCOUNT_UNLOCK: &str = "account.unlock";
+/// Details hold the organization and the old and new role.
+pub const ACTION_ROLE_CHANGE: &str = "org.role_change";
+/// Details hold the organization and the role the member had.
+pub const ACTION_MEMBER_REMOVE: &str = "org.member_remove";
+
+/// What users see of their own account's history.
+pub const SECURITY_ACTIVITY_ACTIONS: &[&str] = &[
+    ACTION_REGISTER,
+    ACTION_LOGIN_SUCCESS,
+    ACTION_LOGIN_FAILURE,
+    ACTION_PROFILE_UPDATE,
+    ACTION_PASSWORD_CHANGE,
+    ACTION_ACCOUNT_DELETE,
+];
+
+/// An event to append to the audit log.
+pub struct NewAuditEvent {
+    pub actor_id: Option<Uuid>,
+    pub subject_id: Option<Uuid>,
+    pub action: &'static str,
+    pub details: Value,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub request_id: Option<Uuid>,
+}
+
+impl NewAuditEvent {
+    pub fn new(action: &'static str, context: &RequestContext) -> Self {
+        Self {
+            actor_id: None,
+            subject_id: None,
+            action,
+            details: Value::Object(Default::default()),
+            ip_address: context.ip.map(|ip| ip.to_string()),
+            user_agent: context.user_agent.clone(),
+            request_id: context.request_id,
+        }
+    }
+
+    pub fn actor(mut self, actor_id: Uuid) -> Self {
+        self.actor_id = Some(actor_id);
+        self
+    }
+
+    pub fn subject(mut self, subject_id: Uuid) -> Self {
+        self.subject_id = Some(subject_id);
+        self
+    }
+
+    pub fn details(mut self, details: Value) -> Self {
+        self.details = details;
+        self
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditEvent {
+    pub id: Uuid,
+    pub actor_id: Option<Uuid>,
+    pub subject_id: Option<Uuid>,
+    pub action: String,
+    pub details: Value,
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub request_id: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Filters for the admin audit log query; all are optional.
+#[derive(Deserialize, Validate)]
+pub struct AuditEventQuery {
+    pub actor_id: Option<Uuid>,
+    pub subject_id: Option<Uuid>,
+    #[validate(length(min = 1, max = 64, message = "Action must be 1 to 64 characters"))]
+    pub action: Option<String>,
+    /// Events at or after this time.
+    pub since: Option<DateTime<Utc>>,
+    /// Events before this time.
+    pub until: Option<DateTime<Utc>>,
+    #[validate(range(min = 1, message = "Page must be at least 1"))]
+    pub page: Option<i64>,
+    #[validate(range(min = 1, max = 100, message = "Per page must be 1 to 100"))]
+    pub per_page: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct AuditEventPage {
+    pub events: Vec<AuditEvent>,
+    pub page: i64,
+    pub per_page: i64,
+    pub total: i64,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct SecurityActivityQuery {
+    #[validate(range(min = 1, max = 100, message = "Limit must be 1 to 100"))]
+    pub limit: Option<i64>,
+}
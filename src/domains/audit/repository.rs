@@ -0,0 +1,108 @@
+use sqlx::PgPool;
+use tracing::instrument;
+use uuid::Uuid;
+use crate::domains::audit::entity::{AuditEvent, AuditEventQuery, NewAuditEvent};
+
+#[instrument(name = "db.insert_audit_event", skip_all, fields(db.system = "postgresql", db.operation = "INSERT"))]
+pub async fn insert_audit_event(pool: &PgPool, event: &NewAuditEvent) -> Result<(), String> {
+    sqlx::query!(
+        r#"
+        INSERT INTO audit_events (actor_id, subject_id, action, details, ip_address, user_agent, request_id)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+        event.actor_id,
+        event.subject_id,
+        event.action,
+        event.details,
+        event.ip_address,
+        event.user_agent,
+        event.request_id
+    )
+    .execute(pool)
+    .await
+    .map(|_| ())
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+/// Events matching every filter that is set, newest first, along with how
+/// many match in total.
+#[instrument(name = "db.find_audit_events", skip_all, fields(db.system = "postgresql", db.operation = "SELECT"))]
+pub async fn find_audit_events(
+    pool: &PgPool,
+    filter: &AuditEventQuery,
+    limit: i64,
+    offset: i64,
+) -> Result<(Vec<AuditEvent>, i64), String> {
+    let events = sqlx::query_as!(
+        AuditEvent,
+        r#"
+        SELECT id, actor_id, subject_id, action, details, ip_address, user_agent, request_id, created_at
+        FROM audit_events
+        WHERE ($1::uuid IS NULL OR actor_id = $1)
+          AND ($2::uuid IS NULL OR subject_id = $2)
+          AND ($3::text IS NULL OR action = $3)
+          AND ($4::timestamptz IS NULL OR created_at >= $4)
+          AND ($5::timestamptz IS NULL OR created_at < $5)
+        ORDER BY created_at DESC, id
+        LIMIT $6 OFFSET $7
+        "#,
+        filter.actor_id,
+        filter.subject_id,
+        filter.action,
+        filter.since,
+        filter.until,
+        limit,
+        offset
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("Database error: {}", e))?;
+
+    let total = sqlx::query_scalar!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM audit_events
+        WHERE ($1::uuid IS NULL OR actor_id = $1)
+          AND ($2::uuid IS NULL OR subject_id = $2)
+          AND ($3::text IS NULL OR action = $3)
+          AND ($4::timestamptz IS NULL OR created_at >= $4)
+          AND ($5::timestamptz IS NULL OR created_at < $5)
+        "#,
+        filter.actor_id,
+        filter.subject_id,
+        filter.action,
+        filter.since,
+        filter.until
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| format!("Database error: {}", e))?;
+
+    Ok((events, total))
+}
+
+/// The user's most recent events among `actions`, newest first.
+#[instrument(name = "db.find_user_audit_events", skip_all, fields(db.system = "postgresql", db.operation = "SELECT"))]
+pub async fn find_user_audit_events(
+    pool: &PgPool,
+    subject_id: &Uuid,
+    actions: &[String],
+    limit: i64,
+) -> Result<Vec<AuditEvent>, String> {
+    sqlx::query_as!(
+        AuditEvent,
+        r#"
+        SELECT id, actor_id, subject_id, action, details, ip_address, user_agent, request_id, created_at
+        FROM audit_events
+        WHERE subject_id = $1 AND action = ANY($2)
+        ORDER BY created_at DESC, id
+        LIMIT $3
+        "#,
+        subject_id,
+        actions,
+        limit
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("Database error: {}", e))
+}
@@ -0,0 +1,12 @@
+use actix_web::web;
+use super::controller;
+
+/// Mounted inside the `/admin` scope, which provides authentication.
+pub fn configure_admin(cfg: &mut web::ServiceConfig) {
+    cfg.service(controller::handle_list_audit_events);
+}
+
+/// Mounted inside the `/users` scope, which provides authentication.
+pub fn configure_activity(cfg: &mut web::ServiceConfig) {
+    cfg.service(controller::handle_security_activity);
+}
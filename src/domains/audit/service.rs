@@ -0,0 +1,53 @@
+use log::error;
+use sqlx::PgPool;
+use tracing::instrument;
+use uuid::Uuid;
+use crate::domains::admin::service::require_admin;
+use crate::domains::audit::entity::{AuditEvent, AuditEventPage, AuditEventQuery, NewAuditEvent, SECURITY_ACTIVITY_ACTIONS};
+use crate::domains::audit::repository::{find_audit_events, find_user_audit_events, insert_audit_event};
+use crate::utils::error::AppError;
+use crate::utils::extractors::AuthUser;
+
+const DEFAULT_PER_PAGE: i64 = 50;
+const DEFAULT_ACTIVITY_LIMIT: i64 = 20;
+
+fn database_error(e: String) -> AppError {
+    AppError::internal(format!("Database error: {}", e))
+}
+
+/// Appends an event to the audit log. Fails if it cannot be stored, for
+/// actions that must not happen unrecorded.
+pub async fn record(pool: &PgPool, event: NewAuditEvent) -> Result<(), AppError> {
+    insert_audit_event(pool, &event).await
+        .map_err(|e| AppError::internal(format!("Could not write audit event {}: {}", event.action, e)))
+}
+
+/// Appends an event to the audit log, only logging a failure to store it.
+/// For actions that have already taken effect.
+pub async fn record_or_log(pool: &PgPool, event: NewAuditEvent) {
+    if let Err(e) = record(pool, event).await {
+        error!("{}", e);
+    }
+}
+
+/// One page of the audit log for admins, filtered by `query`.
+#[instrument(name = "audit.list_events", skip(pool, admin, query), fields(admin.id = %admin.id))]
+pub async fn list_events(pool: &PgPool, admin: &AuthUser, query: &AuditEventQuery) -> Result<AuditEventPage, AppError> {
+    require_admin(pool, admin).await?;
+
+    let page = query.page.unwrap_or(1);
+    let per_page = query.per_page.unwrap_or(DEFAULT_PER_PAGE);
+    let offset = (page - 1).checked_mul(per_page)
+        .ok_or_else(|| AppError::validation("Page is out of range"))?;
+    let (events, total) = find_audit_events(pool, query, per_page, offset).await.map_err(database_error)?;
+
+    Ok(AuditEventPage { events, page, per_page, total })
+}
+
+/// The user's recent sign-ins and account changes.
+#[instrument(name = "audit.security_activity", skip(pool))]
+pub async fn security_activity(pool: &PgPool, user_id: &Uuid, limit: Option<i64>) -> Result<Vec<AuditEvent>, AppError> {
+    let actions: Vec<String> = SECURITY_ACTIVITY_ACTIONS.iter().map(|action| action.to_string()).collect();
+    find_user_audit_events(pool, user_id, &actions, limit.unwrap_or(DEFAULT_ACTIVITY_LIMIT)).await
+        .map_err(database_error)
+}
@@ -1,15 +1,18 @@
-use actix_web::http::StatusCode;
-use actix_web::{post, web, HttpResponse};  // Add StatusCode import
+use actix_web::{get, post, web, HttpResponse};
 use serde::Deserialize;
 use serde_json::json;
 use sqlx::PgPool;
-use crate::domains::auth::service::{register_user, login_user};
-use crate::utils::error::AppError;
+use crate::config;
+use crate::domains::auth::entity::LoginOutcome;
+use crate::domains::auth::service::{
+    register_user, register_user_uniform, register_with_invitation, login_user, login_with_magic_link,
+    request_magic_link, request_unlock, unlock_with_token,
+};
+use crate::utils::error::{field_errors, AppError};
+use crate::utils::extractors::RequestContext;
 use crate::utils::response::{Response, ResponseBuilder};
 use log::{error, warn};
 use validator::{Validate, ValidationErrors};
-use lazy_static::lazy_static;
-use regex::Regex;
 
 #[derive(Deserialize, Validate)]
 pub struct AuthRequest {
@@ -17,19 +20,63 @@ pub struct AuthRequest {
     #[validate(length(min = 1, message = "Email is required"))]
     pub email: String,
 
-    #[validate(length(min = 8, message = "Password must be at least 8 characters"))]
-    #[validate(regex(path = "PASSWORD_REGEX", message = "Password must contain at least one number and one letter"))]
+    // Only presence is checked; the password policy applies when a password is set
+    #[validate(length(min = 1, message = "Password is required"))]
     pub password: String,
 }
 
+#[derive(Deserialize, Validate)]
+pub struct UnlockRequest {
+    #[validate(email(message = "Invalid email format"))]
+    pub email: String,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct MagicLinkRequest {
+    #[validate(email(message = "Invalid email format"))]
+    pub email: String,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct ConsumeMagicLinkRequest {
+    #[validate(length(min = 1, message = "Token is required"))]
+    pub token: String,
+
+    /// Returned to the device when it asked for the link.
+    #[validate(length(min = 1, message = "Nonce is required"))]
+    pub nonce: String,
+}
+
+#[derive(Deserialize)]
+pub struct UnlockQuery {
+    pub token: String,
+}
+
 #[derive(Deserialize, Validate)]
 pub struct RegisterRequest {
     #[validate(email(message = "Invalid email format"))]
     #[validate(length(min = 1, message = "Email is required"))]
     pub email: String,
 
-    #[validate(length(min = 8, message = "Password must be at least 8 characters"))]
-    #[validate(regex(path = "PASSWORD_REGEX", message = "Password must contain at least one number and one letter"))]
+    /// Checked against the password policy by the service.
+    #[validate(length(min = 1, message = "Password is required"))]
+    pub password: String,
+
+    #[validate(length(min = 1, message = "Name is required"))]
+    pub name: Option<String>,
+
+    pub phone: Option<String>,
+    pub address: Option<String>,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct InvitationRegisterRequest {
+    /// From the invitation email, which also determines the account's email.
+    #[validate(length(min = 1, message = "Token is required"))]
+    pub token: String,
+
+    /// Checked against the password policy by the service.
+    #[validate(length(min = 1, message = "Password is required"))]
     pub password: String,
 
     #[validate(length(min = 1, message = "Name is required"))]
@@ -39,35 +86,54 @@ pub struct RegisterRequest {
     pub address: Option<String>,
 }
 
-lazy_static! {
-    static ref PASSWORD_REGEX: Regex = Regex::new(r"^.*[A-Za-z].*\d.*$|^.*\d.*[A-Za-z].*$").unwrap();
+/// A token, or the passkey challenge the client must answer at
+/// `POST /api/auth/webauthn/authenticate` to get one.
+pub fn login_response(outcome: LoginOutcome) -> HttpResponse {
+    match outcome {
+        LoginOutcome::Token(token) => Response::ok(json!({ "token": token })),
+        LoginOutcome::SecondFactorRequired(webauthn) => Response::ok(json!({
+            "second_factor_required": true,
+            "webauthn": webauthn
+        })),
+    }
 }
 
 fn handle_validation_errors(errors: ValidationErrors) -> HttpResponse {
     warn!("Validation failed: {:?}", errors);
-    let validation_errors = errors
-        .field_errors()
-        .into_iter()
-        .map(|(field, errors)| {
-            let messages: Vec<String> = errors
-                .iter()
-                .map(|error| error.message.as_ref().unwrap_or(&error.code).to_string())
-                .collect();
-            (field.to_string(), json!(messages))
-        })
-        .collect::<serde_json::Map<String, serde_json::Value>>();
-
-    Response::bad_request_with_data("Validation failed", validation_errors)
+    Response::bad_request_with_data("Validation failed", field_errors(&errors))
 }
 
 #[post("/register")]
 pub async fn handle_register(
     pool: web::Data<PgPool>,
+    context: RequestContext,
     req: web::Json<RegisterRequest>,
 ) -> Result<HttpResponse, AppError> {
     if let Err(errors) = req.validate() {
         return Ok(handle_validation_errors(errors));
     }
+
+    if config::get_registration_mode() == "uniform" {
+        return match register_user_uniform(
+            pool.get_ref(),
+            req.email.clone(),
+            req.name.as_deref(),
+            req.address.as_deref(),
+            req.phone.as_deref(),
+            &req.password,
+            &context
+        ).await {
+            Ok(()) => Ok(Response::accepted(json!({
+                "message": "Registration received. Check your email to continue."
+            }))),
+            Err(e @ AppError::FieldValidationError(_)) => Err(e),
+            Err(e @ AppError::ServiceUnavailableError(_)) => Err(e),
+            Err(e) => {
+                error!("Unexpected error during registration: {}", e);
+                Ok(Response::internal_error("An unexpected error occurred"))
+            }
+        };
+    }
     
     match register_user(
         pool.get_ref(), 
@@ -75,13 +141,19 @@ pub async fn handle_register(
         req.name.as_deref(), 
         req.address.as_deref(), 
         req.phone.as_deref(), 
-        &req.password
+        &req.password,
+        &context
     ).await {
         Ok(user) => Ok(Response::created(user)),
         Err(AppError::ValidationError(e)) => {
             warn!("Registration validation error: {}", e);
             Ok(Response::bad_request(&e))
         },
+        Err(AppError::FieldValidationError(errors)) => Ok(handle_validation_errors(errors)),
+        Err(e @ AppError::ServiceUnavailableError(_)) => {
+            warn!("Registration shed under load: {}", e);
+            Err(e)
+        },
         Err(AppError::DatabaseError(e)) => {
             error!("Database error during registration: {}", e);
             Ok(Response::internal_error("Failed to create user account"))
@@ -93,31 +165,68 @@ pub async fn handle_register(
     }
 }
 
+/// Registration for someone invited to an organization. Works in either
+/// registration mode, since the invitation already told them whether the
+/// email had an account.
+#[post("/register/invitation")]
+pub async fn handle_register_with_invitation(
+    pool: web::Data<PgPool>,
+    context: RequestContext,
+    req: web::Json<InvitationRegisterRequest>,
+) -> Result<HttpResponse, AppError> {
+    if let Err(errors) = req.validate() {
+        return Ok(handle_validation_errors(errors));
+    }
+
+    match register_with_invitation(
+        pool.get_ref(),
+        &req.token,
+        req.name.as_deref(),
+        req.address.as_deref(),
+        req.phone.as_deref(),
+        &req.password,
+        &context
+    ).await {
+        Ok(user) => Ok(Response::created(user)),
+        Err(AppError::ValidationError(e)) => {
+            warn!("Registration validation error: {}", e);
+            Ok(Response::bad_request(&e))
+        },
+        Err(AppError::FieldValidationError(errors)) => Ok(handle_validation_errors(errors)),
+        Err(e @ AppError::ServiceUnavailableError(_)) => {
+            warn!("Registration shed under load: {}", e);
+            Err(e)
+        },
+        Err(e) => {
+            error!("Unexpected error during registration: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
+
 #[post("/login")]
 pub async fn handle_login(
     pool: web::Data<PgPool>,
+    context: RequestContext,
     req: web::Json<AuthRequest>,
 ) -> Result<HttpResponse, AppError> {
     if let Err(errors) = req.validate() {
         return Ok(handle_validation_errors(errors));
     }
 
-    match login_user(pool.get_ref(), &req.email, &req.password).await {
-        Ok(token) => Ok(Response::ok(json!({ "token": token }))),
+    match login_user(pool.get_ref(), &req.email, &req.password, &context).await {
+        Ok(outcome) => Ok(login_response(outcome)),
         Err(AppError::AuthenticationError(e)) => {
             warn!("Authentication failed: {}", e);
             Ok(Response::unauthorized(&e))
         },
-        Err(AppError::RateLimitExceeded(e)) => {
+        Err(e @ AppError::RateLimitExceeded { .. }) => {
             warn!("Rate limit exceeded for user: {}", req.email);
-            Ok(HttpResponse::TooManyRequests().json(json!({
-                "status": "error",
-                "code": StatusCode::TOO_MANY_REQUESTS.as_u16(),
-                "message": e,
-                "data": {
-                    "retry_after": 300
-                }
-            })))
+            Err(e)
+        },
+        Err(e @ AppError::ServiceUnavailableError(_)) => {
+            warn!("Login shed under load: {}", e);
+            Err(e)
         },
         Err(AppError::ValidationError(e)) => {
             warn!("Login validation error: {}", e);
@@ -132,4 +241,97 @@ pub async fn handle_login(
             Ok(Response::internal_error("An unexpected error occurred"))
         }
     }
-}
\ No newline at end of file
+}
+
+#[post("/unlock")]
+pub async fn handle_request_unlock(
+    pool: web::Data<PgPool>,
+    req: web::Json<UnlockRequest>,
+) -> Result<HttpResponse, AppError> {
+    if let Err(errors) = req.validate() {
+        return Ok(handle_validation_errors(errors));
+    }
+
+    match request_unlock(pool.get_ref(), &req.email).await {
+        Ok(()) => Ok(Response::ok(json!({
+            "message": "If the account is locked, an unlock link has been sent to its email"
+        }))),
+        Err(e @ AppError::RateLimitExceeded { .. }) => {
+            warn!("Unlock rate limit exceeded for: {}", req.email);
+            Err(e)
+        },
+        Err(e) => {
+            error!("Unexpected error while requesting unlock: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
+
+/// Target of the link in the unlock email.
+#[get("/unlock")]
+pub async fn handle_unlock(
+    pool: web::Data<PgPool>,
+    query: web::Query<UnlockQuery>,
+) -> Result<HttpResponse, AppError> {
+    match unlock_with_token(pool.get_ref(), &query.token).await {
+        Ok(()) => Ok(Response::ok(json!({ "message": "Your account has been unlocked" }))),
+        Err(AppError::ValidationError(e)) | Err(AppError::NotFoundError(e)) => {
+            warn!("Unlock failed: {}", e);
+            Ok(Response::bad_request("Invalid or expired unlock link"))
+        },
+        Err(e) => {
+            error!("Unexpected error while unlocking account: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
+
+/// Emails a sign-in link. The returned nonce must be kept by the caller and
+/// sent back with the link's token.
+#[post("/magic-link")]
+pub async fn handle_request_magic_link(
+    pool: web::Data<PgPool>,
+    req: web::Json<MagicLinkRequest>,
+) -> Result<HttpResponse, AppError> {
+    if let Err(errors) = req.validate() {
+        return Ok(handle_validation_errors(errors));
+    }
+
+    match request_magic_link(pool.get_ref(), &req.email).await {
+        Ok(nonce) => Ok(Response::accepted(json!({
+            "message": "If the email belongs to an account, a sign-in link has been sent to it",
+            "nonce": nonce
+        }))),
+        Err(e @ AppError::RateLimitExceeded { .. }) => {
+            warn!("Magic link rate limit exceeded for: {}", req.email);
+            Err(e)
+        },
+        Err(e) => {
+            error!("Unexpected error while sending magic link: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
+
+#[post("/magic-link/consume")]
+pub async fn handle_consume_magic_link(
+    pool: web::Data<PgPool>,
+    context: RequestContext,
+    req: web::Json<ConsumeMagicLinkRequest>,
+) -> Result<HttpResponse, AppError> {
+    if let Err(errors) = req.validate() {
+        return Ok(handle_validation_errors(errors));
+    }
+
+    match login_with_magic_link(pool.get_ref(), &req.token, &req.nonce, &context).await {
+        Ok(outcome) => Ok(login_response(outcome)),
+        Err(AppError::AuthenticationError(e)) => {
+            warn!("Magic link login failed: {}", e);
+            Ok(Response::unauthorized(&e))
+        },
+        Err(e) => {
+            error!("Unexpected error during magic link login: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
@@ -0,0 +1,33 @@
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+/// Failed login state for one (normalised) email.
+pub struct LoginLockout {
+    pub email: String,
+    pub failed_attempts: i32,
+    pub lockout_count: i32,
+    pub locked_until: Option<DateTime<Utc>>,
+}
+
+impl LoginLockout {
+    /// Seconds until the lockout ends, if it is still in force.
+    pub fn remaining_secs(&self, now: DateTime<Utc>) -> Option<u64> {
+        self.locked_until
+            .filter(|until| *until > now)
+            .map(|until| ((until - now).num_milliseconds() as u64).div_ceil(1000))
+    }
+}
+
+/// A magic link redeemed by its token.
+pub struct ConsumedMagicLink {
+    pub user_id: Uuid,
+    pub nonce_hash: String,
+}
+
+/// What a password or magic link sign-in yields.
+pub enum LoginOutcome {
+    Token(String),
+    /// The user has passkeys, one of which must complete the sign-in;
+    /// holds the WebAuthn request options for it.
+    SecondFactorRequired(serde_json::Value),
+}
@@ -1,3 +1,5 @@
 pub mod controller;
+pub mod entity;
+pub mod repository;
 pub mod service;
-pub mod route;
\ No newline at end of file
+pub mod route;
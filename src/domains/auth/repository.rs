@@ -0,0 +1,209 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+use tracing::instrument;
+use crate::domains::auth::entity::{ConsumedMagicLink, LoginLockout};
+
+#[instrument(name = "db.find_login_lockout", skip_all, fields(db.system = "postgresql", db.operation = "SELECT"))]
+pub async fn find_login_lockout(pool: &PgPool, email: &str) -> Result<Option<LoginLockout>, String> {
+    sqlx::query_as!(
+        LoginLockout,
+        r#"
+        SELECT email, failed_attempts, lockout_count, locked_until
+        FROM login_lockouts
+        WHERE email = $1
+        "#,
+        email
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+/// Counts a failed login and, once `threshold` is reached, locks the email
+/// for `base_secs * 2^lockout_count` seconds (at most `max_secs`).
+///
+/// History older than `max_secs` is forgotten, so an account is not punished
+/// for failures long past.
+#[instrument(name = "db.record_failed_login", skip_all, fields(db.system = "postgresql", db.operation = "UPSERT"))]
+pub async fn record_failed_login(
+    pool: &PgPool,
+    email: &str,
+    threshold: i32,
+    base_secs: i64,
+    max_secs: i64,
+) -> Result<LoginLockout, String> {
+    sqlx::query!(
+        r#"
+        INSERT INTO login_lockouts (email, failed_attempts, last_failed_at)
+        VALUES ($1, 1, NOW())
+        ON CONFLICT (email) DO UPDATE SET
+            failed_attempts = CASE
+                WHEN login_lockouts.last_failed_at < NOW() - make_interval(secs => $2) THEN 1
+                ELSE login_lockouts.failed_attempts + 1
+            END,
+            lockout_count = CASE
+                WHEN login_lockouts.last_failed_at < NOW() - make_interval(secs => $2) THEN 0
+                ELSE login_lockouts.lockout_count
+            END,
+            last_failed_at = NOW()
+        "#,
+        email,
+        max_secs as f64
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| format!("Database error: {}", e))?;
+
+    // Row locking makes concurrent failures past the threshold lock only once.
+    sqlx::query_as!(
+        LoginLockout,
+        r#"
+        UPDATE login_lockouts
+        SET
+            failed_attempts = CASE WHEN failed_attempts >= $2 THEN 0 ELSE failed_attempts END,
+            lockout_count = CASE WHEN failed_attempts >= $2 THEN lockout_count + 1 ELSE lockout_count END,
+            locked_until = CASE
+                WHEN failed_attempts >= $2
+                    THEN NOW() + make_interval(secs => LEAST($3 * power(2, LEAST(lockout_count, 30)), $4))
+                ELSE locked_until
+            END
+        WHERE email = $1
+        RETURNING email, failed_attempts, lockout_count, locked_until
+        "#,
+        email,
+        threshold,
+        base_secs as f64,
+        max_secs as f64
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+#[instrument(name = "db.clear_login_lockout", skip_all, fields(db.system = "postgresql", db.operation = "DELETE"))]
+pub async fn clear_login_lockout(pool: &PgPool, email: &str) -> Result<bool, String> {
+    sqlx::query!("DELETE FROM login_lockouts WHERE email = $1", email)
+        .execute(pool)
+        .await
+        .map(|result| result.rows_affected() > 0)
+        .map_err(|e| format!("Database error: {}", e))
+}
+
+/// Deletes lockouts that have expired and have seen no failure for
+/// `idle_secs`, whose history `record_failed_login` would forget anyway.
+/// Returns how many were deleted.
+#[instrument(name = "db.delete_stale_login_lockouts", skip_all, fields(db.system = "postgresql", db.operation = "DELETE"))]
+pub async fn delete_stale_login_lockouts(pool: &PgPool, idle_secs: i64) -> Result<u64, String> {
+    sqlx::query!(
+        r#"
+        DELETE FROM login_lockouts
+        WHERE last_failed_at < NOW() - make_interval(secs => $1)
+            AND (locked_until IS NULL OR locked_until < NOW())
+        "#,
+        idle_secs as f64
+    )
+    .execute(pool)
+    .await
+    .map(|result| result.rows_affected())
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+/// Stores an unlock token, replacing the user's earlier ones so only the
+/// latest emailed link works.
+#[instrument(name = "db.create_unlock_token", skip_all, fields(db.system = "postgresql", db.operation = "INSERT"))]
+pub async fn create_unlock_token(
+    pool: &PgPool,
+    user_id: &Uuid,
+    token_hash: &str,
+    expires_at: DateTime<Utc>,
+) -> Result<(), String> {
+    sqlx::query!("DELETE FROM account_unlock_tokens WHERE user_id = $1", user_id)
+        .execute(pool)
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO account_unlock_tokens (user_id, token_hash, expires_at)
+        VALUES ($1, $2, $3)
+        "#,
+        user_id,
+        token_hash,
+        expires_at
+    )
+    .execute(pool)
+    .await
+    .map(|_| ())
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+/// Marks an unexpired, unused token as used and returns its user.
+#[instrument(name = "db.consume_unlock_token", skip_all, fields(db.system = "postgresql", db.operation = "UPDATE"))]
+pub async fn consume_unlock_token(pool: &PgPool, token_hash: &str) -> Result<Option<Uuid>, String> {
+    sqlx::query_scalar!(
+        r#"
+        UPDATE account_unlock_tokens
+        SET used_at = NOW()
+        WHERE token_hash = $1 AND used_at IS NULL AND expires_at > NOW()
+        RETURNING user_id
+        "#,
+        token_hash
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+/// Stores a magic link, invalidating the user's earlier unused ones and
+/// dropping everyone's expired ones. `user_id` is `None` for emails without
+/// an account, whose links are stored only so the work matches.
+#[instrument(name = "db.create_magic_link", skip_all, fields(db.system = "postgresql", db.operation = "INSERT"))]
+pub async fn create_magic_link(
+    pool: &PgPool,
+    user_id: Option<&Uuid>,
+    token_hash: &str,
+    nonce_hash: &str,
+    expires_at: DateTime<Utc>,
+) -> Result<(), String> {
+    sqlx::query!(
+        "DELETE FROM magic_links WHERE (user_id = $1 AND used_at IS NULL) OR expires_at <= NOW()",
+        user_id
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| format!("Database error: {}", e))?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO magic_links (user_id, token_hash, nonce_hash, expires_at)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        user_id,
+        token_hash,
+        nonce_hash,
+        expires_at
+    )
+    .execute(pool)
+    .await
+    .map(|_| ())
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+/// Marks an unexpired, unused link as used and returns it.
+#[instrument(name = "db.consume_magic_link", skip_all, fields(db.system = "postgresql", db.operation = "UPDATE"))]
+pub async fn consume_magic_link(pool: &PgPool, token_hash: &str) -> Result<Option<ConsumedMagicLink>, String> {
+    sqlx::query_as!(
+        ConsumedMagicLink,
+        r#"
+        UPDATE magic_links
+        SET used_at = NOW()
+        WHERE token_hash = $1 AND user_id IS NOT NULL AND used_at IS NULL AND expires_at > NOW()
+        RETURNING user_id AS "user_id!", nonce_hash
+        "#,
+        token_hash
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("Database error: {}", e))
+}
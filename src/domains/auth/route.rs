@@ -1,10 +1,36 @@
 use actix_web::web;
 use super::controller;
+use crate::config;
+use crate::domains::identity::route as identity_routes;
+use crate::domains::webauthn::route as webauthn_routes;
+use crate::utils::middleware::rate_limit::RateLimitMiddleware;
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
-    cfg.service(
-        web::scope("/auth")
-            .service(controller::handle_register)
-            .service(controller::handle_login)
-    );
-}
\ No newline at end of file
+    let per_ip = RateLimitMiddleware::per_ip("auth", 20, 60);
+
+    // The last wrap runs first: the per-IP limiter is outermost, so requests
+    // it rejects never reach the shared bucket. The shared ceiling is opt-in,
+    // since any fixed cap across all clients also lets a few of them lock
+    // everyone else out.
+    match config::get_rate_limit("AUTH_GLOBAL") {
+        Some((max_requests, window_secs)) => cfg.service(
+            web::scope("/auth")
+                .wrap(RateLimitMiddleware::per_route_group("auth_global", max_requests, window_secs))
+                .wrap(per_ip)
+                .configure(configure_routes)
+        ),
+        None => cfg.service(web::scope("/auth").wrap(per_ip).configure(configure_routes)),
+    };
+}
+
+fn configure_routes(cfg: &mut web::ServiceConfig) {
+    cfg.service(controller::handle_register)
+        .service(controller::handle_register_with_invitation)
+        .service(controller::handle_login)
+        .service(controller::handle_request_unlock)
+        .service(controller::handle_unlock)
+        .service(controller::handle_request_magic_link)
+        .service(controller::handle_consume_magic_link)
+        .configure(identity_routes::configure_sign_in)
+        .configure(webauthn_routes::configure_ceremonies);
+}
@@ -1,73 +1,512 @@
-use bcrypt::{hash, DEFAULT_COST};
 use sqlx::PgPool;
 use chrono::Utc;
-use log::{warn, info};
-use crate::domains::user::repository::{find_user_by_email, create_user};
+use log::{error, warn, info};
+use serde_json::json;
+use tracing::instrument;
+use uuid::Uuid;
+use crate::config;
+use crate::domains::audit::entity::{NewAuditEvent, ACTION_LOGIN_FAILURE, ACTION_LOGIN_SUCCESS, ACTION_REGISTER};
+use crate::domains::audit::service as audit;
+use crate::domains::auth::repository::{
+    clear_login_lockout, consume_magic_link, consume_unlock_token, create_magic_link, create_unlock_token,
+    delete_stale_login_lockouts, find_login_lockout, record_failed_login,
+};
+use crate::domains::invitation::repository::find_pending_invitation;
+use crate::domains::invitation::service as invitation_service;
+use crate::domains::user::repository::{find_user_by_email, find_user_by_id, find_user_role, create_user, replace_password_hash};
+use crate::domains::auth::entity::LoginOutcome;
+use crate::domains::session::service::start_session;
 use crate::domains::user::entity::User;
+use crate::domains::webauthn::service::second_factor_options;
 use crate::utils::auth;
 use crate::utils::error::AppError;
-use crate::utils::auth::verify_user_password;
-use crate::utils::rate_limiter::LOGIN_LIMITER;
+use crate::utils::extractors::RequestContext;
+use crate::utils::auth::{hash_password, Claims, verify_dummy_password, verify_user_password, PASSWORD_HASHER};
+use crate::utils::mailer::{self, Email};
+use crate::utils::password_policy::{PasswordContext, PASSWORD_POLICY};
+use crate::utils::rate_limiter::{LOGIN_LIMITER, MAGIC_LINK_LIMITER, UNLOCK_LIMITER};
 
+#[instrument(name = "auth.register_user", skip_all)]
 pub async fn register_user(
     pool: &PgPool,
     email: String,
     name: Option<&str>,
     address: Option<&str>,
     phone: Option<&str>,
-    password: &str
+    password: &str,
+    context: &RequestContext
 ) -> Result<User, AppError> {
+    check_password_policy(password, &email, name)?;
+
     // Check if email already exists
     if let Ok(Some(_)) = find_user_by_email(pool, &email).await {
         return Err(AppError::validation("Email already exists"));
     }
 
-    let password_hash = hash(password.as_bytes(), DEFAULT_COST)
-        .map_err(|e| AppError::internal(format!("Password hashing error: {}", e)))?;
+    create_account(pool, email, name, address, phone, password, context).await
+}
+
+/// Registration for `REGISTRATION_MODE=uniform`: the caller gets the same
+/// result whether or not the email is taken. The owner of an existing
+/// account is told by email instead, and both paths hash the password so
+/// they take the same time.
+#[instrument(name = "auth.register_user_uniform", skip_all)]
+pub async fn register_user_uniform(
+    pool: &PgPool,
+    email: String,
+    name: Option<&str>,
+    address: Option<&str>,
+    phone: Option<&str>,
+    password: &str,
+    context: &RequestContext
+) -> Result<(), AppError> {
+    check_password_policy(password, &email, name)?;
+
+    let existing = find_user_by_email(pool, &email).await
+        .map_err(|e| AppError::internal(format!("Database error: {}", e)))?;
+
+    let (user_email, subject, body) = match existing {
+        Some(user) => {
+            warn!("Registration attempt with existing email: {}", user.email);
+            hash_password(password).await?;
+            (
+                user.email,
+                "Someone tried to register with your email",
+                "Someone tried to create an account with this email address, which already has one.\n\n\
+                 If it was you, sign in with your existing account instead. If not, you can ignore this email."
+                    .to_string(),
+            )
+        },
+        None => {
+            let user = create_account(pool, email, name, address, phone, password, context).await?;
+            (user.email, "Welcome", "Your account has been created. You can now sign in.".to_string())
+        },
+    };
+
+    mailer::send_in_background(Email::new(&user_email, subject, body));
+    Ok(())
+}
 
-    let user = User {
+fn check_password_policy(password: &str, email: &str, name: Option<&str>) -> Result<(), AppError> {
+    PASSWORD_POLICY
+        .validate("password", password, &PasswordContext { email, name })
+        .map_err(AppError::FieldValidationError)
+}
+
+async fn create_account(
+    pool: &PgPool,
+    email: String,
+    name: Option<&str>,
+    address: Option<&str>,
+    phone: Option<&str>,
+    password: &str,
+    context: &RequestContext
+) -> Result<User, AppError> {
+    let password_hash = hash_password(password).await?;
+
+    let user = new_user(email, name, address, phone, password_hash);
+    let user = create_user(pool, &user).await
+        .map_err(|e| AppError::DatabaseError(sqlx::Error::Protocol(e)))?;
+
+    let event = NewAuditEvent::new(ACTION_REGISTER, context)
+        .actor(user.id)
+        .subject(user.id)
+        .details(json!({ "method": "password" }));
+    audit::record_or_log(pool, event).await;
+    Ok(user)
+}
+
+/// Registration through an organization invitation. The account gets the
+/// invited email, already verified since the token was sent to it, and joins
+/// the organization in the same transaction.
+#[instrument(name = "auth.register_with_invitation", skip_all)]
+pub async fn register_with_invitation(
+    pool: &PgPool,
+    token: &str,
+    name: Option<&str>,
+    address: Option<&str>,
+    phone: Option<&str>,
+    password: &str,
+    context: &RequestContext
+) -> Result<User, AppError> {
+    let invitation = find_pending_invitation(pool, &auth::hash_secret_token(token)).await
+        .map_err(|e| AppError::internal(format!("Database error: {}", e)))?
+        .ok_or_else(|| AppError::validation("Invalid or expired invitation"))?;
+    check_password_policy(password, &invitation.email, name)?;
+
+    if let Ok(Some(_)) = find_user_by_email(pool, &invitation.email).await {
+        return Err(AppError::validation("An account already exists for this email; sign in to accept the invitation"));
+    }
+
+    let password_hash = hash_password(password).await?;
+    let user = new_user(invitation.email, name, address, phone, password_hash);
+
+    let mut tx = pool.begin().await.map_err(AppError::DatabaseError)?;
+    let user = create_user(&mut *tx, &user).await
+        .map_err(|e| AppError::DatabaseError(sqlx::Error::Protocol(e)))?;
+    let joined = invitation_service::redeem(&mut tx, token, &user.id, &user.email).await?;
+    tx.commit().await.map_err(AppError::DatabaseError)?;
+
+    let event = NewAuditEvent::new(ACTION_REGISTER, context)
+        .actor(user.id)
+        .subject(user.id)
+        .details(json!({ "method": "invitation", "org_id": joined.org_id }));
+    audit::record_or_log(pool, event).await;
+    Ok(user)
+}
+
+fn new_user(email: String, name: Option<&str>, address: Option<&str>, phone: Option<&str>, password_hash: String) -> User {
+    User {
         id: uuid::Uuid::new_v4(),
         email,
         name: name.map(String::from),
         phone: phone.map(String::from),
         address: address.map(String::from),
-        password_hash,
+        password_hash: Some(password_hash),
         created_at: Utc::now(),
         updated_at: None,
         deleted_at: None,
-    };
-
-    create_user(pool, &user).await
-        .map_err(|e| AppError::DatabaseError(sqlx::Error::Protocol(e)))
+    }
 }
 
+#[instrument(name = "auth.login_user", skip_all, fields(user.id = tracing::field::Empty))]
 pub async fn login_user(
     pool: &PgPool,
     email: &str,
-    password: &str
-) -> Result<String, AppError> {
+    password: &str,
+    context: &RequestContext
+) -> Result<LoginOutcome, AppError> {
+    // The rate limit and lockout are keyed by email whether or not it is
+    // registered, so the response does not reveal which emails exist.
+    let lockout_key = normalize_email(email);
+
     // Check rate limit before processing login
-    LOGIN_LIMITER.check_rate_limit(email).await?;
+    LOGIN_LIMITER.check_rate_limit(&lockout_key).await?;
+    check_login_lockout(pool, &lockout_key, context).await?;
 
-    let user = match find_user_by_email(pool, email).await {
-        Ok(Some(user)) => user,
-        Ok(None) => {
+    let user = find_user_by_email(pool, email).await
+        .map_err(|e| AppError::internal(format!("Database error: {}", e)))?;
+
+    let verified = match &user {
+        Some(user) => {
+            tracing::Span::current().record("user.id", tracing::field::display(user.id));
+            verify_user_password(user, password).await?
+        },
+        None => {
             warn!("Login attempt with non-existent email: {}", email);
+            // Take as long as a wrong password would
+            verify_dummy_password(password).await?;
+            false
+        },
+    };
+
+    let user = match user {
+        Some(user) if verified => user,
+        user => {
+            warn!("Failed login attempt for user: {}", email);
+            record_login_failure(pool, &lockout_key, user.as_ref(), context).await?;
             return Err(AppError::authentication("Invalid credentials"));
         },
-        Err(e) => return Err(AppError::internal(format!("Database error: {}", e))),
     };
 
-    if !verify_user_password(&user, password)
-        .map_err(|e| AppError::internal(e))? {
-        warn!("Failed login attempt for user: {}", email);
-        return Err(AppError::authentication("Invalid credentials"));
+    if user.password_hash.as_deref().is_some_and(|hash| PASSWORD_HASHER.needs_rehash(hash)) {
+        rehash_password(pool, &user, password).await;
     }
 
-    // Reset rate limit counter on successful login
-    LOGIN_LIMITER.reset(email).await;
+    // Reset rate limit counter and lockout state on successful login
+    LOGIN_LIMITER.reset(&lockout_key).await;
+    clear_login_lockout(pool, &lockout_key).await
+        .map_err(|e| AppError::internal(format!("Database error: {}", e)))?;
     info!("Successful login for user: {}", email);
 
-    auth::generate_token(user.id)
-        .map_err(|e| AppError::internal(e))
-}
\ No newline at end of file
+    complete_first_factor(pool, &user.id, "password", context).await
+}
+
+/// Emails a single-use sign-in link to `email` if it belongs to an account
+/// and returns the nonce the requesting device must present with the link.
+/// A nonce is returned for unknown emails too, after the same database work,
+/// so neither the response nor its timing reveals which are registered.
+#[instrument(name = "auth.request_magic_link", skip_all)]
+pub async fn request_magic_link(pool: &PgPool, email: &str) -> Result<String, AppError> {
+    MAGIC_LINK_LIMITER.check_rate_limit(&normalize_email(email)).await?;
+
+    let (nonce, nonce_hash) = auth::generate_secret_token();
+    let user = find_user_by_email(pool, email).await
+        .map_err(|e| AppError::internal(format!("Database error: {}", e)))?;
+
+    // Unknown emails get a link too, never sent, so both take the same time
+    let (token, token_hash) = auth::generate_secret_token();
+    let ttl_mins = config::get_magic_link_ttl_mins();
+    let expires_at = Utc::now() + chrono::Duration::minutes(ttl_mins);
+    create_magic_link(pool, user.as_ref().map(|user| &user.id), &token_hash, &nonce_hash, expires_at).await
+        .map_err(|e| AppError::internal(format!("Database error: {}", e)))?;
+    let Some(user) = user else {
+        warn!("Magic link requested for non-existent email: {}", email);
+        return Ok(nonce);
+    };
+
+    mailer::send_in_background(Email::new(
+        &user.email,
+        "Your sign-in link",
+        format!(
+            "Use this link to sign in (valid for {} minutes). Open it on the device where you asked for it:\n{}?token={}\n\n\
+             If you did not ask to sign in, you can ignore this email.",
+            ttl_mins,
+            config::get_magic_link_url(),
+            token
+        ),
+    ));
+    info!("Sent magic link to user {}", user.id);
+    Ok(nonce)
+}
+
+/// Redeems a magic link for an access token, as a password login would.
+/// The link is used up even if the nonce does not match, so a stolen link
+/// cannot be retried.
+#[instrument(name = "auth.login_with_magic_link", skip_all, fields(user.id = tracing::field::Empty))]
+pub async fn login_with_magic_link(
+    pool: &PgPool,
+    token: &str,
+    nonce: &str,
+    context: &RequestContext
+) -> Result<LoginOutcome, AppError> {
+    let link = consume_magic_link(pool, &auth::hash_secret_token(token)).await
+        .map_err(|e| AppError::internal(format!("Database error: {}", e)))?
+        .ok_or_else(|| AppError::authentication("Invalid or expired sign-in link"))?;
+    tracing::Span::current().record("user.id", tracing::field::display(link.user_id));
+
+    if link.nonce_hash != auth::hash_secret_token(nonce) {
+        warn!("Magic link for user {} redeemed from another device", link.user_id);
+        audit_login_failure(pool, Some(link.user_id), "magic_link", "wrong_device", context).await;
+        return Err(AppError::authentication("This sign-in link was requested from another device"));
+    }
+
+    let user = find_user_by_id(pool, &link.user_id).await
+        .map_err(|e| AppError::internal(format!("Database error: {}", e)))?
+        .ok_or_else(|| AppError::authentication("Invalid or expired sign-in link"))?;
+    info!("Successful magic link login for user: {}", user.email);
+
+    complete_first_factor(pool, &user.id, "magic_link", context).await
+}
+
+/// Issues a token after a password, magic link or identity provider sign-in,
+/// unless the user has registered passkeys: then one of them has to complete
+/// the sign-in.
+pub async fn complete_first_factor(
+    pool: &PgPool,
+    user_id: &Uuid,
+    method: &str,
+    context: &RequestContext
+) -> Result<LoginOutcome, AppError> {
+    match second_factor_options(pool, user_id).await? {
+        Some(options) => {
+            info!("User {} must complete sign-in with a passkey", user_id);
+            Ok(LoginOutcome::SecondFactorRequired(options))
+        },
+        None => issue_token(pool, user_id, method, context).await.map(LoginOutcome::Token),
+    }
+}
+
+/// Starts a session for a user who has just signed in with `method` and
+/// issues an access token bound to it.
+pub async fn issue_token(pool: &PgPool, user_id: &Uuid, method: &str, context: &RequestContext) -> Result<String, AppError> {
+    let roles = find_user_role(pool, user_id).await
+        .map_err(|e| AppError::internal(format!("Database error: {}", e)))?
+        .into_iter()
+        .collect();
+    let claims = Claims::new(*user_id).with_roles(roles);
+    let expires_at = chrono::DateTime::from_timestamp(claims.exp, 0)
+        .ok_or_else(|| AppError::internal("Token expiry out of range"))?;
+
+    let session_id = start_session(pool, user_id, context, expires_at).await?;
+    let token = auth::generate_token(&claims.with_session(session_id))?;
+
+    let event = NewAuditEvent::new(ACTION_LOGIN_SUCCESS, context)
+        .actor(*user_id)
+        .subject(*user_id)
+        .details(json!({ "method": method, "session_id": session_id }));
+    audit::record_or_log(pool, event).await;
+    Ok(token)
+}
+
+/// Records a rejected sign-in, against the account it was for when known.
+async fn audit_login_failure(
+    pool: &PgPool,
+    user_id: Option<Uuid>,
+    method: &str,
+    reason: &str,
+    context: &RequestContext
+) {
+    let mut event = NewAuditEvent::new(ACTION_LOGIN_FAILURE, context)
+        .details(json!({ "method": method, "reason": reason }));
+    if let Some(user_id) = user_id {
+        event = event.subject(user_id);
+    }
+    audit::record_or_log(pool, event).await;
+}
+
+/// Upgrades an outdated hash while the plaintext is at hand. Failures are
+/// only logged: the user is already authenticated and can be upgraded on
+/// their next login.
+async fn rehash_password(pool: &PgPool, user: &User, password: &str) {
+    let new_hash = match hash_password(password).await {
+        Ok(new_hash) => new_hash,
+        Err(e) => {
+            warn!("Could not rehash password for user {}: {}", user.id, e);
+            return;
+        },
+    };
+
+    match replace_password_hash(pool, &user.id, user.password_hash.as_deref(), &new_hash).await {
+        Ok(true) => info!("Upgraded password hash for user {}", user.id),
+        Ok(false) => warn!("Password for user {} changed during rehash, keeping the new one", user.id),
+        Err(e) => warn!("Could not store rehashed password for user {}: {}", user.id, e),
+    }
+}
+
+fn normalize_email(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+async fn check_login_lockout(pool: &PgPool, lockout_key: &str, context: &RequestContext) -> Result<(), AppError> {
+    let lockout = find_login_lockout(pool, lockout_key).await
+        .map_err(|e| AppError::internal(format!("Database error: {}", e)))?;
+
+    if let Some(retry_after) = lockout.and_then(|lockout| lockout.remaining_secs(Utc::now())) {
+        warn!("Login attempt for locked email: {}", lockout_key);
+        let user_id = find_user_by_email(pool, lockout_key).await.ok().flatten().map(|user| user.id);
+        audit_login_failure(pool, user_id, "password", "locked", context).await;
+        return Err(AppError::rate_limited(
+            format!(
+                "Too many failed login attempts. Please try again after {} seconds or use the unlock link sent by email",
+                retry_after
+            ),
+            retry_after,
+        ));
+    }
+
+    Ok(())
+}
+
+async fn record_login_failure(
+    pool: &PgPool,
+    lockout_key: &str,
+    user: Option<&User>,
+    context: &RequestContext
+) -> Result<(), AppError> {
+    audit_login_failure(pool, user.map(|user| user.id), "password", "invalid_credentials", context).await;
+
+    let lockout = record_failed_login(
+        pool,
+        lockout_key,
+        config::get_lockout_threshold(),
+        config::get_lockout_base_secs(),
+        config::get_lockout_max_secs(),
+    )
+    .await
+    .map_err(|e| AppError::internal(format!("Database error: {}", e)))?;
+
+    // A lockout that started with this attempt has its failure count reset
+    let locked_now = lockout.failed_attempts == 0 && lockout.remaining_secs(Utc::now()).is_some();
+    if locked_now {
+        warn!(
+            "Locked login for {} until {:?} (lockout #{})",
+            lockout.email, lockout.locked_until, lockout.lockout_count
+        );
+        if let Some(user) = user {
+            send_unlock_email(pool, user).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Emails the user a single-use link that lifts their lockout.
+async fn send_unlock_email(pool: &PgPool, user: &User) -> Result<(), AppError> {
+    let (token, token_hash) = auth::generate_secret_token();
+    let ttl_mins = config::get_unlock_token_ttl_mins();
+    let expires_at = Utc::now() + chrono::Duration::minutes(ttl_mins);
+
+    create_unlock_token(pool, &user.id, &token_hash, expires_at).await
+        .map_err(|e| AppError::internal(format!("Database error: {}", e)))?;
+
+    mailer::send_in_background(Email::new(
+        &user.email,
+        "Your account has been locked",
+        format!(
+            "We locked sign-in to your account after several failed login attempts.\n\n\
+             If this was you, unlock it now with this link (valid for {} minutes):\n{}/api/auth/unlock?token={}\n\n\
+             If it was not you, consider changing your password.",
+            ttl_mins,
+            config::get_public_url(),
+            token
+        ),
+    ));
+    Ok(())
+}
+
+/// Sends a fresh unlock link if `email` belongs to a locked account. Always
+/// succeeds, unless rate limited, so the caller cannot learn whether the
+/// email is registered. The limit is per address, whatever the account's
+/// state, so requests from many IPs cannot flood one inbox.
+#[instrument(name = "auth.request_unlock", skip_all)]
+pub async fn request_unlock(pool: &PgPool, email: &str) -> Result<(), AppError> {
+    let lockout_key = normalize_email(email);
+    UNLOCK_LIMITER.check_rate_limit(&lockout_key).await?;
+
+    let locked = find_login_lockout(pool, &lockout_key).await
+        .map_err(|e| AppError::internal(format!("Database error: {}", e)))?
+        .is_some_and(|lockout| lockout.remaining_secs(Utc::now()).is_some());
+    if !locked {
+        return Ok(());
+    }
+
+    match find_user_by_email(pool, email).await {
+        Ok(Some(user)) => send_unlock_email(pool, &user).await,
+        Ok(None) => Ok(()),
+        Err(e) => Err(AppError::internal(format!("Database error: {}", e))),
+    }
+}
+
+#[instrument(name = "auth.unlock_with_token", skip_all, fields(user.id = tracing::field::Empty))]
+pub async fn unlock_with_token(pool: &PgPool, token: &str) -> Result<(), AppError> {
+    let user_id = consume_unlock_token(pool, &auth::hash_secret_token(token)).await
+        .map_err(|e| AppError::internal(format!("Database error: {}", e)))?
+        .ok_or_else(|| AppError::validation("Invalid or expired unlock link"))?;
+    tracing::Span::current().record("user.id", tracing::field::display(user_id));
+
+    unlock_account(pool, &user_id).await
+}
+
+/// Clears the lockout and failure history of a user's email.
+pub async fn unlock_account(pool: &PgPool, user_id: &Uuid) -> Result<(), AppError> {
+    let user = find_user_by_id(pool, user_id).await
+        .map_err(|e| AppError::internal(format!("Database error: {}", e)))?
+        .ok_or_else(|| AppError::not_found(format!("User not found for ID: {}", user_id)))?;
+
+    let lockout_key = normalize_email(&user.email);
+    clear_login_lockout(pool, &lockout_key).await
+        .map_err(|e| AppError::internal(format!("Database error: {}", e)))?;
+    LOGIN_LIMITER.reset(&lockout_key).await;
+    info!("Unlocked login for user: {}", user.id);
+    Ok(())
+}
+
+/// Periodically deletes expired lockouts of emails that have not failed a
+/// login for `LOCKOUT_MAX_SECS`. Every email tried gets a row, so without
+/// this the table would grow with each guess.
+pub fn start_lockout_sweeper(pool: PgPool) {
+    let interval = std::time::Duration::from_secs(config::get_lockout_sweep_secs());
+    let idle_secs = config::get_lockout_max_secs();
+    actix_web::rt::spawn(async move {
+        let mut ticker = actix_web::rt::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match delete_stale_login_lockouts(&pool, idle_secs).await {
+                Ok(0) => {},
+                Ok(deleted) => info!("Deleted {} stale login lockouts", deleted),
+                Err(e) => error!("Login lockout sweep failed: {}", e),
+            }
+        }
+    });
+}
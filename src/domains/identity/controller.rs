@@ -0,0 +1,166 @@
+use actix_web::cookie::{time, Cookie, SameSite};
+use actix_web::{delete, get, post, web, http::header, HttpRequest, HttpResponse};
+use log::{error, warn};
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::config;
+use crate::domains::auth::controller::login_response;
+use crate::domains::identity::entity::{CallbackQuery, OidcOutcome};
+use crate::domains::identity::service::{complete_authorization, list_identities, start_authorization, unlink_identity};
+use crate::utils::error::AppError;
+use crate::utils::extractors::{AuthUser, RequestContext};
+use crate::utils::response::{Response, ResponseBuilder};
+
+/// Holds the value tying an authorization request to the browser that
+/// started it. Only sent back to the callback, which arrives as a top-level
+/// navigation from the provider and so carries `SameSite=Lax` cookies.
+const BROWSER_BINDING_COOKIE: &str = "oidc_binding";
+const BROWSER_BINDING_PATH: &str = "/api/auth/oidc";
+
+fn browser_binding_cookie(value: String) -> Cookie<'static> {
+    Cookie::build(BROWSER_BINDING_COOKIE, value)
+        .path(BROWSER_BINDING_PATH)
+        .http_only(true)
+        .secure(config::get_public_url().starts_with("https://"))
+        .same_site(SameSite::Lax)
+        .max_age(time::Duration::minutes(config::get_oidc_auth_request_ttl_mins()))
+        .finish()
+}
+
+/// Sends the browser to the provider to sign in.
+#[get("/oidc/{provider}/authorize")]
+pub async fn handle_authorize(
+    pool: web::Data<PgPool>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    match start_authorization(pool.get_ref(), &path.into_inner(), None).await {
+        Ok((url, browser_binding)) => Ok(HttpResponse::Found()
+            .insert_header((header::LOCATION, url))
+            .cookie(browser_binding_cookie(browser_binding))
+            .finish()),
+        Err(AppError::NotFoundError(e)) => Ok(Response::not_found(&e)),
+        Err(e) => {
+            error!("Unexpected error while starting OIDC sign-in: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
+
+/// Where the provider redirects back to, for both sign-in and linking.
+/// Only the browser holding the request's binding cookie may complete it.
+#[get("/oidc/{provider}/callback")]
+pub async fn handle_callback(
+    req: HttpRequest,
+    pool: web::Data<PgPool>,
+    path: web::Path<String>,
+    query: web::Query<CallbackQuery>,
+    context: RequestContext,
+) -> Result<HttpResponse, AppError> {
+    let browser_binding = req.cookie(BROWSER_BINDING_COOKIE).map(|cookie| cookie.value().to_string());
+    let mut response = callback_response(&pool, &path.into_inner(), &query, browser_binding.as_deref(), &context).await;
+    if browser_binding.is_some() {
+        let mut cookie = browser_binding_cookie(String::new());
+        cookie.make_removal();
+        response.add_cookie(&cookie).map_err(AppError::internal)?;
+    }
+    Ok(response)
+}
+
+async fn callback_response(
+    pool: &PgPool,
+    provider: &str,
+    query: &CallbackQuery,
+    browser_binding: Option<&str>,
+    context: &RequestContext,
+) -> HttpResponse {
+    if let Some(e) = &query.error {
+        warn!("Identity provider returned an error: {} {:?}", e, query.error_description);
+        return Response::bad_request("Sign-in was cancelled or refused by the identity provider");
+    }
+    let (Some(code), Some(state)) = (&query.code, &query.state) else {
+        return Response::bad_request("Missing code or state");
+    };
+
+    match complete_authorization(pool, provider, code, state, browser_binding, context).await {
+        Ok(OidcOutcome::SignedIn(outcome)) => login_response(outcome),
+        Ok(OidcOutcome::Linked(identity)) => Response::ok(identity),
+        Err(AppError::ValidationError(e)) => {
+            warn!("OIDC callback rejected: {}", e);
+            Response::bad_request(&e)
+        },
+        Err(AppError::AuthenticationError(e)) => {
+            warn!("OIDC authentication failed: {}", e);
+            Response::unauthorized(&e)
+        },
+        Err(AppError::NotFoundError(e)) => Response::not_found(&e),
+        Err(e) => {
+            error!("Unexpected error during OIDC callback: {}", e);
+            Response::internal_error("An unexpected error occurred")
+        }
+    }
+}
+
+#[get("")]
+pub async fn handle_list_identities(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    user.require_full_access()?;
+
+    match list_identities(pool.get_ref(), &user.id).await {
+        Ok(identities) => Ok(Response::ok(identities)),
+        Err(e) => {
+            error!("Unexpected error while listing identities: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
+
+/// Starts linking a provider; the client sends the browser to the returned URL.
+#[post("/{provider}")]
+pub async fn handle_link_identity(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    user.require_full_access()?;
+
+    match start_authorization(pool.get_ref(), &path.into_inner(), Some(user.id)).await {
+        Ok((url, browser_binding)) => {
+            let mut response = Response::ok(json!({ "authorization_url": url }));
+            response.add_cookie(&browser_binding_cookie(browser_binding)).map_err(AppError::internal)?;
+            Ok(response)
+        },
+        Err(AppError::NotFoundError(e)) => Ok(Response::not_found(&e)),
+        Err(e) => {
+            error!("Unexpected error while starting OIDC linking: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
+
+#[delete("/{id}")]
+pub async fn handle_unlink_identity(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    user.require_full_access()?;
+
+    match unlink_identity(pool.get_ref(), &user.id, &path.into_inner()).await {
+        Ok(()) => Ok(Response::ok(json!({ "message": "Identity unlinked" }))),
+        Err(AppError::ValidationError(e)) => {
+            warn!("Validation error: {}", e);
+            Ok(Response::bad_request(&e))
+        },
+        Err(AppError::NotFoundError(e)) => {
+            warn!("Identity not found: {}", e);
+            Ok(Response::not_found(&e))
+        },
+        Err(e) => {
+            error!("Unexpected error while unlinking identity: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
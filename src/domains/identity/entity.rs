@@ -0,0 +1,44 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::domains::auth::entity::LoginOutcome;
+
+/// An external account at an OpenID Connect provider, linked to a user.
+#[derive(Serialize)]
+pub struct UserIdentity {
+    pub id: Uuid,
+    #[serde(skip_serializing)]
+    pub user_id: Uuid,
+    pub provider: String,
+    /// The account's id at the provider (`sub`).
+    pub subject: String,
+    pub email: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_login_at: Option<DateTime<Utc>>,
+}
+
+/// What we remember about an authorization request until the provider
+/// redirects back.
+pub struct OidcAuthRequest {
+    pub provider: String,
+    pub nonce: String,
+    pub code_verifier: String,
+    /// Set when a signed-in user is linking the provider to their account.
+    pub user_id: Option<Uuid>,
+    /// Hash of the value in the starting browser's binding cookie.
+    pub browser_binding_hash: String,
+}
+
+#[derive(Deserialize)]
+pub struct CallbackQuery {
+    pub code: Option<String>,
+    pub state: Option<String>,
+    pub error: Option<String>,
+    pub error_description: Option<String>,
+}
+
+/// How a completed authorization request was used.
+pub enum OidcOutcome {
+    SignedIn(LoginOutcome),
+    Linked(UserIdentity),
+}
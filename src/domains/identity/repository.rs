@@ -0,0 +1,145 @@
+use chrono::{DateTime, Utc};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+use tracing::instrument;
+use crate::domains::identity::entity::{OidcAuthRequest, UserIdentity};
+
+/// Stores a new authorization request, dropping expired ones on the way.
+#[instrument(name = "db.create_oidc_auth_request", skip_all, fields(db.system = "postgresql", db.operation = "INSERT"))]
+pub async fn create_oidc_auth_request(
+    pool: &PgPool,
+    state_hash: &str,
+    request: &OidcAuthRequest,
+    expires_at: DateTime<Utc>,
+) -> Result<(), String> {
+    sqlx::query!("DELETE FROM oidc_auth_requests WHERE expires_at < NOW()")
+        .execute(pool)
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+
+    sqlx::query!(
+        r#"
+        INSERT INTO oidc_auth_requests (state_hash, provider, nonce, code_verifier, user_id, browser_binding_hash, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        "#,
+        state_hash,
+        request.provider,
+        request.nonce,
+        request.code_verifier,
+        request.user_id,
+        request.browser_binding_hash,
+        expires_at
+    )
+    .execute(pool)
+    .await
+    .map(|_| ())
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+/// Removes and returns the unexpired request for `state_hash`, so each
+/// `state` can be redeemed once.
+#[instrument(name = "db.consume_oidc_auth_request", skip_all, fields(db.system = "postgresql", db.operation = "DELETE"))]
+pub async fn consume_oidc_auth_request(pool: &PgPool, state_hash: &str) -> Result<Option<OidcAuthRequest>, String> {
+    sqlx::query_as!(
+        OidcAuthRequest,
+        r#"
+        DELETE FROM oidc_auth_requests
+        WHERE state_hash = $1 AND expires_at > NOW()
+        RETURNING provider, nonce, code_verifier, user_id, browser_binding_hash
+        "#,
+        state_hash
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+#[instrument(name = "db.find_identity", skip_all, fields(db.system = "postgresql", db.operation = "SELECT"))]
+pub async fn find_identity(pool: &PgPool, provider: &str, subject: &str) -> Result<Option<UserIdentity>, String> {
+    sqlx::query_as!(
+        UserIdentity,
+        r#"
+        SELECT i.id, i.user_id, i.provider, i.subject, i.email, i.created_at, i.last_login_at
+        FROM user_identities i
+        JOIN users u ON u.id = i.user_id
+        WHERE i.provider = $1 AND i.subject = $2 AND u.deleted_at IS NULL
+        "#,
+        provider,
+        subject
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+#[instrument(name = "db.create_identity", skip_all, fields(db.system = "postgresql", db.operation = "INSERT"))]
+pub async fn create_identity(
+    executor: impl PgExecutor<'_>,
+    user_id: &Uuid,
+    provider: &str,
+    subject: &str,
+    email: Option<&str>,
+) -> Result<UserIdentity, String> {
+    sqlx::query_as!(
+        UserIdentity,
+        r#"
+        INSERT INTO user_identities (user_id, provider, subject, email, last_login_at)
+        VALUES ($1, $2, $3, $4, NOW())
+        RETURNING id, user_id, provider, subject, email, created_at, last_login_at
+        "#,
+        user_id,
+        provider,
+        subject,
+        email
+    )
+    .fetch_one(executor)
+    .await
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+#[instrument(name = "db.record_identity_login", skip_all, fields(db.system = "postgresql", db.operation = "UPDATE"))]
+pub async fn record_identity_login(pool: &PgPool, id: &Uuid, email: Option<&str>) -> Result<(), String> {
+    sqlx::query!(
+        r#"
+        UPDATE user_identities
+        SET last_login_at = NOW(), email = COALESCE($2, email)
+        WHERE id = $1
+        "#,
+        id,
+        email
+    )
+    .execute(pool)
+    .await
+    .map(|_| ())
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+#[instrument(name = "db.list_identities", skip_all, fields(db.system = "postgresql", db.operation = "SELECT"))]
+pub async fn list_identities(pool: &PgPool, user_id: &Uuid) -> Result<Vec<UserIdentity>, String> {
+    sqlx::query_as!(
+        UserIdentity,
+        r#"
+        SELECT id, user_id, provider, subject, email, created_at, last_login_at
+        FROM user_identities
+        WHERE user_id = $1
+        ORDER BY created_at
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+#[instrument(name = "db.delete_identity", skip_all, fields(db.system = "postgresql", db.operation = "DELETE"))]
+pub async fn delete_identity(pool: &PgPool, user_id: &Uuid, id: &Uuid) -> Result<bool, String> {
+    sqlx::query!(
+        "DELETE FROM user_identities WHERE id = $1 AND user_id = $2",
+        id,
+        user_id
+    )
+    .execute(pool)
+    .await
+    .map(|result| result.rows_affected() > 0)
+    .map_err(|e| format!("Database error: {}", e))
+}
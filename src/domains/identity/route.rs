@@ -0,0 +1,18 @@
+use actix_web::web;
+use super::controller;
+
+/// Sign-in endpoints, mounted inside the `/auth` scope.
+pub fn configure_sign_in(cfg: &mut web::ServiceConfig) {
+    cfg.service(controller::handle_authorize)
+        .service(controller::handle_callback);
+}
+
+/// Linking endpoints, mounted inside the authenticated `/users` scope.
+pub fn configure_linking(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/identities")
+            .service(controller::handle_list_identities)
+            .service(controller::handle_link_identity)
+            .service(controller::handle_unlink_identity)
+    );
+}
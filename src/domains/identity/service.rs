@@ -0,0 +1,226 @@
+use chrono::Utc;
+use log::{info, warn};
+use serde_json::json;
+use sqlx::PgPool;
+use tracing::instrument;
+use uuid::Uuid;
+use crate::config;
+use crate::domains::audit::entity::{NewAuditEvent, ACTION_REGISTER};
+use crate::domains::audit::service as audit;
+use crate::domains::auth::service::complete_first_factor;
+use crate::domains::identity::entity::{OidcAuthRequest, OidcOutcome, UserIdentity};
+use crate::domains::identity::repository::{
+    consume_oidc_auth_request, create_identity, create_oidc_auth_request, delete_identity, find_identity,
+    list_identities as find_identities, record_identity_login,
+};
+use crate::domains::user::entity::User;
+use crate::domains::user::repository::{create_user, find_user_by_email, find_user_by_id, mark_email_verified};
+use crate::utils::auth::hash_secret_token;
+use crate::utils::error::AppError;
+use crate::utils::extractors::RequestContext;
+use crate::utils::oidc::{pkce_challenge, random_token, IdTokenClaims, OidcProvider, OIDC_PROVIDERS};
+
+fn provider(name: &str) -> Result<&'static OidcProvider, AppError> {
+    OIDC_PROVIDERS
+        .get(&name.to_lowercase())
+        .ok_or_else(|| AppError::not_found(format!("Unknown identity provider: {}", name)))
+}
+
+fn database_error(e: String) -> AppError {
+    AppError::internal(format!("Database error: {}", e))
+}
+
+/// Starts an authorization-code flow with PKCE and returns the provider URL
+/// to send the browser to, and the value for the browser's binding cookie.
+/// With `user_id`, completing it links the provider to that user instead of
+/// signing in.
+#[instrument(name = "identity.start_authorization", skip(pool))]
+pub async fn start_authorization(
+    pool: &PgPool,
+    provider_name: &str,
+    user_id: Option<Uuid>,
+) -> Result<(String, String), AppError> {
+    let provider = provider(provider_name)?;
+
+    let (state, browser_binding) = (random_token(), random_token());
+    let request = OidcAuthRequest {
+        provider: provider.name.clone(),
+        nonce: random_token(),
+        code_verifier: random_token(),
+        user_id,
+        browser_binding_hash: hash_secret_token(&browser_binding),
+    };
+    let url = provider
+        .authorization_url(&state, &request.nonce, &pkce_challenge(&request.code_verifier))
+        .await?;
+
+    let expires_at = Utc::now() + chrono::Duration::minutes(config::get_oidc_auth_request_ttl_mins());
+    create_oidc_auth_request(pool, &hash_secret_token(&state), &request, expires_at).await
+        .map_err(database_error)?;
+
+    Ok((url, browser_binding))
+}
+
+/// Handles the provider's redirect back: checks `state` and that the browser
+/// is the one that started the flow, redeems the code and verifies the ID
+/// token, then signs in or links as the request asked.
+///
+/// Without the browser check, a link URL started on one account and sent to
+/// someone else would attach their provider account to the sender's.
+#[instrument(
+    name = "identity.complete_authorization",
+    skip(pool, code, state, browser_binding, context),
+    fields(user.id = tracing::field::Empty)
+)]
+pub async fn complete_authorization(
+    pool: &PgPool,
+    provider_name: &str,
+    code: &str,
+    state: &str,
+    browser_binding: Option<&str>,
+    context: &RequestContext,
+) -> Result<OidcOutcome, AppError> {
+    let provider = provider(provider_name)?;
+
+    let request = consume_oidc_auth_request(pool, &hash_secret_token(state)).await
+        .map_err(database_error)?
+        .filter(|request| request.provider == provider.name)
+        .ok_or_else(|| AppError::validation("Invalid or expired sign-in request; please start again"))?;
+
+    if browser_binding.is_none_or(|binding| hash_secret_token(binding) != request.browser_binding_hash) {
+        warn!("{} callback from a browser that did not start the request", provider.name);
+        return Err(AppError::validation(
+            "This sign-in was started in a different browser; please start again from this one",
+        ));
+    }
+
+    let claims = provider.exchange_code(code, &request.code_verifier, &request.nonce).await?;
+
+    match request.user_id {
+        Some(user_id) => {
+            tracing::Span::current().record("user.id", tracing::field::display(user_id));
+            link_identity(pool, provider, &user_id, &claims).await.map(OidcOutcome::Linked)
+        },
+        None => {
+            let user_id = sign_in(pool, provider, &claims, context).await?;
+            tracing::Span::current().record("user.id", tracing::field::display(user_id));
+            complete_first_factor(pool, &user_id, "oidc", context).await.map(OidcOutcome::SignedIn)
+        },
+    }
+}
+
+/// Finds the user linked to the provider account, creating a password-less
+/// user on first sign-in.
+///
+/// An existing account with the same email is never linked automatically:
+/// that would hand it to whoever controls the email at the provider. Its
+/// owner has to sign in and link the provider instead.
+async fn sign_in(
+    pool: &PgPool,
+    provider: &OidcProvider,
+    claims: &IdTokenClaims,
+    context: &RequestContext,
+) -> Result<Uuid, AppError> {
+    if let Some(identity) = find_identity(pool, &provider.name, &claims.sub).await.map_err(database_error)? {
+        record_identity_login(pool, &identity.id, claims.email.as_deref()).await.map_err(database_error)?;
+        info!("User {} signed in with {}", identity.user_id, provider.name);
+        return Ok(identity.user_id);
+    }
+
+    let email = claims.email.as_deref()
+        .ok_or_else(|| AppError::validation("The identity provider did not share an email address"))?;
+    // Providers that do not say are not vouching for the address
+    if claims.email_verified != Some(true) {
+        return Err(AppError::validation("Your email address is not verified with the identity provider"));
+    }
+    if find_user_by_email(pool, email).await.map_err(database_error)?.is_some() {
+        warn!("{} sign-in for existing email {} without a linked identity", provider.name, email);
+        return Err(AppError::validation(format!(
+            "An account with this email already exists. Sign in and link {} from your account instead",
+            provider.name
+        )));
+    }
+
+    let user = User {
+        id: Uuid::new_v4(),
+        email: email.to_string(),
+        name: claims.name.clone(),
+        phone: None,
+        address: None,
+        password_hash: None,
+        created_at: Utc::now(),
+        updated_at: None,
+        deleted_at: None,
+    };
+    let mut tx = pool.begin().await.map_err(AppError::DatabaseError)?;
+    let user = create_user(&mut *tx, &user).await.map_err(database_error)?;
+    create_identity(&mut *tx, &user.id, &provider.name, &claims.sub, Some(email)).await.map_err(database_error)?;
+    mark_email_verified(&mut *tx, &user.id, email).await.map_err(database_error)?;
+    tx.commit().await.map_err(AppError::DatabaseError)?;
+    info!("Created user {} from {} sign-in", user.id, provider.name);
+
+    let event = NewAuditEvent::new(ACTION_REGISTER, context)
+        .actor(user.id)
+        .subject(user.id)
+        .details(json!({ "method": "oidc", "provider": provider.name }));
+    audit::record_or_log(pool, event).await;
+
+    Ok(user.id)
+}
+
+async fn link_identity(
+    pool: &PgPool,
+    provider: &OidcProvider,
+    user_id: &Uuid,
+    claims: &IdTokenClaims,
+) -> Result<UserIdentity, AppError> {
+    if let Some(identity) = find_identity(pool, &provider.name, &claims.sub).await.map_err(database_error)? {
+        if identity.user_id != *user_id {
+            warn!("User {} tried to link a {} account owned by another user", user_id, provider.name);
+            return Err(AppError::validation(format!(
+                "This {} account is already linked to another user",
+                provider.name
+            )));
+        }
+        return Ok(identity);
+    }
+
+    let linked = find_identities(pool, user_id).await.map_err(database_error)?;
+    if linked.iter().any(|identity| identity.provider == provider.name) {
+        return Err(AppError::validation(format!(
+            "A different {} account is already linked; unlink it first",
+            provider.name
+        )));
+    }
+
+    let identity = create_identity(pool, user_id, &provider.name, &claims.sub, claims.email.as_deref()).await
+        .map_err(database_error)?;
+    info!("Linked {} identity to user {}", provider.name, user_id);
+    Ok(identity)
+}
+
+#[instrument(name = "identity.list_identities", skip(pool))]
+pub async fn list_identities(pool: &PgPool, user_id: &Uuid) -> Result<Vec<UserIdentity>, AppError> {
+    find_identities(pool, user_id).await.map_err(database_error)
+}
+
+/// Unlinks a provider unless it is the user's only way to sign in.
+#[instrument(name = "identity.unlink_identity", skip(pool))]
+pub async fn unlink_identity(pool: &PgPool, user_id: &Uuid, id: &Uuid) -> Result<(), AppError> {
+    let user = find_user_by_id(pool, user_id).await
+        .map_err(database_error)?
+        .ok_or_else(|| AppError::not_found(format!("User not found for ID: {}", user_id)))?;
+    let identities = find_identities(pool, user_id).await.map_err(database_error)?;
+    if !identities.iter().any(|identity| identity.id == *id) {
+        return Err(AppError::not_found(format!("Identity not found for ID: {}", id)));
+    }
+    if user.password_hash.is_none() && identities.len() == 1 {
+        return Err(AppError::validation(
+            "Set a password or link another provider before unlinking your only sign-in method",
+        ));
+    }
+
+    delete_identity(pool, user_id, id).await.map_err(database_error)?;
+    info!("Unlinked identity {} from user {}", id, user_id);
+    Ok(())
+}
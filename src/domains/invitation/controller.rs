@@ -0,0 +1,114 @@
+use actix_web::{delete, get, post, web, HttpResponse};
+use log::{error, warn};
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+use validator::Validate;
+use crate::domains::invitation::entity::{AcceptInvitationRequest, InvitationRequest};
+use crate::domains::invitation::service::{accept_invitation, create_invitation, list_invitations, revoke_invitation};
+use crate::utils::auth::SCOPE_ORGS_READ;
+use crate::utils::error::{field_errors, AppError};
+use crate::utils::extractors::{AuthUser, TenantScope};
+use crate::utils::response::{Response, ResponseBuilder};
+
+#[post("/{org_id}/invitations")]
+pub async fn handle_create_invitation(
+    scope: TenantScope,
+    pool: web::Data<PgPool>,
+    req: web::Json<InvitationRequest>,
+) -> Result<HttpResponse, AppError> {
+    scope.user().require_full_access()?;
+    if let Err(errors) = req.validate() {
+        warn!("Validation failed: {:?}", errors);
+        return Ok(Response::bad_request_with_data("Validation failed", field_errors(&errors)));
+    }
+
+    match create_invitation(pool.get_ref(), &scope, &req).await {
+        Ok(invitation) => Ok(Response::created(invitation)),
+        Err(AppError::ForbiddenError(e)) => Ok(Response::forbidden(&e)),
+        Err(AppError::ValidationError(e)) => {
+            warn!("Validation error: {}", e);
+            Ok(Response::bad_request(&e))
+        },
+        Err(AppError::NotFoundError(e)) => {
+            warn!("Organization not found: {}", e);
+            Ok(Response::not_found(&e))
+        },
+        Err(e) => {
+            error!("Unexpected error while creating invitation: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
+
+#[get("/{org_id}/invitations")]
+pub async fn handle_list_invitations(
+    scope: TenantScope,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    scope.user().require_scope(SCOPE_ORGS_READ)?;
+
+    match list_invitations(pool.get_ref(), &scope).await {
+        Ok(invitations) => Ok(Response::ok(invitations)),
+        Err(AppError::ForbiddenError(e)) => Ok(Response::forbidden(&e)),
+        Err(e) => {
+            error!("Unexpected error while listing invitations: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
+
+#[delete("/{org_id}/invitations/{id}")]
+pub async fn handle_revoke_invitation(
+    scope: TenantScope,
+    pool: web::Data<PgPool>,
+    path: web::Path<(Uuid, Uuid)>,
+) -> Result<HttpResponse, AppError> {
+    scope.user().require_full_access()?;
+    let (_, id) = path.into_inner();
+
+    match revoke_invitation(pool.get_ref(), &scope, &id).await {
+        Ok(()) => Ok(Response::ok(json!({ "message": "Invitation revoked" }))),
+        Err(AppError::ForbiddenError(e)) => Ok(Response::forbidden(&e)),
+        Err(AppError::NotFoundError(e)) => {
+            warn!("Invitation not found: {}", e);
+            Ok(Response::not_found(&e))
+        },
+        Err(e) => {
+            error!("Unexpected error while revoking invitation: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
+
+/// Joins the organization for a signed-in user. New users register with
+/// the token at `POST /api/auth/register/invitation` instead.
+#[post("/invitations/accept")]
+pub async fn handle_accept_invitation(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+    req: web::Json<AcceptInvitationRequest>,
+) -> Result<HttpResponse, AppError> {
+    user.require_full_access()?;
+    if let Err(errors) = req.validate() {
+        warn!("Validation failed: {:?}", errors);
+        return Ok(Response::bad_request_with_data("Validation failed", field_errors(&errors)));
+    }
+
+    match accept_invitation(pool.get_ref(), &user, &req.token).await {
+        Ok(joined) => Ok(Response::ok(joined)),
+        Err(AppError::ForbiddenError(e)) => Ok(Response::forbidden(&e)),
+        Err(AppError::ValidationError(e)) => {
+            warn!("Validation error: {}", e);
+            Ok(Response::bad_request(&e))
+        },
+        Err(AppError::NotFoundError(e)) => {
+            warn!("User not found: {}", e);
+            Ok(Response::not_found(&e))
+        },
+        Err(e) => {
+            error!("Unexpected error while accepting invitation: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+use crate::domains::organization::entity::OrgRole;
+
+/// A pending invitation, as listed to the organization's admins.
+#[derive(Debug, Serialize)]
+pub struct Invitation {
+    pub id: Uuid,
+    pub email: String,
+    pub role: String,
+    pub invited_by: Option<Uuid>,
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// What redeeming an invitation needs to know about it.
+pub struct PendingInvitation {
+    pub org_id: Uuid,
+    pub email: String,
+    pub role: String,
+}
+
+/// The membership an accepted invitation led to.
+#[derive(Serialize)]
+pub struct JoinedOrganization {
+    pub org_id: Uuid,
+    pub role: String,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct InvitationRequest {
+    #[validate(email(message = "Invalid email format"))]
+    #[validate(length(max = 255, message = "Email must be at most 255 characters"))]
+    pub email: String,
+
+    /// Defaults to `member`.
+    pub role: Option<OrgRole>,
+
+    /// Days until the invitation expires (default: `ORG_INVITATION_TTL_DAYS`).
+    #[validate(range(min = 1, max = 30, message = "Expiry must be 1 to 30 days"))]
+    pub expires_in_days: Option<i64>,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct AcceptInvitationRequest {
+    #[validate(length(min = 1, message = "Token is required"))]
+    pub token: String,
+}
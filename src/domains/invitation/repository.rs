@@ -0,0 +1,141 @@
+use chrono::{DateTime, Utc};
+use sqlx::{PgConnection, PgExecutor, PgPool};
+use tracing::instrument;
+use uuid::Uuid;
+use crate::domains::invitation::entity::{Invitation, PendingInvitation};
+use crate::domains::organization::entity::OrgRole;
+use crate::utils::extractors::TenantScope;
+
+/// Invites `email` on behalf of the caller, replacing any pending invitation
+/// it already has. Returns the invitation and whether one was replaced.
+#[instrument(name = "db.replace_invitation", skip_all, fields(db.system = "postgresql", db.operation = "INSERT"))]
+pub async fn replace_invitation(
+    conn: &mut PgConnection,
+    scope: &TenantScope,
+    email: &str,
+    role: OrgRole,
+    token_hash: &str,
+    expires_at: DateTime<Utc>,
+) -> Result<(Invitation, bool), String> {
+    let replaced = sqlx::query!(
+        "DELETE FROM org_invitations WHERE org_id = $1 AND email = $2 AND accepted_at IS NULL",
+        scope.org_id(),
+        email
+    )
+    .execute(&mut *conn)
+    .await
+    .map(|result| result.rows_affected() > 0)
+    .map_err(|e| format!("Database error: {}", e))?;
+
+    let invitation = sqlx::query_as!(
+        Invitation,
+        r#"
+        INSERT INTO org_invitations (org_id, email, role, token_hash, invited_by, expires_at)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        RETURNING id, email, role, invited_by, expires_at, created_at
+        "#,
+        scope.org_id(),
+        email,
+        role.as_str(),
+        token_hash,
+        scope.user().id,
+        expires_at
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|e| format!("Database error: {}", e))?;
+
+    Ok((invitation, replaced))
+}
+
+/// Invitations not yet accepted, expired ones included, newest first.
+#[instrument(name = "db.list_invitations", skip_all, fields(db.system = "postgresql", db.operation = "SELECT"))]
+pub async fn list_invitations(pool: &PgPool, scope: &TenantScope) -> Result<Vec<Invitation>, String> {
+    sqlx::query_as!(
+        Invitation,
+        r#"
+        SELECT id, email, role, invited_by, expires_at, created_at
+        FROM org_invitations
+        WHERE org_id = $1 AND accepted_at IS NULL
+        ORDER BY created_at DESC, id
+        "#,
+        scope.org_id()
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+/// Deletes a pending invitation; returns whether there was one.
+#[instrument(name = "db.delete_invitation", skip_all, fields(db.system = "postgresql", db.operation = "DELETE"))]
+pub async fn delete_invitation(pool: &PgPool, scope: &TenantScope, id: &Uuid) -> Result<bool, String> {
+    sqlx::query!(
+        "DELETE FROM org_invitations WHERE id = $1 AND org_id = $2 AND accepted_at IS NULL",
+        id,
+        scope.org_id()
+    )
+    .execute(pool)
+    .await
+    .map(|result| result.rows_affected() > 0)
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+#[instrument(name = "db.is_member_email", skip_all, fields(db.system = "postgresql", db.operation = "SELECT"))]
+pub async fn is_member_email(pool: &PgPool, scope: &TenantScope, email: &str) -> Result<bool, String> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT EXISTS (
+            SELECT 1
+            FROM memberships m
+            JOIN users u ON u.id = m.user_id
+            WHERE m.org_id = $1 AND LOWER(u.email) = $2 AND u.deleted_at IS NULL
+        ) AS "exists!"
+        "#,
+        scope.org_id(),
+        email
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+/// An unexpired invitation that has not been accepted yet.
+#[instrument(name = "db.find_pending_invitation", skip_all, fields(db.system = "postgresql", db.operation = "SELECT"))]
+pub async fn find_pending_invitation(pool: &PgPool, token_hash: &str) -> Result<Option<PendingInvitation>, String> {
+    sqlx::query_as!(
+        PendingInvitation,
+        r#"
+        SELECT org_id, email, role
+        FROM org_invitations
+        WHERE token_hash = $1 AND accepted_at IS NULL AND expires_at > NOW()
+        "#,
+        token_hash
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+/// Marks an unexpired invitation as accepted by `user_id` and returns it, if
+/// it was still pending.
+#[instrument(name = "db.claim_invitation", skip_all, fields(db.system = "postgresql", db.operation = "UPDATE"))]
+pub async fn claim_invitation(
+    executor: impl PgExecutor<'_>,
+    token_hash: &str,
+    user_id: &Uuid,
+) -> Result<Option<PendingInvitation>, String> {
+    sqlx::query_as!(
+        PendingInvitation,
+        r#"
+        UPDATE org_invitations
+        SET accepted_at = NOW(), accepted_by = $2
+        WHERE token_hash = $1 AND accepted_at IS NULL AND expires_at > NOW()
+        RETURNING org_id, email, role
+        "#,
+        token_hash,
+        user_id
+    )
+    .fetch_optional(executor)
+    .await
+    .map_err(|e| format!("Database error: {}", e))
+}
@@ -0,0 +1,10 @@
+use actix_web::web;
+use super::controller;
+
+/// Mounted inside the `/orgs` scope, which provides authentication.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(controller::handle_accept_invitation)
+        .service(controller::handle_create_invitation)
+        .service(controller::handle_list_invitations)
+        .service(controller::handle_revoke_invitation);
+}
@@ -0,0 +1,131 @@
+use chrono::Utc;
+use log::{info, warn};
+use sqlx::{PgConnection, PgPool};
+use tracing::instrument;
+use uuid::Uuid;
+use crate::config;
+use crate::domains::invitation::entity::{Invitation, InvitationRequest, JoinedOrganization};
+use crate::domains::invitation::repository::{
+    claim_invitation, delete_invitation, is_member_email, list_invitations as find_invitations, replace_invitation,
+};
+use crate::domains::organization::entity::OrgRole;
+use crate::domains::organization::repository::{find_organization, insert_member};
+use crate::domains::user::repository::{find_user_by_id, mark_email_verified};
+use crate::utils::auth;
+use crate::utils::error::AppError;
+use crate::utils::extractors::{AuthUser, TenantScope};
+use crate::utils::mailer::{self, Email};
+
+fn database_error(e: String) -> AppError {
+    AppError::internal(format!("Database error: {}", e))
+}
+
+/// Invites `request.email` to the organization and emails them a link.
+/// Admins can invite members and admins; inviting an owner takes an owner.
+/// A pending invitation to the same email is replaced, so its link stops
+/// working.
+#[instrument(name = "invitation.create_invitation", skip_all, fields(org.id = %scope.org_id()))]
+pub async fn create_invitation(
+    pool: &PgPool,
+    scope: &TenantScope,
+    request: &InvitationRequest,
+) -> Result<Invitation, AppError> {
+    let role = request.role.unwrap_or(OrgRole::Member);
+    scope.require_role(OrgRole::Admin)?;
+    if role == OrgRole::Owner {
+        scope.require_role(OrgRole::Owner)?;
+    }
+
+    let email = request.email.trim().to_lowercase();
+    if is_member_email(pool, scope, &email).await.map_err(database_error)? {
+        return Err(AppError::validation("This email already belongs to a member"));
+    }
+    let organization = find_organization(pool, scope).await
+        .map_err(database_error)?
+        .ok_or_else(|| AppError::not_found(format!("Organization not found for ID: {}", scope.org_id())))?;
+
+    let (token, token_hash) = auth::generate_secret_token();
+    let ttl_days = request.expires_in_days.unwrap_or_else(config::get_org_invitation_ttl_days);
+    let expires_at = Utc::now() + chrono::Duration::days(ttl_days);
+
+    let mut tx = pool.begin().await.map_err(AppError::DatabaseError)?;
+    let (invitation, replaced) = replace_invitation(&mut tx, scope, &email, role, &token_hash, expires_at).await
+        .map_err(database_error)?;
+    tx.commit().await.map_err(AppError::DatabaseError)?;
+
+    mailer::send_in_background(Email::new(
+        &email,
+        &format!("You are invited to join {}", organization.name),
+        format!(
+            "You have been invited to join {} with the {} role. Use this link to accept (valid for {} days):\n{}?token={}\n\n\
+             If you do not have an account yet, you can create one from the link.",
+            organization.name,
+            role.as_str(),
+            ttl_days,
+            config::get_org_invitation_url(),
+            token
+        ),
+    ));
+    if replaced {
+        info!("Replaced the pending invitation to organization {} for {}", scope.org_id(), email);
+    }
+    info!("User {} invited {} to organization {} as {}", scope.user().id, email, scope.org_id(), role.as_str());
+    Ok(invitation)
+}
+
+#[instrument(name = "invitation.list_invitations", skip_all, fields(org.id = %scope.org_id()))]
+pub async fn list_invitations(pool: &PgPool, scope: &TenantScope) -> Result<Vec<Invitation>, AppError> {
+    scope.require_role(OrgRole::Admin)?;
+    find_invitations(pool, scope).await.map_err(database_error)
+}
+
+#[instrument(name = "invitation.revoke_invitation", skip(pool, scope), fields(org.id = %scope.org_id()))]
+pub async fn revoke_invitation(pool: &PgPool, scope: &TenantScope, id: &Uuid) -> Result<(), AppError> {
+    scope.require_role(OrgRole::Admin)?;
+    if !delete_invitation(pool, scope, id).await.map_err(database_error)? {
+        return Err(AppError::not_found(format!("Invitation not found for ID: {}", id)));
+    }
+
+    info!("User {} revoked invitation {} to organization {}", scope.user().id, id, scope.org_id());
+    Ok(())
+}
+
+/// Accepts an invitation for a signed-in user, whose email must be the one
+/// it was sent to.
+#[instrument(name = "invitation.accept_invitation", skip_all, fields(user.id = %user.id))]
+pub async fn accept_invitation(pool: &PgPool, user: &AuthUser, token: &str) -> Result<JoinedOrganization, AppError> {
+    let account = find_user_by_id(pool, &user.id).await
+        .map_err(database_error)?
+        .ok_or_else(|| AppError::not_found(format!("User not found for ID: {}", user.id)))?;
+
+    let mut tx = pool.begin().await.map_err(AppError::DatabaseError)?;
+    let joined = redeem(&mut tx, token, &account.id, &account.email).await?;
+    tx.commit().await.map_err(AppError::DatabaseError)?;
+    Ok(joined)
+}
+
+/// Uses up an invitation in the caller's transaction: adds `user_id` to the
+/// organization and, since the token was emailed to them, marks their email
+/// as verified. Fails if the invitation is not pending or was sent to
+/// another email; the caller then rolls back.
+pub async fn redeem(
+    conn: &mut PgConnection,
+    token: &str,
+    user_id: &Uuid,
+    email: &str,
+) -> Result<JoinedOrganization, AppError> {
+    let invitation = claim_invitation(&mut *conn, &auth::hash_secret_token(token), user_id).await
+        .map_err(database_error)?
+        .ok_or_else(|| AppError::validation("Invalid or expired invitation"))?;
+    if !invitation.email.eq_ignore_ascii_case(email) {
+        warn!("User {} tried to accept an invitation to organization {} sent to another email", user_id, invitation.org_id);
+        return Err(AppError::forbidden("This invitation was sent to another email address"));
+    }
+
+    let role = insert_member(&mut *conn, &invitation.org_id, user_id, &invitation.role).await
+        .map_err(database_error)?;
+    mark_email_verified(&mut *conn, user_id, email).await.map_err(database_error)?;
+
+    info!("User {} joined organization {} as {}", user_id, invitation.org_id, role);
+    Ok(JoinedOrganization { org_id: invitation.org_id, role })
+}
@@ -1,4 +1,13 @@
 pub mod user;
 pub mod auth;
+pub mod admin;
+pub mod api_key;
+pub mod audit;
 pub mod health;
-pub mod root;
\ No newline at end of file
+pub mod identity;
+pub mod invitation;
+pub mod organization;
+pub mod root;
+pub mod session;
+pub mod well_known;
+pub mod webauthn;
\ No newline at end of file
@@ -0,0 +1,241 @@
+use actix_web::{delete, get, post, put, web, HttpResponse};
+use log::{error, warn};
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+use validator::Validate;
+use crate::domains::organization::entity::{OrganizationRequest, UpdateMemberRequest};
+use crate::domains::organization::service::{
+    change_member_role, create_organization, delete_organization, get_organization, list_members,
+    issue_tenant_token, list_organizations, remove_member, update_organization,
+};
+use crate::utils::auth::SCOPE_ORGS_READ;
+use crate::utils::error::{field_errors, AppError};
+use crate::utils::extractors::{AuthUser, RequestContext, TenantScope};
+use crate::utils::response::{Response, ResponseBuilder};
+
+#[post("")]
+pub async fn handle_create_organization(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+    req: web::Json<OrganizationRequest>,
+) -> Result<HttpResponse, AppError> {
+    user.require_full_access()?;
+    if let Err(errors) = req.validate() {
+        warn!("Validation failed: {:?}", errors);
+        return Ok(Response::bad_request_with_data("Validation failed", field_errors(&errors)));
+    }
+
+    match create_organization(pool.get_ref(), &user.id, &req.name).await {
+        Ok(organization) => Ok(Response::created(organization)),
+        Err(AppError::ValidationError(e)) => {
+            warn!("Validation error: {}", e);
+            Ok(Response::bad_request(&e))
+        },
+        Err(e) => {
+            error!("Unexpected error while creating organization: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
+
+#[get("")]
+pub async fn handle_list_organizations(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    user.require_scope(SCOPE_ORGS_READ)?;
+
+    match list_organizations(pool.get_ref(), &user.id).await {
+        Ok(organizations) => Ok(Response::ok(organizations)),
+        Err(e) => {
+            error!("Unexpected error while listing organizations: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
+
+/// The organization selected by the token's tenant or the `X-Org-Id` header.
+#[get("/current")]
+pub async fn handle_current_organization(
+    scope: TenantScope,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    fetch_organization(pool.get_ref(), &scope).await
+}
+
+#[get("/{org_id}")]
+pub async fn handle_get_organization(
+    scope: TenantScope,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    fetch_organization(pool.get_ref(), &scope).await
+}
+
+async fn fetch_organization(pool: &PgPool, scope: &TenantScope) -> Result<HttpResponse, AppError> {
+    scope.user().require_scope(SCOPE_ORGS_READ)?;
+
+    match get_organization(pool, scope).await {
+        Ok(organization) => Ok(Response::ok(organization)),
+        Err(AppError::NotFoundError(e)) => {
+            warn!("Organization not found: {}", e);
+            Ok(Response::not_found(&e))
+        },
+        Err(e) => {
+            error!("Unexpected error while fetching organization: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
+
+#[put("/{org_id}")]
+pub async fn handle_update_organization(
+    scope: TenantScope,
+    pool: web::Data<PgPool>,
+    req: web::Json<OrganizationRequest>,
+) -> Result<HttpResponse, AppError> {
+    scope.user().require_full_access()?;
+    if let Err(errors) = req.validate() {
+        warn!("Validation failed: {:?}", errors);
+        return Ok(Response::bad_request_with_data("Validation failed", field_errors(&errors)));
+    }
+
+    match update_organization(pool.get_ref(), &scope, &req.name).await {
+        Ok(organization) => Ok(Response::ok(organization)),
+        Err(AppError::ForbiddenError(e)) => Ok(Response::forbidden(&e)),
+        Err(AppError::ValidationError(e)) => {
+            warn!("Validation error: {}", e);
+            Ok(Response::bad_request(&e))
+        },
+        Err(AppError::NotFoundError(e)) => {
+            warn!("Organization not found: {}", e);
+            Ok(Response::not_found(&e))
+        },
+        Err(e) => {
+            error!("Unexpected error while updating organization: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
+
+#[delete("/{org_id}")]
+pub async fn handle_delete_organization(
+    scope: TenantScope,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    scope.user().require_full_access()?;
+
+    match delete_organization(pool.get_ref(), &scope).await {
+        Ok(()) => Ok(Response::ok(json!({ "message": "Organization deleted" }))),
+        Err(AppError::ForbiddenError(e)) => Ok(Response::forbidden(&e)),
+        Err(AppError::NotFoundError(e)) => {
+            warn!("Organization not found: {}", e);
+            Ok(Response::not_found(&e))
+        },
+        Err(e) => {
+            error!("Unexpected error while deleting organization: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
+
+/// Exchanges the caller's token for one bound to the organization, whose
+/// `tenant` claim selects it without the `X-Org-Id` header.
+#[post("/{org_id}/token")]
+pub async fn handle_switch_organization(
+    scope: TenantScope,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    scope.user().require_full_access()?;
+
+    match issue_tenant_token(pool.get_ref(), &scope).await {
+        Ok((token, expires_at)) => Ok(Response::ok(json!({
+            "token": token,
+            "organization_id": scope.org_id(),
+            "expires_at": expires_at,
+        }))),
+        Err(AppError::ForbiddenError(e)) => Ok(Response::forbidden(&e)),
+        Err(AppError::AuthenticationError(e)) => {
+            warn!("Authentication error: {}", e);
+            Ok(Response::unauthorized(&e))
+        },
+        Err(e) => {
+            error!("Unexpected error while switching organization: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
+
+#[get("/{org_id}/members")]
+pub async fn handle_list_members(
+    scope: TenantScope,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    scope.user().require_scope(SCOPE_ORGS_READ)?;
+
+    match list_members(pool.get_ref(), &scope).await {
+        Ok(members) => Ok(Response::ok(members)),
+        Err(e) => {
+            error!("Unexpected error while listing members: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
+
+#[put("/{org_id}/members/{user_id}")]
+pub async fn handle_update_member(
+    scope: TenantScope,
+    pool: web::Data<PgPool>,
+    path: web::Path<(Uuid, Uuid)>,
+    req: web::Json<UpdateMemberRequest>,
+    context: RequestContext,
+) -> Result<HttpResponse, AppError> {
+    scope.user().require_full_access()?;
+    let (_, user_id) = path.into_inner();
+
+    match change_member_role(pool.get_ref(), &scope, &user_id, req.role, &context).await {
+        Ok(()) => Ok(Response::ok(json!({ "message": "Member role updated", "role": req.role }))),
+        Err(AppError::ForbiddenError(e)) => Ok(Response::forbidden(&e)),
+        Err(AppError::ValidationError(e)) => {
+            warn!("Validation error: {}", e);
+            Ok(Response::bad_request(&e))
+        },
+        Err(AppError::NotFoundError(e)) => {
+            warn!("Member not found: {}", e);
+            Ok(Response::not_found(&e))
+        },
+        Err(e) => {
+            error!("Unexpected error while updating member: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
+
+/// Removes a member, or leaves the organization when the ID is the caller's.
+#[delete("/{org_id}/members/{user_id}")]
+pub async fn handle_remove_member(
+    scope: TenantScope,
+    pool: web::Data<PgPool>,
+    path: web::Path<(Uuid, Uuid)>,
+    context: RequestContext,
+) -> Result<HttpResponse, AppError> {
+    scope.user().require_full_access()?;
+    let (_, user_id) = path.into_inner();
+
+    match remove_member(pool.get_ref(), &scope, &user_id, &context).await {
+        Ok(()) => Ok(Response::ok(json!({ "message": "Member removed" }))),
+        Err(AppError::ForbiddenError(e)) => Ok(Response::forbidden(&e)),
+        Err(AppError::ValidationError(e)) => {
+            warn!("Validation error: {}", e);
+            Ok(Response::bad_request(&e))
+        },
+        Err(AppError::NotFoundError(e)) => {
+            warn!("Member not found: {}", e);
+            Ok(Response::not_found(&e))
+        },
+        Err(e) => {
+            error!("Unexpected error while removing member: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
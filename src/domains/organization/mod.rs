@@ -0,0 +1,5 @@
+pub mod controller;
+pub mod entity;
+pub mod repository;
+pub mod route;
+pub mod service;
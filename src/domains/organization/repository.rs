@@ -0,0 +1,234 @@
+use sqlx::{PgConnection, PgExecutor, PgPool};
+use uuid::Uuid;
+use tracing::instrument;
+use crate::domains::organization::entity::{Member, OrgRole, Organization, OrganizationResponse};
+use crate::utils::extractors::TenantScope;
+
+/// Creates an organization with `owner_id` as its first owner.
+#[instrument(name = "db.create_organization", skip_all, fields(db.system = "postgresql", db.operation = "INSERT"))]
+pub async fn create_organization(conn: &mut PgConnection, name: &str, owner_id: &Uuid) -> Result<Organization, String> {
+    let organization = sqlx::query_as!(
+        Organization,
+        r#"
+        INSERT INTO organizations (name)
+        VALUES ($1)
+        RETURNING id, name, created_at, updated_at
+        "#,
+        name
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .map_err(|e| format!("Database error: {}", e))?;
+
+    sqlx::query!(
+        "INSERT INTO memberships (org_id, user_id, role) VALUES ($1, $2, $3)",
+        organization.id,
+        owner_id,
+        OrgRole::Owner.as_str()
+    )
+    .execute(&mut *conn)
+    .await
+    .map_err(|e| format!("Database error: {}", e))?;
+
+    Ok(organization)
+}
+
+/// Organizations the user belongs to, with their role in each.
+#[instrument(name = "db.list_user_organizations", skip_all, fields(db.system = "postgresql", db.operation = "SELECT"))]
+pub async fn list_user_organizations(pool: &PgPool, user_id: &Uuid) -> Result<Vec<OrganizationResponse>, String> {
+    let rows = sqlx::query!(
+        r#"
+        SELECT o.id, o.name, o.created_at, o.updated_at, m.role
+        FROM organizations o
+        JOIN memberships m ON m.org_id = o.id
+        WHERE m.user_id = $1
+        ORDER BY o.name, o.id
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("Database error: {}", e))?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| OrganizationResponse {
+            organization: Organization {
+                id: row.id,
+                name: row.name,
+                created_at: row.created_at,
+                updated_at: row.updated_at,
+            },
+            role: row.role,
+        })
+        .collect())
+}
+
+/// The user's role in an organization, if they are a member. This is the
+/// check behind every [`TenantScope`].
+#[instrument(name = "db.find_membership_role", skip_all, fields(db.system = "postgresql", db.operation = "SELECT"))]
+pub async fn find_membership_role(pool: &PgPool, org_id: &Uuid, user_id: &Uuid) -> Result<Option<String>, String> {
+    sqlx::query_scalar!(
+        "SELECT role FROM memberships WHERE org_id = $1 AND user_id = $2",
+        org_id,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+#[instrument(name = "db.find_organization", skip_all, fields(db.system = "postgresql", db.operation = "SELECT"))]
+pub async fn find_organization(pool: &PgPool, scope: &TenantScope) -> Result<Option<Organization>, String> {
+    sqlx::query_as!(
+        Organization,
+        "SELECT id, name, created_at, updated_at FROM organizations WHERE id = $1",
+        scope.org_id()
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+#[instrument(name = "db.update_organization", skip_all, fields(db.system = "postgresql", db.operation = "UPDATE"))]
+pub async fn update_organization(pool: &PgPool, scope: &TenantScope, name: &str) -> Result<Option<Organization>, String> {
+    sqlx::query_as!(
+        Organization,
+        r#"
+        UPDATE organizations
+        SET name = $2
+        WHERE id = $1
+        RETURNING id, name, created_at, updated_at
+        "#,
+        scope.org_id(),
+        name
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+/// Deletes the organization and its memberships; returns whether it existed.
+#[instrument(name = "db.delete_organization", skip_all, fields(db.system = "postgresql", db.operation = "DELETE"))]
+pub async fn delete_organization(pool: &PgPool, scope: &TenantScope) -> Result<bool, String> {
+    sqlx::query!("DELETE FROM organizations WHERE id = $1", scope.org_id())
+        .execute(pool)
+        .await
+        .map(|result| result.rows_affected() > 0)
+        .map_err(|e| format!("Database error: {}", e))
+}
+
+/// Locks the organization until the transaction ends, so membership
+/// changes that must keep an owner are applied one at a time.
+#[instrument(name = "db.lock_organization", skip_all, fields(db.system = "postgresql", db.operation = "SELECT"))]
+pub async fn lock_organization(conn: &mut PgConnection, scope: &TenantScope) -> Result<bool, String> {
+    sqlx::query_scalar!("SELECT id FROM organizations WHERE id = $1 FOR UPDATE", scope.org_id())
+        .fetch_optional(conn)
+        .await
+        .map(|id| id.is_some())
+        .map_err(|e| format!("Database error: {}", e))
+}
+
+/// Members of the organization, owners first.
+#[instrument(name = "db.list_members", skip_all, fields(db.system = "postgresql", db.operation = "SELECT"))]
+pub async fn list_members(pool: &PgPool, scope: &TenantScope) -> Result<Vec<Member>, String> {
+    sqlx::query_as!(
+        Member,
+        r#"
+        SELECT m.user_id, u.email, u.name, m.role, m.created_at AS joined_at
+        FROM memberships m
+        JOIN users u ON u.id = m.user_id
+        WHERE m.org_id = $1 AND u.deleted_at IS NULL
+        ORDER BY CASE m.role WHEN 'owner' THEN 0 WHEN 'admin' THEN 1 ELSE 2 END, u.email
+        "#,
+        scope.org_id()
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+#[instrument(name = "db.find_member_role", skip_all, fields(db.system = "postgresql", db.operation = "SELECT"))]
+pub async fn find_member_role(
+    executor: impl PgExecutor<'_>,
+    scope: &TenantScope,
+    user_id: &Uuid,
+) -> Result<Option<String>, String> {
+    sqlx::query_scalar!(
+        "SELECT role FROM memberships WHERE org_id = $1 AND user_id = $2",
+        scope.org_id(),
+        user_id
+    )
+    .fetch_optional(executor)
+    .await
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+#[instrument(name = "db.count_owners", skip_all, fields(db.system = "postgresql", db.operation = "SELECT"))]
+pub async fn count_owners(executor: impl PgExecutor<'_>, scope: &TenantScope) -> Result<i64, String> {
+    sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "count!" FROM memberships WHERE org_id = $1 AND role = 'owner'"#,
+        scope.org_id()
+    )
+    .fetch_one(executor)
+    .await
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+#[instrument(name = "db.update_member_role", skip_all, fields(db.system = "postgresql", db.operation = "UPDATE"))]
+pub async fn update_member_role(
+    executor: impl PgExecutor<'_>,
+    scope: &TenantScope,
+    user_id: &Uuid,
+    role: OrgRole,
+) -> Result<(), String> {
+    sqlx::query!(
+        "UPDATE memberships SET role = $3 WHERE org_id = $1 AND user_id = $2",
+        scope.org_id(),
+        user_id,
+        role.as_str()
+    )
+    .execute(executor)
+    .await
+    .map(|_| ())
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+#[instrument(name = "db.remove_member", skip_all, fields(db.system = "postgresql", db.operation = "DELETE"))]
+pub async fn remove_member(executor: impl PgExecutor<'_>, scope: &TenantScope, user_id: &Uuid) -> Result<(), String> {
+    sqlx::query!(
+        "DELETE FROM memberships WHERE org_id = $1 AND user_id = $2",
+        scope.org_id(),
+        user_id
+    )
+    .execute(executor)
+    .await
+    .map(|_| ())
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+/// Adds a member, keeping the current role of someone who already is one,
+/// and returns their role. This does not take a [`TenantScope`]: the
+/// invitation being redeemed is what allows the user in.
+#[instrument(name = "db.insert_member", skip_all, fields(db.system = "postgresql", db.operation = "INSERT"))]
+pub async fn insert_member(
+    executor: impl PgExecutor<'_>,
+    org_id: &Uuid,
+    user_id: &Uuid,
+    role: &str,
+) -> Result<String, String> {
+    sqlx::query_scalar!(
+        r#"
+        INSERT INTO memberships (org_id, user_id, role)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (org_id, user_id) DO UPDATE SET role = memberships.role
+        RETURNING role
+        "#,
+        org_id,
+        user_id,
+        role
+    )
+    .fetch_one(executor)
+    .await
+    .map_err(|e| format!("Database error: {}", e))
+}
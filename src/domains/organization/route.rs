@@ -0,0 +1,25 @@
+use actix_web::web;
+use super::controller;
+use crate::domains::invitation::route as invitation_routes;
+use crate::utils::middleware::auth::AuthMiddleware;
+use crate::utils::middleware::rate_limit::RateLimitMiddleware;
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/orgs")
+            .wrap(RateLimitMiddleware::per_user("orgs", 120, 60))
+            .wrap(AuthMiddleware::new())
+            .service(controller::handle_create_organization)
+            .service(controller::handle_list_organizations)
+            // Before "/{org_id}", which would otherwise match it
+            .service(controller::handle_current_organization)
+            .service(controller::handle_get_organization)
+            .service(controller::handle_update_organization)
+            .service(controller::handle_delete_organization)
+            .service(controller::handle_switch_organization)
+            .service(controller::handle_list_members)
+            .service(controller::handle_update_member)
+            .service(controller::handle_remove_member)
+            .configure(invitation_routes::configure)
+    );
+}
@@ -0,0 +1,203 @@
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde_json::json;
+use sqlx::PgPool;
+use tracing::instrument;
+use uuid::Uuid;
+use crate::domains::audit::entity::{NewAuditEvent, ACTION_MEMBER_REMOVE, ACTION_ROLE_CHANGE};
+use crate::domains::audit::service as audit;
+use crate::domains::organization::entity::{Member, OrgRole, Organization, OrganizationResponse};
+use crate::domains::organization::repository::{
+    count_owners, create_organization as insert_organization, delete_organization as remove_organization,
+    find_member_role, find_organization, list_members as find_members, list_user_organizations, lock_organization,
+    remove_member as delete_member, update_member_role, update_organization as rename_organization,
+};
+use crate::domains::session::repository::find_active_session;
+use crate::utils::auth::{self, Claims};
+use crate::utils::error::AppError;
+use crate::utils::extractors::{RequestContext, TenantScope};
+
+fn database_error(e: String) -> AppError {
+    AppError::internal(format!("Database error: {}", e))
+}
+
+fn not_found(scope: &TenantScope) -> AppError {
+    AppError::not_found(format!("Organization not found for ID: {}", scope.org_id()))
+}
+
+/// Creates an organization owned by `user_id`.
+#[instrument(name = "organization.create_organization", skip(pool))]
+pub async fn create_organization(pool: &PgPool, user_id: &Uuid, name: &str) -> Result<OrganizationResponse, AppError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(AppError::validation("Name cannot be empty"));
+    }
+
+    let mut tx = pool.begin().await.map_err(AppError::DatabaseError)?;
+    let organization = insert_organization(&mut tx, name, user_id).await.map_err(database_error)?;
+    tx.commit().await.map_err(AppError::DatabaseError)?;
+
+    info!("User {} created organization {}", user_id, organization.id);
+    Ok(OrganizationResponse { organization, role: OrgRole::Owner.as_str().to_string() })
+}
+
+#[instrument(name = "organization.list_organizations", skip(pool))]
+pub async fn list_organizations(pool: &PgPool, user_id: &Uuid) -> Result<Vec<OrganizationResponse>, AppError> {
+    list_user_organizations(pool, user_id).await.map_err(database_error)
+}
+
+#[instrument(name = "organization.get_organization", skip_all, fields(org.id = %scope.org_id()))]
+pub async fn get_organization(pool: &PgPool, scope: &TenantScope) -> Result<OrganizationResponse, AppError> {
+    let organization = find_organization(pool, scope).await
+        .map_err(database_error)?
+        .ok_or_else(|| not_found(scope))?;
+    Ok(OrganizationResponse { organization, role: scope.role().as_str().to_string() })
+}
+
+/// Renames the organization; admins and owners only.
+#[instrument(name = "organization.update_organization", skip_all, fields(org.id = %scope.org_id()))]
+pub async fn update_organization(pool: &PgPool, scope: &TenantScope, name: &str) -> Result<Organization, AppError> {
+    scope.require_role(OrgRole::Admin)?;
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(AppError::validation("Name cannot be empty"));
+    }
+
+    rename_organization(pool, scope, name).await
+        .map_err(database_error)?
+        .ok_or_else(|| not_found(scope))
+}
+
+/// Deletes the organization and all its memberships; owners only.
+#[instrument(name = "organization.delete_organization", skip_all, fields(org.id = %scope.org_id()))]
+pub async fn delete_organization(pool: &PgPool, scope: &TenantScope) -> Result<(), AppError> {
+    scope.require_role(OrgRole::Owner)?;
+    if !remove_organization(pool, scope).await.map_err(database_error)? {
+        return Err(not_found(scope));
+    }
+
+    info!("User {} deleted organization {}", scope.user().id, scope.org_id());
+    Ok(())
+}
+
+#[instrument(name = "organization.list_members", skip_all, fields(org.id = %scope.org_id()))]
+pub async fn list_members(pool: &PgPool, scope: &TenantScope) -> Result<Vec<Member>, AppError> {
+    find_members(pool, scope).await.map_err(database_error)
+}
+
+/// Changes a member's role. Admins manage admins and members; only owners
+/// can make or unmake owners, and the last owner cannot step down.
+#[instrument(name = "organization.change_member_role", skip(pool, scope, context), fields(org.id = %scope.org_id()))]
+pub async fn change_member_role(
+    pool: &PgPool,
+    scope: &TenantScope,
+    user_id: &Uuid,
+    role: OrgRole,
+    context: &RequestContext,
+) -> Result<(), AppError> {
+    scope.require_role(OrgRole::Admin)?;
+
+    let mut tx = pool.begin().await.map_err(AppError::DatabaseError)?;
+    if !lock_organization(&mut tx, scope).await.map_err(database_error)? {
+        return Err(not_found(scope));
+    }
+    let current = member_role(&mut tx, scope, user_id).await?;
+    if current == role {
+        return Ok(());
+    }
+    if current == OrgRole::Owner || role == OrgRole::Owner {
+        scope.require_role(OrgRole::Owner)?;
+    }
+    if current == OrgRole::Owner {
+        ensure_another_owner(&mut tx, scope).await?;
+    }
+
+    update_member_role(&mut *tx, scope, user_id, role).await.map_err(database_error)?;
+    tx.commit().await.map_err(AppError::DatabaseError)?;
+
+    info!(
+        "User {} changed the role of {} in organization {} from {} to {}",
+        scope.user().id, user_id, scope.org_id(), current.as_str(), role.as_str()
+    );
+    let event = NewAuditEvent::new(ACTION_ROLE_CHANGE, context)
+        .actor(scope.user().id)
+        .subject(*user_id)
+        .details(json!({ "org_id": scope.org_id(), "old_role": current.as_str(), "new_role": role.as_str() }));
+    audit::record_or_log(pool, event).await;
+    Ok(())
+}
+
+/// Removes a member. Anyone can leave; removing others takes an admin, and
+/// removing an owner takes an owner. The last owner cannot leave.
+#[instrument(name = "organization.remove_member", skip(pool, scope, context), fields(org.id = %scope.org_id()))]
+pub async fn remove_member(pool: &PgPool, scope: &TenantScope, user_id: &Uuid, context: &RequestContext) -> Result<(), AppError> {
+    let leaving = *user_id == scope.user().id;
+    if !leaving {
+        scope.require_role(OrgRole::Admin)?;
+    }
+
+    let mut tx = pool.begin().await.map_err(AppError::DatabaseError)?;
+    if !lock_organization(&mut tx, scope).await.map_err(database_error)? {
+        return Err(not_found(scope));
+    }
+    let current = member_role(&mut tx, scope, user_id).await?;
+    if current == OrgRole::Owner {
+        if !leaving {
+            scope.require_role(OrgRole::Owner)?;
+        }
+        ensure_another_owner(&mut tx, scope).await?;
+    }
+
+    delete_member(&mut *tx, scope, user_id).await.map_err(database_error)?;
+    tx.commit().await.map_err(AppError::DatabaseError)?;
+
+    info!("User {} removed {} from organization {}", scope.user().id, user_id, scope.org_id());
+    let event = NewAuditEvent::new(ACTION_MEMBER_REMOVE, context)
+        .actor(scope.user().id)
+        .subject(*user_id)
+        .details(json!({ "org_id": scope.org_id(), "role": current.as_str() }));
+    audit::record_or_log(pool, event).await;
+    Ok(())
+}
+
+/// Issues a token bound to the organization, for the caller's session. It
+/// expires with the session, and signing the session out revokes it too.
+#[instrument(name = "organization.issue_tenant_token", skip(pool))]
+pub async fn issue_tenant_token(pool: &PgPool, scope: &TenantScope) -> Result<(String, DateTime<Utc>), AppError> {
+    let user = scope.user();
+    // Tokens without a session could not be signed out early
+    let session_id = user.session_id
+        .ok_or_else(|| AppError::forbidden("Sign in again to switch organizations"))?;
+    let session = find_active_session(pool, &session_id).await
+        .map_err(database_error)?
+        .ok_or_else(|| AppError::authentication("Session has ended"))?;
+
+    let mut claims = Claims::new(user.id)
+        .with_roles(user.roles.clone())
+        .with_session(session_id)
+        .with_tenant(*scope.org_id());
+    claims.exp = claims.exp.min(session.expires_at.timestamp());
+    let expires_at = DateTime::from_timestamp(claims.exp, 0)
+        .ok_or_else(|| AppError::internal("Token expiry out of range"))?;
+    let token = auth::generate_token(&claims)?;
+
+    info!("User {} switched to organization {}", user.id, scope.org_id());
+    Ok((token, expires_at))
+}
+
+async fn member_role(conn: &mut sqlx::PgConnection, scope: &TenantScope, user_id: &Uuid) -> Result<OrgRole, AppError> {
+    find_member_role(conn, scope, user_id).await
+        .map_err(database_error)?
+        .and_then(|role| OrgRole::parse(&role))
+        .ok_or_else(|| AppError::not_found(format!("Member not found for ID: {}", user_id)))
+}
+
+async fn ensure_another_owner(conn: &mut sqlx::PgConnection, scope: &TenantScope) -> Result<(), AppError> {
+    if count_owners(conn, scope).await.map_err(database_error)? < 2 {
+        warn!("Refused to leave organization {} without an owner", scope.org_id());
+        return Err(AppError::validation(
+            "An organization needs at least one owner; make someone else an owner first",
+        ));
+    }
+    Ok(())
+}
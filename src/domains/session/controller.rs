@@ -0,0 +1,66 @@
+use actix_web::{delete, get, web, HttpResponse};
+use log::{error, warn};
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+use crate::domains::session::service::{list_sessions, revoke_other_sessions, revoke_session};
+use crate::utils::error::AppError;
+use crate::utils::extractors::AuthUser;
+use crate::utils::response::{Response, ResponseBuilder};
+
+#[get("")]
+pub async fn handle_list_sessions(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    user.require_full_access()?;
+
+    match list_sessions(pool.get_ref(), &user).await {
+        Ok(sessions) => Ok(Response::ok(sessions)),
+        Err(e) => {
+            error!("Unexpected error while listing sessions: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
+
+/// Signs out everywhere else.
+#[delete("")]
+pub async fn handle_revoke_other_sessions(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    user.require_full_access()?;
+
+    match revoke_other_sessions(pool.get_ref(), &user).await {
+        Ok(revoked) => Ok(Response::ok(json!({
+            "message": "Signed out of all other sessions",
+            "revoked": revoked
+        }))),
+        Err(e) => {
+            error!("Unexpected error while revoking sessions: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
+
+#[delete("/{id}")]
+pub async fn handle_revoke_session(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    user.require_full_access()?;
+
+    match revoke_session(pool.get_ref(), &user.id, &path.into_inner()).await {
+        Ok(()) => Ok(Response::ok(json!({ "message": "Session revoked" }))),
+        Err(AppError::NotFoundError(e)) => {
+            warn!("Session not found: {}", e);
+            Ok(Response::not_found(&e))
+        },
+        Err(e) => {
+            error!("Unexpected error while revoking session: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
@@ -0,0 +1,25 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+/// A sign-in from one device or browser.
+#[derive(Debug, Serialize)]
+pub struct Session {
+    pub id: Uuid,
+    #[serde(skip_serializing)]
+    pub user_id: Uuid,
+    pub user_agent: Option<String>,
+    pub ip_address: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// A session as listed to its owner.
+#[derive(Serialize)]
+pub struct SessionResponse {
+    #[serde(flatten)]
+    pub session: Session,
+    /// Whether this is the session the listing was requested from.
+    pub current: bool,
+}
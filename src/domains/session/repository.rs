@@ -0,0 +1,119 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+use tracing::instrument;
+use crate::domains::session::entity::Session;
+
+/// Records a new session, first dropping the user's expired and revoked ones.
+#[instrument(name = "db.create_session", skip_all, fields(db.system = "postgresql", db.operation = "INSERT"))]
+pub async fn create_session(
+    pool: &PgPool,
+    user_id: &Uuid,
+    user_agent: Option<&str>,
+    ip_address: Option<&str>,
+    expires_at: DateTime<Utc>,
+) -> Result<Session, String> {
+    sqlx::query!(
+        "DELETE FROM sessions WHERE user_id = $1 AND (expires_at <= NOW() OR revoked_at IS NOT NULL)",
+        user_id
+    )
+    .execute(pool)
+    .await
+    .map_err(|e| format!("Database error: {}", e))?;
+
+    sqlx::query_as!(
+        Session,
+        r#"
+        INSERT INTO sessions (user_id, user_agent, ip_address, expires_at)
+        VALUES ($1, $2, $3, $4)
+        RETURNING id, user_id, user_agent, ip_address, created_at, last_seen_at, expires_at
+        "#,
+        user_id,
+        user_agent,
+        ip_address,
+        expires_at
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+/// Finds an unrevoked, unexpired session.
+#[instrument(name = "db.find_active_session", skip_all, fields(db.system = "postgresql", db.operation = "SELECT"))]
+pub async fn find_active_session(pool: &PgPool, id: &Uuid) -> Result<Option<Session>, String> {
+    sqlx::query_as!(
+        Session,
+        r#"
+        SELECT id, user_id, user_agent, ip_address, created_at, last_seen_at, expires_at
+        FROM sessions
+        WHERE id = $1 AND revoked_at IS NULL AND expires_at > NOW()
+        "#,
+        id
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+/// Active sessions of a user, most recently used first.
+#[instrument(name = "db.list_sessions", skip_all, fields(db.system = "postgresql", db.operation = "SELECT"))]
+pub async fn list_sessions(pool: &PgPool, user_id: &Uuid) -> Result<Vec<Session>, String> {
+    sqlx::query_as!(
+        Session,
+        r#"
+        SELECT id, user_id, user_agent, ip_address, created_at, last_seen_at, expires_at
+        FROM sessions
+        WHERE user_id = $1 AND revoked_at IS NULL AND expires_at > NOW()
+        ORDER BY last_seen_at DESC
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+#[instrument(name = "db.touch_session", skip_all, fields(db.system = "postgresql", db.operation = "UPDATE"))]
+pub async fn touch_session(pool: &PgPool, id: &Uuid) -> Result<(), String> {
+    sqlx::query!("UPDATE sessions SET last_seen_at = NOW() WHERE id = $1", id)
+        .execute(pool)
+        .await
+        .map(|_| ())
+        .map_err(|e| format!("Database error: {}", e))
+}
+
+/// Revokes one of the user's sessions; returns whether there was one to revoke.
+#[instrument(name = "db.revoke_session", skip_all, fields(db.system = "postgresql", db.operation = "UPDATE"))]
+pub async fn revoke_session(pool: &PgPool, user_id: &Uuid, id: &Uuid) -> Result<bool, String> {
+    sqlx::query!(
+        r#"
+        UPDATE sessions
+        SET revoked_at = NOW()
+        WHERE id = $1 AND user_id = $2 AND revoked_at IS NULL AND expires_at > NOW()
+        "#,
+        id,
+        user_id
+    )
+    .execute(pool)
+    .await
+    .map(|result| result.rows_affected() > 0)
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+/// Revokes every session of the user except `keep`; returns how many were revoked.
+#[instrument(name = "db.revoke_other_sessions", skip_all, fields(db.system = "postgresql", db.operation = "UPDATE"))]
+pub async fn revoke_other_sessions(pool: &PgPool, user_id: &Uuid, keep: Option<&Uuid>) -> Result<u64, String> {
+    sqlx::query!(
+        r#"
+        UPDATE sessions
+        SET revoked_at = NOW()
+        WHERE user_id = $1 AND revoked_at IS NULL AND id IS DISTINCT FROM $2
+        "#,
+        user_id,
+        keep
+    )
+    .execute(pool)
+    .await
+    .map(|result| result.rows_affected())
+    .map_err(|e| format!("Database error: {}", e))
+}
@@ -0,0 +1,12 @@
+use actix_web::web;
+use super::controller;
+
+/// Mounted inside the `/users` scope, which provides authentication.
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/sessions")
+            .service(controller::handle_list_sessions)
+            .service(controller::handle_revoke_other_sessions)
+            .service(controller::handle_revoke_session)
+    );
+}
@@ -0,0 +1,97 @@
+use chrono::{DateTime, Duration, Utc};
+use log::{info, warn};
+use sqlx::PgPool;
+use tracing::instrument;
+use uuid::Uuid;
+use crate::config;
+use crate::domains::session::entity::SessionResponse;
+use crate::domains::session::repository::{
+    create_session, find_active_session, list_sessions as find_sessions, revoke_other_sessions as mark_other_sessions_revoked,
+    revoke_session as mark_session_revoked, touch_session,
+};
+use crate::utils::auth::Claims;
+use crate::utils::error::AppError;
+use crate::utils::extractors::{AuthUser, RequestContext};
+
+fn database_error(e: String) -> AppError {
+    AppError::internal(format!("Database error: {}", e))
+}
+
+/// Records a sign-in from the client in `context` and returns the session id
+/// to embed in the token.
+#[instrument(name = "session.start_session", skip(pool, context))]
+pub async fn start_session(
+    pool: &PgPool,
+    user_id: &Uuid,
+    context: &RequestContext,
+    expires_at: DateTime<Utc>,
+) -> Result<Uuid, AppError> {
+    let ip_address = context.ip.map(|ip| ip.to_string());
+    let session = create_session(pool, user_id, context.user_agent.as_deref(), ip_address.as_deref(), expires_at).await
+        .map_err(database_error)?;
+    info!("Started session {} for user {}", session.id, user_id);
+    Ok(session.id)
+}
+
+/// Checks that the session a token was issued for is still active, and
+/// records that it was seen.
+#[instrument(name = "session.authenticate", skip_all, fields(session.id = tracing::field::Empty))]
+pub async fn authenticate(pool: &PgPool, claims: &Claims) -> Result<(), AppError> {
+    let Some(sid) = &claims.sid else {
+        return Ok(());
+    };
+    let id = Uuid::parse_str(sid).map_err(|_| AppError::authentication("Invalid session"))?;
+    tracing::Span::current().record("session.id", tracing::field::display(id));
+
+    // Impersonation tokens belong to the admin's session
+    let owner = claims.act.as_ref().map_or(&claims.sub, |actor| &actor.sub);
+    let session = find_active_session(pool, &id).await
+        .map_err(database_error)?
+        .filter(|session| session.user_id.to_string() == *owner)
+        .ok_or_else(|| AppError::authentication("Session has been signed out"))?;
+
+    // As with API keys, recording every request would turn reads into writes
+    let interval = Duration::seconds(config::get_session_touch_interval_secs());
+    if session.last_seen_at < Utc::now() - interval {
+        if let Err(e) = touch_session(pool, &session.id).await {
+            warn!("Could not record activity of session {}: {}", session.id, e);
+        }
+    }
+    Ok(())
+}
+
+#[instrument(name = "session.list_sessions", skip(pool, user), fields(user.id = %user.id))]
+pub async fn list_sessions(pool: &PgPool, user: &AuthUser) -> Result<Vec<SessionResponse>, AppError> {
+    let sessions = find_sessions(pool, &user.id).await.map_err(database_error)?;
+
+    Ok(sessions
+        .into_iter()
+        .map(|session| SessionResponse {
+            current: user.session_id == Some(session.id),
+            session,
+        })
+        .collect())
+}
+
+/// Signs one of the user's sessions out, which may be the current one.
+#[instrument(name = "session.revoke_session", skip(pool))]
+pub async fn revoke_session(pool: &PgPool, user_id: &Uuid, id: &Uuid) -> Result<(), AppError> {
+    let revoked = mark_session_revoked(pool, user_id, id).await.map_err(database_error)?;
+    if !revoked {
+        return Err(AppError::not_found(format!("Session not found for ID: {}", id)));
+    }
+
+    info!("Revoked session {} of user {}", id, user_id);
+    Ok(())
+}
+
+/// Signs the user out everywhere except the session of this request.
+/// Returns how many sessions were signed out.
+#[instrument(name = "session.revoke_other_sessions", skip(pool, user), fields(user.id = %user.id))]
+pub async fn revoke_other_sessions(pool: &PgPool, user: &AuthUser) -> Result<u64, AppError> {
+    let revoked = mark_other_sessions_revoked(pool, &user.id, user.session_id.as_ref()).await
+        .map_err(database_error)?;
+
+    info!("Revoked {} other sessions of user {}", revoked, user.id);
+    Ok(revoked)
+}
@@ -1,28 +1,24 @@
-use actix_web::{get, put, web, HttpRequest};
+use actix_web::{get, put, web};
 use actix_web::HttpResponse;
 use sqlx::PgPool;
-use crate::utils::auth::Claims;
-use crate::utils::error::AppError;
+use crate::utils::auth::{SCOPE_PROFILE_READ, SCOPE_PROFILE_WRITE};
+use crate::utils::extractors::{AuthUser, RequestContext};
+use crate::utils::error::{field_errors, AppError};
 use crate::utils::response::{Response, ResponseBuilder};
-use crate::domains::user::service::{update_user_profile, get_user_profile};
-use crate::domains::user::entity::UpdateProfileRequest;
-use actix_web::HttpMessage;
+use crate::domains::user::service::{update_user_profile, get_user_profile, change_password, profile_history};
+use crate::domains::user::entity::{UpdateProfileRequest, ChangePasswordRequest, ProfileHistoryQuery};
+use serde_json::json;
 use log::{error, warn};
+use validator::Validate;
 
 #[get("/profile")]
 pub async fn handle_get_profile(
-    req: HttpRequest,
+    user: AuthUser,
     pool: web::Data<PgPool>,
 ) -> Result<HttpResponse, AppError> {
-    let extensions = req.extensions();
-    let claims = extensions
-        .get::<Claims>()
-        .ok_or_else(|| {
-            error!("Failed to get user claims from request");
-            AppError::AuthenticationError("Session expired or invalid".to_string())
-        })?;
+    user.require_scope(SCOPE_PROFILE_READ)?;
 
-    match get_user_profile(pool.get_ref(), &claims.sub).await {
+    match get_user_profile(pool.get_ref(), &user.id).await {
         Ok(profile) => Ok(Response::ok(profile)),
         Err(AppError::ValidationError(e)) => {
             warn!("Validation error: {}", e);
@@ -49,19 +45,19 @@ pub async fn handle_get_profile(
 
 #[put("/profile")]
 pub async fn handle_update_profile(
-    req: HttpRequest,
+    user: AuthUser,
     pool: web::Data<PgPool>,
+    context: RequestContext,
     update_data: web::Json<UpdateProfileRequest>,
 ) -> Result<HttpResponse, AppError> {
-    let extensions = req.extensions();
-    let claims = extensions
-        .get::<Claims>()
-        .ok_or_else(|| {
-            error!("Failed to get user claims from request");
-            AppError::AuthenticationError("Session expired or invalid".to_string())
-        })?;
+    user.require_scope(SCOPE_PROFILE_WRITE)?;
+    // `profile:write` covers the plain profile fields; changing the sign-in
+    // email or deleting the account is for the user themselves.
+    if update_data.email.is_some() || update_data.deleted_at.is_some() {
+        user.require_full_access()?;
+    }
 
-    match update_user_profile(pool.get_ref(), &claims.sub, &update_data.0).await {
+    match update_user_profile(pool.get_ref(), &user, &update_data.0, &context).await {
         Ok(profile) => Ok(Response::ok(profile)),
         Err(AppError::ValidationError(e)) => {
             warn!("Validation error: {}", e);
@@ -85,3 +81,113 @@ pub async fn handle_update_profile(
         }
     }
 }
+
+
+#[put("/password")]
+pub async fn handle_change_password(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+    context: RequestContext,
+    request: web::Json<ChangePasswordRequest>,
+) -> Result<HttpResponse, AppError> {
+    user.require_full_access()?;
+
+    match change_password(pool.get_ref(), &user.id, &request, &context).await {
+        Ok(()) => Ok(Response::ok(json!({ "message": "Password changed" }))),
+        Err(e @ AppError::FieldValidationError(_)) => {
+            warn!("Password change rejected: {}", e);
+            Err(e)
+        },
+        Err(AppError::ValidationError(e)) => {
+            warn!("Validation error: {}", e);
+            Ok(Response::bad_request(&e))
+        },
+        Err(AppError::NotFoundError(e)) => {
+            warn!("User not found: {}", e);
+            Ok(Response::not_found(&e))
+        },
+        Err(e @ AppError::ServiceUnavailableError(_)) => Err(e),
+        Err(e) => {
+            error!("Unexpected error while changing password: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
+#[get("/profile/history")]
+pub async fn handle_profile_history(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+    query: web::Query<ProfileHistoryQuery>,
+) -> Result<HttpResponse, AppError> {
+    user.require_scope(SCOPE_PROFILE_READ)?;
+    if let Err(errors) = query.validate() {
+        warn!("Validation failed: {:?}", errors);
+        return Ok(Response::bad_request_with_data("Validation failed", field_errors(&errors)));
+    }
+
+    match profile_history(pool.get_ref(), &user.id, &query).await {
+        Ok(page) => Ok(Response::ok(page)),
+        Err(AppError::ValidationError(e)) => {
+            warn!("Validation error: {}", e);
+            Ok(Response::bad_request(&e))
+        },
+        Err(e) => {
+            error!("Unexpected error while fetching profile history: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::dev::Service as _;
+    use actix_web::http::StatusCode;
+    use actix_web::{test, App, HttpMessage};
+    use serde_json::Value;
+    use uuid::Uuid;
+    use crate::utils::auth::Claims;
+
+    /// Sends `body` to `PUT /profile` as if `AuthMiddleware` had verified
+    /// `claims`. The pool never connects, so only requests rejected before
+    /// reaching the database can be checked.
+    async fn update_profile_with(claims: Claims, body: Value) -> StatusCode {
+        let pool = PgPool::connect_lazy("postgres://localhost/unused").unwrap();
+        let app = test::init_service(
+            App::new()
+                .app_data(web::Data::new(pool))
+                .wrap_fn(move |req, srv| {
+                    req.extensions_mut().insert(claims.clone());
+                    srv.call(req)
+                })
+                .service(handle_update_profile),
+        )
+        .await;
+
+        let req = test::TestRequest::put().uri("/profile").set_json(body).to_request();
+        test::call_service(&app, req).await.status()
+    }
+
+    fn impersonation_claims() -> Claims {
+        Claims::new(Uuid::new_v4()).with_actor(Uuid::new_v4())
+    }
+
+    #[actix_web::test]
+    async fn impersonation_token_cannot_change_email() {
+        let status = update_profile_with(impersonation_claims(), json!({ "email": "new@example.com" })).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[actix_web::test]
+    async fn impersonation_token_cannot_delete_account() {
+        let status = update_profile_with(impersonation_claims(), json!({ "deleted_at": "2026-01-01T00:00:00Z" })).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[actix_web::test]
+    async fn profile_write_scope_cannot_change_email() {
+        let claims = Claims::new(Uuid::new_v4()).with_scopes(vec![SCOPE_PROFILE_WRITE.to_string()]);
+        let status = update_profile_with(claims, json!({ "email": "new@example.com" })).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+}
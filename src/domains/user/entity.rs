@@ -1,6 +1,7 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
+use validator::Validate;
 
 #[derive(Serialize)]
 pub struct User {
@@ -9,7 +10,9 @@ pub struct User {
     pub name: Option<String>,
     pub phone: Option<String>,
     pub address: Option<String>,
-    pub password_hash: String,
+    /// `None` for accounts that only sign in through an identity provider.
+    #[serde(skip_serializing)]
+    pub password_hash: Option<String>,
     pub created_at: DateTime<Utc>,
     pub updated_at: Option<DateTime<Utc>>,
     pub deleted_at: Option<DateTime<Utc>>,
@@ -23,4 +26,37 @@ pub struct UpdateProfileRequest {
     pub address: Option<String>,
     pub updated_at: Option<DateTime<Utc>>,
     pub deleted_at: Option<DateTime<Utc>>,
-}
\ No newline at end of file
+}
+
+#[derive(Deserialize)]
+pub struct ChangePasswordRequest {
+    /// May be omitted by accounts that do not have a password yet.
+    #[serde(default)]
+    pub current_password: String,
+    pub new_password: String,
+}
+/// One update of a user's profile, with the before and after values of the
+/// fields it changed.
+#[derive(Serialize)]
+pub struct ProfileHistoryEntry {
+    pub id: Uuid,
+    pub actor_id: Option<Uuid>,
+    pub changes: serde_json::Value,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct ProfileHistoryQuery {
+    #[validate(range(min = 1, message = "Page must be at least 1"))]
+    pub page: Option<i64>,
+    #[validate(range(min = 1, max = 100, message = "Per page must be 1 to 100"))]
+    pub per_page: Option<i64>,
+}
+
+#[derive(Serialize)]
+pub struct ProfileHistoryPage {
+    pub entries: Vec<ProfileHistoryEntry>,
+    pub page: i64,
+    pub per_page: i64,
+    pub total: i64,
+}
@@ -1,8 +1,12 @@
-use sqlx::PgPool;
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use sqlx::{PgConnection, PgExecutor, PgPool};
 use uuid::Uuid;
-use crate::domains::user::entity::User;
+use tracing::instrument;
+use crate::domains::user::entity::{ProfileHistoryEntry, User};
 
-pub async fn create_user(pool: &PgPool, user: &User) -> Result<User, String> {
+#[instrument(name = "db.create_user", skip_all, fields(db.system = "postgresql", db.operation = "INSERT"))]
+pub async fn create_user(executor: impl PgExecutor<'_>, user: &User) -> Result<User, String> {
     sqlx::query_as!(
         User,
         r#"
@@ -20,11 +24,12 @@ pub async fn create_user(pool: &PgPool, user: &User) -> Result<User, String> {
         user.updated_at,
         user.deleted_at
     )
-    .fetch_one(pool)
+    .fetch_one(executor)
     .await
     .map_err(|e| format!("Database error: {}", e))
 }
 
+#[instrument(name = "db.find_user_by_email", skip_all, fields(db.system = "postgresql", db.operation = "SELECT"))]
 pub async fn find_user_by_email(pool: &PgPool, email: &str) -> Result<Option<User>, String> {
     sqlx::query_as!(
         User,
@@ -40,6 +45,7 @@ pub async fn find_user_by_email(pool: &PgPool, email: &str) -> Result<Option<Use
     .map_err(|e| format!("Database error: {}", e))
 }
 
+#[instrument(name = "db.find_user_by_id", skip_all, fields(db.system = "postgresql", db.operation = "SELECT"))]
 pub async fn find_user_by_id(pool: &PgPool, id: &Uuid) -> Result<Option<User>, String> {
     sqlx::query_as!(
         User,
@@ -55,7 +61,8 @@ pub async fn find_user_by_id(pool: &PgPool, id: &Uuid) -> Result<Option<User>, S
     .map_err(|e| format!("Database error: {}", e))
 }
 
-pub async fn update_user(pool: &PgPool, user: &User) -> Result<User, String> {
+#[instrument(name = "db.update_user", skip_all, fields(db.system = "postgresql", db.operation = "UPDATE"))]
+pub async fn update_user(executor: impl PgExecutor<'_>, user: &User) -> Result<User, String> {
     sqlx::query_as!(
         User,
         r#"
@@ -67,7 +74,9 @@ pub async fn update_user(pool: &PgPool, user: &User) -> Result<User, String> {
             address = $4,
             password_hash = $5,
             updated_at = $6,
-            deleted_at = $7
+            deleted_at = $7,
+            -- A new address has not been verified
+            email_verified_at = CASE WHEN email = $1::VARCHAR THEN email_verified_at END
         WHERE id = $8 AND deleted_at IS NULL
         RETURNING id, email, name, phone, address, password_hash, created_at, updated_at, deleted_at
         "#,
@@ -80,7 +89,149 @@ pub async fn update_user(pool: &PgPool, user: &User) -> Result<User, String> {
         user.deleted_at,
         user.id
     )
-    .fetch_one(pool)
+    .fetch_one(executor)
+    .await
+    .map_err(|e| format!("Database error: {}", e))
+}
+/// Reads a user and locks the row until the transaction ends, so it cannot
+/// change between being read and updated.
+#[instrument(name = "db.lock_user_by_id", skip_all, fields(db.system = "postgresql", db.operation = "SELECT"))]
+pub async fn lock_user_by_id(conn: &mut PgConnection, id: &Uuid) -> Result<Option<User>, String> {
+    sqlx::query_as!(
+        User,
+        r#"
+        SELECT id, email, name, phone, address, password_hash, created_at, updated_at, deleted_at
+        FROM users
+        WHERE id = $1 AND deleted_at IS NULL
+        FOR UPDATE
+        "#,
+        id
+    )
+    .fetch_optional(conn)
     .await
     .map_err(|e| format!("Database error: {}", e))
-}
\ No newline at end of file
+}
+
+#[instrument(name = "db.find_user_role", skip_all, fields(db.system = "postgresql", db.operation = "SELECT"))]
+pub async fn find_user_role(pool: &PgPool, id: &Uuid) -> Result<Option<String>, String> {
+    sqlx::query_scalar!(
+        r#"
+        SELECT role
+        FROM users
+        WHERE id = $1 AND deleted_at IS NULL
+        "#,
+        id
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+/// Replaces the password hash only if it is still `current_hash` (`None` for
+/// an account without a password), so a password changed concurrently is
+/// never overwritten. Returns whether it was.
+#[instrument(name = "db.replace_password_hash", skip_all, fields(db.system = "postgresql", db.operation = "UPDATE"))]
+pub async fn replace_password_hash(
+    pool: &PgPool,
+    id: &Uuid,
+    current_hash: Option<&str>,
+    new_hash: &str,
+) -> Result<bool, String> {
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET password_hash = $3
+        WHERE id = $1 AND password_hash IS NOT DISTINCT FROM $2
+        "#,
+        id,
+        current_hash,
+        new_hash
+    )
+    .execute(pool)
+    .await
+    .map(|result| result.rows_affected() > 0)
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+/// Marks the user's email as verified, provided it is still `email`.
+#[instrument(name = "db.mark_email_verified", skip_all, fields(db.system = "postgresql", db.operation = "UPDATE"))]
+pub async fn mark_email_verified(executor: impl PgExecutor<'_>, id: &Uuid, email: &str) -> Result<(), String> {
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET email_verified_at = NOW()
+        WHERE id = $1 AND LOWER(email) = LOWER($2) AND email_verified_at IS NULL
+        "#,
+        id,
+        email
+    )
+    .execute(executor)
+    .await
+    .map(|_| ())
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+#[instrument(name = "db.insert_profile_history", skip_all, fields(db.system = "postgresql", db.operation = "INSERT"))]
+pub async fn insert_profile_history(
+    executor: impl PgExecutor<'_>,
+    user_id: &Uuid,
+    actor_id: &Uuid,
+    changes: &Value,
+) -> Result<(), String> {
+    sqlx::query!(
+        "INSERT INTO user_profile_history (user_id, actor_id, changes) VALUES ($1, $2, $3)",
+        user_id,
+        actor_id,
+        changes
+    )
+    .execute(executor)
+    .await
+    .map(|_| ())
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+/// A page of the user's profile changes, newest first, and how many there are.
+#[instrument(name = "db.find_profile_history", skip_all, fields(db.system = "postgresql", db.operation = "SELECT"))]
+pub async fn find_profile_history(
+    pool: &PgPool,
+    user_id: &Uuid,
+    limit: i64,
+    offset: i64,
+) -> Result<(Vec<ProfileHistoryEntry>, i64), String> {
+    let entries = sqlx::query_as!(
+        ProfileHistoryEntry,
+        r#"
+        SELECT id, actor_id, changes, created_at
+        FROM user_profile_history
+        WHERE user_id = $1
+        ORDER BY created_at DESC, id
+        LIMIT $2 OFFSET $3
+        "#,
+        user_id,
+        limit,
+        offset
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("Database error: {}", e))?;
+
+    let total = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "count!" FROM user_profile_history WHERE user_id = $1"#,
+        user_id
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| format!("Database error: {}", e))?;
+
+    Ok((entries, total))
+}
+
+/// Deletes profile history recorded before `cutoff`; returns how many entries.
+#[instrument(name = "db.delete_profile_history_before", skip_all, fields(db.system = "postgresql", db.operation = "DELETE"))]
+pub async fn delete_profile_history_before(pool: &PgPool, cutoff: DateTime<Utc>) -> Result<u64, String> {
+    sqlx::query!("DELETE FROM user_profile_history WHERE created_at < $1", cutoff)
+        .execute(pool)
+        .await
+        .map(|result| result.rows_affected())
+        .map_err(|e| format!("Database error: {}", e))
+}
@@ -1,12 +1,26 @@
 use actix_web::web;
 use super::controller;
+use crate::domains::api_key::route as api_key_routes;
+use crate::domains::audit::route as audit_routes;
+use crate::domains::identity::route as identity_routes;
+use crate::domains::session::route as session_routes;
+use crate::domains::webauthn::route as webauthn_routes;
 use crate::utils::middleware::auth::AuthMiddleware;
+use crate::utils::middleware::rate_limit::RateLimitMiddleware;
 
 pub fn configure(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/users")
+            .wrap(RateLimitMiddleware::per_user("users", 120, 60))
             .wrap(AuthMiddleware::new())
             .service(controller::handle_get_profile)
             .service(controller::handle_update_profile)
+            .service(controller::handle_change_password)
+            .service(controller::handle_profile_history)
+            .configure(api_key_routes::configure)
+            .configure(identity_routes::configure_linking)
+            .configure(session_routes::configure)
+            .configure(webauthn_routes::configure_credentials)
+            .configure(audit_routes::configure_activity)
     );
 }
\ No newline at end of file
@@ -1,22 +1,32 @@
 use uuid::Uuid;
 use sqlx::PgPool;
-use chrono::Utc;
-use crate::domains::user::repository::{find_user_by_id, update_user};
+use chrono::{Duration, Utc};
+use serde_json::{json, Map, Value};
+use crate::domains::audit::entity::{NewAuditEvent, ACTION_ACCOUNT_DELETE, ACTION_PASSWORD_CHANGE, ACTION_PROFILE_UPDATE};
+use crate::domains::audit::service as audit;
+use crate::domains::user::repository::{
+    delete_profile_history_before, find_profile_history, find_user_by_id, insert_profile_history, lock_user_by_id,
+    replace_password_hash, update_user,
+};
 use crate::domains::user::dto::{UserProfileResponse, create_user_profile_response};
 use crate::utils::error::AppError;
-use super::entity::{User, UpdateProfileRequest};
+use super::entity::{User, UpdateProfileRequest, ChangePasswordRequest, ProfileHistoryPage, ProfileHistoryQuery};
+use crate::config;
+use crate::utils::auth::{hash_password, verify_user_password};
+use crate::utils::extractors::{AuthUser, RequestContext};
+use crate::utils::password_policy::{PasswordContext, PASSWORD_POLICY};
+use std::borrow::Cow;
+use validator::{ValidationError, ValidationErrors};
 use regex::Regex;
+use tracing::instrument;
 
-// Add this function
-pub async fn get_user_profile(pool: &PgPool, user_id: &str) -> Result<UserProfileResponse, AppError> {
-    let uuid = Uuid::parse_str(user_id)
-        .map_err(|e| AppError::ValidationError(format!("Invalid user ID format: {}", e)))?;
-
-    let user = match find_user_by_id(pool, &uuid).await {
+#[instrument(name = "user.get_user_profile", skip(pool))]
+pub async fn get_user_profile(pool: &PgPool, user_id: &Uuid) -> Result<UserProfileResponse, AppError> {
+    let user = match find_user_by_id(pool, user_id).await {
         Ok(Some(user)) => user,
         Ok(None) => {
-            return Err(AppError::NotFoundError(
-                format!("User profile not found for ID: {}", uuid)
+            return Err(AppError::not_found(
+                format!("User profile not found for ID: {}", user_id)
             ))
         },
         Err(e) => {
@@ -43,15 +53,33 @@ fn validate_phone(phone: &str) -> Result<(), AppError> {
     Ok(())
 }
 
+const DEFAULT_HISTORY_PER_PAGE: i64 = 20;
+
+/// The profile fields that differ between `before` and `after`, with both
+/// values, as `{"phone": {"before": ..., "after": ...}}`.
+fn profile_changes(before: &User, after: &User) -> Map<String, Value> {
+    let fields = [
+        ("email", Some(&before.email), Some(&after.email)),
+        ("name", before.name.as_ref(), after.name.as_ref()),
+        ("phone", before.phone.as_ref(), after.phone.as_ref()),
+        ("address", before.address.as_ref(), after.address.as_ref()),
+    ];
+    fields
+        .into_iter()
+        .filter(|(_, before, after)| before != after)
+        .map(|(field, before, after)| (field.to_string(), json!({ "before": before, "after": after })))
+        .collect()
+}
+
+#[instrument(name = "user.update_user_profile", skip(pool, user, update_data, context), fields(user.id = %user.id))]
 pub async fn update_user_profile(
     pool: &PgPool,
-    user_id: &str,
-    update_data: &UpdateProfileRequest
+    user: &AuthUser,
+    update_data: &UpdateProfileRequest,
+    context: &RequestContext
 ) -> Result<UserProfileResponse, AppError> {
+    let user_id = &user.id;
     // Validate user ID
-    let uuid = Uuid::parse_str(user_id)
-        .map_err(|e| AppError::ValidationError(format!("Invalid user ID format: {}", e)))?;
-
     // Validate email if provided
     if let Some(ref email) = update_data.email {
         validate_email(email)?;
@@ -82,11 +110,14 @@ pub async fn update_user_profile(
         }
     }
 
-    let current_user = match find_user_by_id(pool, &uuid).await {
+    // The history entry is written in the same transaction as the update,
+    // against the row as it was locked
+    let mut tx = pool.begin().await.map_err(AppError::DatabaseError)?;
+    let current_user = match lock_user_by_id(&mut tx, user_id).await {
         Ok(Some(user)) => user,
         Ok(None) => {
-            return Err(AppError::NotFoundError(
-                format!("User profile not found for ID: {}", uuid)
+            return Err(AppError::not_found(
+                format!("User profile not found for ID: {}", user_id)
             ))
         },
         Err(e) => {
@@ -97,20 +128,134 @@ pub async fn update_user_profile(
 
     let updated_user = User {
         id: current_user.id,
-        email: update_data.email.clone().unwrap_or(current_user.email),
+        email: update_data.email.clone().unwrap_or_else(|| current_user.email.clone()),
         name: update_data.name.clone(),
         phone: update_data.phone.clone(),
         address: update_data.address.clone(),
-        password_hash: current_user.password_hash,
+        password_hash: current_user.password_hash.clone(),
         created_at: current_user.created_at,
         updated_at: Some(Utc::now()),
         deleted_at: update_data.deleted_at,
     };
 
-    let result = match update_user(pool, &updated_user).await {
+    let result = match update_user(&mut *tx, &updated_user).await {
         Ok(user) => user,
         Err(e) => return Err(AppError::DatabaseError(sqlx::Error::Protocol(e))),
     };
 
+    // An admin acting as the user is recorded as the actor
+    let actor_id = user.impersonator.unwrap_or(user.id);
+    let changes = profile_changes(&current_user, &result);
+    let fields: Vec<String> = changes.keys().cloned().collect();
+    if !changes.is_empty() {
+        insert_profile_history(&mut *tx, &result.id, &actor_id, &Value::Object(changes)).await
+            .map_err(|e| AppError::DatabaseError(sqlx::Error::Protocol(e)))?;
+    }
+    tx.commit().await.map_err(AppError::DatabaseError)?;
+
+    if !fields.is_empty() {
+        let event = NewAuditEvent::new(ACTION_PROFILE_UPDATE, context)
+            .actor(actor_id)
+            .subject(result.id)
+            .details(json!({ "fields": fields }));
+        audit::record_or_log(pool, event).await;
+    }
+    if result.deleted_at.is_some() {
+        let event = NewAuditEvent::new(ACTION_ACCOUNT_DELETE, context)
+            .actor(actor_id)
+            .subject(result.id);
+        audit::record_or_log(pool, event).await;
+    }
+
     Ok(create_user_profile_response(result))
-}
\ No newline at end of file
+}
+
+#[instrument(name = "user.change_password", skip(pool, request, context))]
+pub async fn change_password(
+    pool: &PgPool,
+    user_id: &Uuid,
+    request: &ChangePasswordRequest,
+    context: &RequestContext
+) -> Result<(), AppError> {
+    let user = match find_user_by_id(pool, user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            return Err(AppError::not_found(
+                format!("User profile not found for ID: {}", user_id)
+            ))
+        },
+        Err(e) => {
+            log::error!("Database error while fetching user: {}", e);
+            return Err(AppError::DatabaseError(sqlx::Error::Protocol(e)))
+        },
+    };
+
+    // Accounts created through an identity provider can set a first password
+    let has_password = user.password_hash.is_some();
+    if has_password && !verify_user_password(&user, &request.current_password).await? {
+        log::warn!("Password change with wrong current password for user: {}", user_id);
+        let mut error = ValidationError::new("incorrect");
+        error.message = Some(Cow::Borrowed("Current password is incorrect"));
+        let mut errors = ValidationErrors::new();
+        errors.add("current_password", error);
+        return Err(AppError::FieldValidationError(errors));
+    }
+
+    let password_context = PasswordContext { email: &user.email, name: user.name.as_deref() };
+    PASSWORD_POLICY
+        .validate("new_password", &request.new_password, &password_context)
+        .map_err(AppError::FieldValidationError)?;
+
+    let new_hash = hash_password(&request.new_password).await?;
+    let replaced = replace_password_hash(pool, &user.id, user.password_hash.as_deref(), &new_hash).await
+        .map_err(|e| AppError::DatabaseError(sqlx::Error::Protocol(e)))?;
+    if !replaced {
+        return Err(AppError::validation("Password was changed by another request; please try again"));
+    }
+
+    log::info!("Password changed for user: {}", user_id);
+    let event = NewAuditEvent::new(ACTION_PASSWORD_CHANGE, context)
+        .actor(*user_id)
+        .subject(*user_id)
+        .details(json!({ "first_password": !has_password }));
+    audit::record_or_log(pool, event).await;
+    Ok(())
+}
+#[instrument(name = "user.profile_history", skip(pool, query))]
+pub async fn profile_history(
+    pool: &PgPool,
+    user_id: &Uuid,
+    query: &ProfileHistoryQuery
+) -> Result<ProfileHistoryPage, AppError> {
+    let page = query.page.unwrap_or(1);
+    let per_page = query.per_page.unwrap_or(DEFAULT_HISTORY_PER_PAGE);
+    let offset = (page - 1).checked_mul(per_page)
+        .ok_or_else(|| AppError::validation("Page is out of range"))?;
+
+    let (entries, total) = find_profile_history(pool, user_id, per_page, offset).await
+        .map_err(|e| AppError::DatabaseError(sqlx::Error::Protocol(e)))?;
+    Ok(ProfileHistoryPage { entries, page, per_page, total })
+}
+
+/// Periodically deletes profile history older than
+/// `PROFILE_HISTORY_RETENTION_DAYS`, unless that is 0.
+pub fn start_profile_history_pruner(pool: PgPool) {
+    let retention_days = config::get_profile_history_retention_days();
+    if retention_days <= 0 {
+        log::info!("Profile history is kept forever");
+        return;
+    }
+
+    let interval = std::time::Duration::from_secs(config::get_profile_history_prune_secs());
+    actix_web::rt::spawn(async move {
+        let mut ticker = actix_web::rt::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match delete_profile_history_before(&pool, Utc::now() - Duration::days(retention_days)).await {
+                Ok(0) => {},
+                Ok(deleted) => log::info!("Deleted {} profile history entries past retention", deleted),
+                Err(e) => log::error!("Profile history pruning failed: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,130 @@
+use actix_web::{delete, get, post, web, HttpResponse};
+use log::{error, warn};
+use serde_json::json;
+use sqlx::PgPool;
+use uuid::Uuid;
+use validator::Validate;
+use crate::domains::webauthn::entity::{AuthenticateRequest, RegisterCredentialRequest};
+use crate::domains::webauthn::service::{
+    authenticate, authentication_options, delete_credential, list_credentials, register_credential,
+    registration_options,
+};
+use crate::utils::error::{field_errors, AppError};
+use crate::utils::extractors::{AuthUser, RequestContext};
+use crate::utils::response::{Response, ResponseBuilder};
+
+#[post("/register/options")]
+pub async fn handle_registration_options(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    user.require_full_access()?;
+
+    match registration_options(pool.get_ref(), &user.id).await {
+        Ok(options) => Ok(Response::ok(options)),
+        Err(AppError::ValidationError(e)) => {
+            warn!("Validation error: {}", e);
+            Ok(Response::bad_request(&e))
+        },
+        Err(AppError::NotFoundError(e)) => Ok(Response::not_found(&e)),
+        Err(e) => {
+            error!("Unexpected error while starting passkey registration: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
+
+#[post("/register")]
+pub async fn handle_register_credential(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+    request: web::Json<RegisterCredentialRequest>,
+) -> Result<HttpResponse, AppError> {
+    user.require_full_access()?;
+
+    if let Err(errors) = request.validate() {
+        warn!("Validation failed: {:?}", errors);
+        return Ok(Response::bad_request_with_data("Validation failed", field_errors(&errors)));
+    }
+
+    match register_credential(pool.get_ref(), &user.id, &request).await {
+        Ok(credential) => Ok(Response::created(credential)),
+        Err(AppError::ValidationError(e)) => {
+            warn!("Passkey registration rejected: {}", e);
+            Ok(Response::bad_request(&e))
+        },
+        Err(e) => {
+            error!("Unexpected error while registering passkey: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
+
+#[post("/authenticate/options")]
+pub async fn handle_authentication_options(
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    match authentication_options(pool.get_ref()).await {
+        Ok(options) => Ok(Response::ok(options)),
+        Err(e) => {
+            error!("Unexpected error while starting passkey sign-in: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
+
+#[post("/authenticate")]
+pub async fn handle_authenticate(
+    pool: web::Data<PgPool>,
+    context: RequestContext,
+    request: web::Json<AuthenticateRequest>,
+) -> Result<HttpResponse, AppError> {
+    match authenticate(pool.get_ref(), &request, &context).await {
+        Ok(token) => Ok(Response::ok(json!({ "token": token }))),
+        Err(AppError::AuthenticationError(e)) => {
+            warn!("Passkey authentication failed: {}", e);
+            Ok(Response::unauthorized(&e))
+        },
+        Err(e) => {
+            error!("Unexpected error during passkey sign-in: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
+
+#[get("")]
+pub async fn handle_list_credentials(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, AppError> {
+    user.require_full_access()?;
+
+    match list_credentials(pool.get_ref(), &user.id).await {
+        Ok(credentials) => Ok(Response::ok(credentials)),
+        Err(e) => {
+            error!("Unexpected error while listing passkeys: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
+
+#[delete("/{id}")]
+pub async fn handle_delete_credential(
+    user: AuthUser,
+    pool: web::Data<PgPool>,
+    path: web::Path<Uuid>,
+) -> Result<HttpResponse, AppError> {
+    user.require_full_access()?;
+
+    match delete_credential(pool.get_ref(), &user.id, &path.into_inner()).await {
+        Ok(()) => Ok(Response::ok(json!({ "message": "Passkey removed" }))),
+        Err(AppError::NotFoundError(e)) => {
+            warn!("Passkey not found: {}", e);
+            Ok(Response::not_found(&e))
+        },
+        Err(e) => {
+            error!("Unexpected error while removing passkey: {}", e);
+            Ok(Response::internal_error("An unexpected error occurred"))
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+use validator::Validate;
+
+pub const PURPOSE_REGISTRATION: &str = "registration";
+/// Signing in with a passkey alone; requires user verification.
+pub const PURPOSE_PASSWORDLESS: &str = "passwordless";
+/// The passkey step after a password or magic link.
+pub const PURPOSE_SECOND_FACTOR: &str = "second_factor";
+
+#[derive(Debug, Serialize)]
+pub struct WebauthnCredential {
+    pub id: Uuid,
+    #[serde(skip_serializing)]
+    pub user_id: Uuid,
+    #[serde(skip_serializing)]
+    pub credential_id: Vec<u8>,
+    #[serde(skip_serializing)]
+    pub public_key: Vec<u8>,
+    #[serde(skip_serializing)]
+    pub sign_count: i64,
+    pub name: String,
+    pub transports: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+pub struct WebauthnChallenge {
+    pub user_id: Option<Uuid>,
+    pub purpose: String,
+    pub challenge: Vec<u8>,
+}
+
+/// `AuthenticatorAttestationResponse` as JSON, binary fields in base64url.
+#[derive(Deserialize)]
+pub struct AttestationResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    #[serde(rename = "attestationObject")]
+    pub attestation_object: String,
+    #[serde(default)]
+    pub transports: Vec<String>,
+}
+
+/// `AuthenticatorAssertionResponse` as JSON, binary fields in base64url.
+#[derive(Deserialize)]
+pub struct AssertionResponse {
+    #[serde(rename = "clientDataJSON")]
+    pub client_data_json: String,
+    #[serde(rename = "authenticatorData")]
+    pub authenticator_data: String,
+    pub signature: String,
+    #[serde(default, rename = "userHandle")]
+    pub user_handle: Option<String>,
+}
+
+/// A `PublicKeyCredential` as serialised by the browser.
+#[derive(Deserialize)]
+pub struct PublicKeyCredential<R> {
+    #[serde(rename = "rawId")]
+    pub raw_id: String,
+    pub response: R,
+}
+
+#[derive(Deserialize, Validate)]
+pub struct RegisterCredentialRequest {
+    pub challenge_id: Uuid,
+
+    #[validate(length(min = 1, max = 100, message = "Name must be 1 to 100 characters"))]
+    pub name: Option<String>,
+
+    pub credential: PublicKeyCredential<AttestationResponse>,
+}
+
+#[derive(Deserialize)]
+pub struct AuthenticateRequest {
+    pub challenge_id: Uuid,
+    pub credential: PublicKeyCredential<AssertionResponse>,
+}
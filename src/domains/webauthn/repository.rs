@@ -0,0 +1,146 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+use tracing::instrument;
+use crate::domains::webauthn::entity::{WebauthnChallenge, WebauthnCredential};
+
+/// Stores a challenge, first dropping expired ones.
+#[instrument(name = "db.create_webauthn_challenge", skip_all, fields(db.system = "postgresql", db.operation = "INSERT"))]
+pub async fn create_challenge(
+    pool: &PgPool,
+    user_id: Option<&Uuid>,
+    purpose: &str,
+    challenge: &[u8],
+    expires_at: DateTime<Utc>,
+) -> Result<Uuid, String> {
+    sqlx::query!("DELETE FROM webauthn_challenges WHERE expires_at <= NOW()")
+        .execute(pool)
+        .await
+        .map_err(|e| format!("Database error: {}", e))?;
+
+    sqlx::query_scalar!(
+        r#"
+        INSERT INTO webauthn_challenges (user_id, purpose, challenge, expires_at)
+        VALUES ($1, $2, $3, $4)
+        RETURNING id
+        "#,
+        user_id,
+        purpose,
+        challenge,
+        expires_at
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+/// Removes and returns an unexpired challenge, so each is answered once.
+#[instrument(name = "db.consume_webauthn_challenge", skip_all, fields(db.system = "postgresql", db.operation = "DELETE"))]
+pub async fn consume_challenge(pool: &PgPool, id: &Uuid) -> Result<Option<WebauthnChallenge>, String> {
+    sqlx::query_as!(
+        WebauthnChallenge,
+        r#"
+        DELETE FROM webauthn_challenges
+        WHERE id = $1 AND expires_at > NOW()
+        RETURNING user_id, purpose, challenge
+        "#,
+        id
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+#[instrument(name = "db.create_webauthn_credential", skip_all, fields(db.system = "postgresql", db.operation = "INSERT"))]
+pub async fn create_credential(pool: &PgPool, credential: &WebauthnCredential) -> Result<WebauthnCredential, String> {
+    sqlx::query_as!(
+        WebauthnCredential,
+        r#"
+        INSERT INTO webauthn_credentials (id, user_id, credential_id, public_key, sign_count, name, transports)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING id, user_id, credential_id, public_key, sign_count, name, transports, created_at, last_used_at
+        "#,
+        credential.id,
+        credential.user_id,
+        credential.credential_id,
+        credential.public_key,
+        credential.sign_count,
+        credential.name,
+        &credential.transports
+    )
+    .fetch_one(pool)
+    .await
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+/// Credentials of a user, newest first.
+#[instrument(name = "db.list_webauthn_credentials", skip_all, fields(db.system = "postgresql", db.operation = "SELECT"))]
+pub async fn list_credentials(pool: &PgPool, user_id: &Uuid) -> Result<Vec<WebauthnCredential>, String> {
+    sqlx::query_as!(
+        WebauthnCredential,
+        r#"
+        SELECT id, user_id, credential_id, public_key, sign_count, name, transports, created_at, last_used_at
+        FROM webauthn_credentials
+        WHERE user_id = $1
+        ORDER BY created_at DESC
+        "#,
+        user_id
+    )
+    .fetch_all(pool)
+    .await
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+/// Finds a credential by the ID the authenticator assigned it, if its
+/// owner still exists.
+#[instrument(name = "db.find_webauthn_credential", skip_all, fields(db.system = "postgresql", db.operation = "SELECT"))]
+pub async fn find_credential(pool: &PgPool, credential_id: &[u8]) -> Result<Option<WebauthnCredential>, String> {
+    sqlx::query_as!(
+        WebauthnCredential,
+        r#"
+        SELECT c.id, c.user_id, c.credential_id, c.public_key, c.sign_count, c.name, c.transports,
+            c.created_at, c.last_used_at
+        FROM webauthn_credentials c
+        JOIN users u ON u.id = c.user_id
+        WHERE c.credential_id = $1 AND u.deleted_at IS NULL
+        "#,
+        credential_id
+    )
+    .fetch_optional(pool)
+    .await
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+/// Records a successful assertion. Only advances the signature counter, so
+/// of two concurrent assertions with the same count only one succeeds.
+#[instrument(name = "db.record_webauthn_credential_use", skip_all, fields(db.system = "postgresql", db.operation = "UPDATE"))]
+pub async fn record_credential_use(pool: &PgPool, id: &Uuid, previous_count: i64, sign_count: i64) -> Result<bool, String> {
+    sqlx::query!(
+        r#"
+        UPDATE webauthn_credentials
+        SET sign_count = $3, last_used_at = NOW()
+        WHERE id = $1 AND sign_count = $2
+        "#,
+        id,
+        previous_count,
+        sign_count
+    )
+    .execute(pool)
+    .await
+    .map(|result| result.rows_affected() > 0)
+    .map_err(|e| format!("Database error: {}", e))
+}
+
+/// Deletes one of the user's credentials; returns whether there was one.
+#[instrument(name = "db.delete_webauthn_credential", skip_all, fields(db.system = "postgresql", db.operation = "DELETE"))]
+pub async fn delete_credential(pool: &PgPool, user_id: &Uuid, id: &Uuid) -> Result<bool, String> {
+    sqlx::query!(
+        "DELETE FROM webauthn_credentials WHERE id = $1 AND user_id = $2",
+        id,
+        user_id
+    )
+    .execute(pool)
+    .await
+    .map(|result| result.rows_affected() > 0)
+    .map_err(|e| format!("Database error: {}", e))
+}
@@ -0,0 +1,23 @@
+use actix_web::web;
+use super::controller;
+
+/// Ceremonies, mounted inside the `/auth` scope. Registration takes the
+/// signed-in user from the `AuthUser` extractor.
+pub fn configure_ceremonies(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/webauthn")
+            .service(controller::handle_registration_options)
+            .service(controller::handle_register_credential)
+            .service(controller::handle_authentication_options)
+            .service(controller::handle_authenticate)
+    );
+}
+
+/// Passkey management, mounted inside the authenticated `/users` scope.
+pub fn configure_credentials(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/passkeys")
+            .service(controller::handle_list_credentials)
+            .service(controller::handle_delete_credential)
+    );
+}
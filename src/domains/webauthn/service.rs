@@ -0,0 +1,252 @@
+use chrono::Utc;
+use log::{info, warn};
+use rand::RngCore;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use tracing::instrument;
+use uuid::Uuid;
+use crate::config;
+use crate::domains::auth::service::issue_token;
+use crate::domains::user::repository::find_user_by_id;
+use crate::domains::webauthn::entity::{
+    AuthenticateRequest, RegisterCredentialRequest, WebauthnCredential, PURPOSE_PASSWORDLESS, PURPOSE_REGISTRATION,
+    PURPOSE_SECOND_FACTOR,
+};
+use crate::domains::webauthn::repository::{
+    consume_challenge, create_challenge, create_credential, delete_credential as remove_credential, find_credential,
+    list_credentials as find_credentials, record_credential_use,
+};
+use crate::utils::error::AppError;
+use crate::utils::extractors::RequestContext;
+use crate::utils::webauthn::{b64, decode_b64, StoredCredential, RELYING_PARTY, SUPPORTED_ALGORITHMS};
+
+fn database_error(e: String) -> AppError {
+    AppError::internal(format!("Database error: {}", e))
+}
+
+/// Stores a fresh random challenge and returns its id and value.
+async fn new_challenge(pool: &PgPool, user_id: Option<&Uuid>, purpose: &str) -> Result<(Uuid, Vec<u8>), AppError> {
+    let mut challenge = vec![0u8; 32];
+    rand::thread_rng().fill_bytes(&mut challenge);
+    let expires_at = Utc::now() + chrono::Duration::seconds(config::get_webauthn_challenge_ttl_secs());
+
+    let id = create_challenge(pool, user_id, purpose, &challenge, expires_at).await.map_err(database_error)?;
+    Ok((id, challenge))
+}
+
+fn credential_descriptors(credentials: &[WebauthnCredential]) -> Vec<Value> {
+    credentials
+        .iter()
+        .map(|credential| json!({
+            "type": "public-key",
+            "id": b64(&credential.credential_id),
+            "transports": credential.transports,
+        }))
+        .collect()
+}
+
+fn timeout_ms() -> i64 {
+    config::get_webauthn_challenge_ttl_secs() * 1000
+}
+
+/// Options for `navigator.credentials.create()` to add a passkey to the
+/// user's account.
+#[instrument(name = "webauthn.registration_options", skip(pool))]
+pub async fn registration_options(pool: &PgPool, user_id: &Uuid) -> Result<Value, AppError> {
+    let user = find_user_by_id(pool, user_id).await
+        .map_err(database_error)?
+        .ok_or_else(|| AppError::not_found(format!("User not found for ID: {}", user_id)))?;
+
+    let credentials = find_credentials(pool, user_id).await.map_err(database_error)?;
+    let max_credentials = config::get_webauthn_max_credentials_per_user();
+    if credentials.len() as i64 >= max_credentials {
+        return Err(AppError::validation(format!(
+            "You can have at most {} passkeys; remove one first",
+            max_credentials
+        )));
+    }
+
+    let (challenge_id, challenge) = new_challenge(pool, Some(user_id), PURPOSE_REGISTRATION).await?;
+    Ok(json!({
+        "challenge_id": challenge_id,
+        "public_key": {
+            "rp": { "id": RELYING_PARTY.id, "name": RELYING_PARTY.name },
+            "user": {
+                "id": b64(user.id.as_bytes()),
+                "name": user.email,
+                "displayName": user.name.as_deref().unwrap_or(&user.email),
+            },
+            "challenge": b64(&challenge),
+            "pubKeyCredParams": SUPPORTED_ALGORITHMS
+                .iter()
+                .map(|alg| json!({ "type": "public-key", "alg": alg }))
+                .collect::<Vec<_>>(),
+            "timeout": timeout_ms(),
+            "excludeCredentials": credential_descriptors(&credentials),
+            "authenticatorSelection": { "residentKey": "preferred", "userVerification": "preferred" },
+            "attestation": "none",
+        },
+    }))
+}
+
+/// Verifies the browser's answer to [`registration_options`] and stores
+/// the new passkey.
+#[instrument(name = "webauthn.register_credential", skip(pool, request))]
+pub async fn register_credential(
+    pool: &PgPool,
+    user_id: &Uuid,
+    request: &RegisterCredentialRequest,
+) -> Result<WebauthnCredential, AppError> {
+    let challenge = consume_challenge(pool, &request.challenge_id).await
+        .map_err(database_error)?
+        .filter(|challenge| challenge.purpose == PURPOSE_REGISTRATION && challenge.user_id == Some(*user_id))
+        .ok_or_else(|| AppError::validation("Invalid or expired challenge; please start again"))?;
+
+    let invalid = |reason: String| {
+        warn!("Rejected passkey registration for user {}: {}", user_id, reason);
+        AppError::validation("The passkey could not be verified")
+    };
+    let response = &request.credential.response;
+    let verified = RELYING_PARTY
+        .verify_registration(
+            &decode_b64(&response.client_data_json).map_err(invalid)?,
+            &decode_b64(&response.attestation_object).map_err(invalid)?,
+            &challenge.challenge,
+            false,
+        )
+        .map_err(invalid)?;
+    if decode_b64(&request.credential.raw_id).map_err(invalid)? != verified.credential_id {
+        return Err(invalid("rawId does not match the attested credential".to_string()));
+    }
+
+    if find_credential(pool, &verified.credential_id).await.map_err(database_error)?.is_some() {
+        return Err(AppError::validation("This passkey is already registered"));
+    }
+
+    let credential = WebauthnCredential {
+        id: Uuid::new_v4(),
+        user_id: *user_id,
+        credential_id: verified.credential_id,
+        public_key: verified.public_key,
+        sign_count: i64::from(verified.sign_count),
+        name: request.name.as_deref().map(str::trim).unwrap_or("Passkey").to_string(),
+        transports: response.transports.clone(),
+        created_at: Utc::now(),
+        last_used_at: None,
+    };
+    let credential = create_credential(pool, &credential).await.map_err(database_error)?;
+    info!("Registered passkey {} for user {}", credential.id, user_id);
+    Ok(credential)
+}
+
+/// Options for `navigator.credentials.get()` to sign in with a passkey
+/// alone. Any discoverable credential may answer, so no account is named.
+#[instrument(name = "webauthn.authentication_options", skip(pool))]
+pub async fn authentication_options(pool: &PgPool) -> Result<Value, AppError> {
+    let (challenge_id, challenge) = new_challenge(pool, None, PURPOSE_PASSWORDLESS).await?;
+    Ok(json!({
+        "challenge_id": challenge_id,
+        "public_key": {
+            "challenge": b64(&challenge),
+            "timeout": timeout_ms(),
+            "rpId": RELYING_PARTY.id,
+            "allowCredentials": [],
+            "userVerification": "required",
+        },
+    }))
+}
+
+/// Options for the passkey step that completes a password or magic link
+/// sign-in, or `None` if the user has no passkeys.
+#[instrument(name = "webauthn.second_factor_options", skip(pool))]
+pub async fn second_factor_options(pool: &PgPool, user_id: &Uuid) -> Result<Option<Value>, AppError> {
+    let credentials = find_credentials(pool, user_id).await.map_err(database_error)?;
+    if credentials.is_empty() {
+        return Ok(None);
+    }
+
+    let (challenge_id, challenge) = new_challenge(pool, Some(user_id), PURPOSE_SECOND_FACTOR).await?;
+    Ok(Some(json!({
+        "challenge_id": challenge_id,
+        "public_key": {
+            "challenge": b64(&challenge),
+            "timeout": timeout_ms(),
+            "rpId": RELYING_PARTY.id,
+            "allowCredentials": credential_descriptors(&credentials),
+            "userVerification": "preferred",
+        },
+    })))
+}
+
+/// Verifies a passkey assertion for a passwordless or second-factor
+/// challenge and issues an access token. Passwordless sign-in requires the
+/// authenticator to have verified the user (PIN or biometrics).
+#[instrument(name = "webauthn.authenticate", skip_all, fields(user.id = tracing::field::Empty))]
+pub async fn authenticate(
+    pool: &PgPool,
+    request: &AuthenticateRequest,
+    context: &RequestContext,
+) -> Result<String, AppError> {
+    let challenge = consume_challenge(pool, &request.challenge_id).await
+        .map_err(database_error)?
+        .filter(|challenge| challenge.purpose == PURPOSE_PASSWORDLESS || challenge.purpose == PURPOSE_SECOND_FACTOR)
+        .ok_or_else(|| AppError::authentication("Invalid or expired challenge; please start again"))?;
+
+    let failed = |reason: String| {
+        warn!("Rejected passkey assertion: {}", reason);
+        AppError::authentication("Passkey verification failed")
+    };
+    let response = &request.credential.response;
+    let credential_id = decode_b64(&request.credential.raw_id).map_err(failed)?;
+    let credential = find_credential(pool, &credential_id).await
+        .map_err(database_error)?
+        .ok_or_else(|| failed("unknown credential".to_string()))?;
+    tracing::Span::current().record("user.id", tracing::field::display(credential.user_id));
+
+    if challenge.user_id.is_some_and(|user_id| user_id != credential.user_id) {
+        return Err(failed(format!("credential {} belongs to another user", credential.id)));
+    }
+    if let Some(user_handle) = response.user_handle.as_deref().filter(|handle| !handle.is_empty()) {
+        if decode_b64(user_handle).map_err(failed)? != credential.user_id.as_bytes() {
+            return Err(failed("user handle does not match the credential".to_string()));
+        }
+    }
+
+    let sign_count = RELYING_PARTY
+        .verify_assertion(
+            &decode_b64(&response.client_data_json).map_err(failed)?,
+            &decode_b64(&response.authenticator_data).map_err(failed)?,
+            &decode_b64(&response.signature).map_err(failed)?,
+            &challenge.challenge,
+            StoredCredential {
+                public_key: &credential.public_key,
+                sign_count: u32::try_from(credential.sign_count).unwrap_or(u32::MAX),
+            },
+            challenge.purpose == PURPOSE_PASSWORDLESS,
+        )
+        .map_err(|e| failed(format!("credential {}: {}", credential.id, e)))?;
+
+    let sign_count = i64::from(sign_count);
+    if !record_credential_use(pool, &credential.id, credential.sign_count, sign_count).await.map_err(database_error)? {
+        return Err(failed(format!("credential {} was used concurrently", credential.id)));
+    }
+
+    info!("User {} signed in with passkey {} ({})", credential.user_id, credential.id, challenge.purpose);
+    issue_token(pool, &credential.user_id, "passkey", context).await
+}
+
+#[instrument(name = "webauthn.list_credentials", skip(pool))]
+pub async fn list_credentials(pool: &PgPool, user_id: &Uuid) -> Result<Vec<WebauthnCredential>, AppError> {
+    find_credentials(pool, user_id).await.map_err(database_error)
+}
+
+#[instrument(name = "webauthn.delete_credential", skip(pool))]
+pub async fn delete_credential(pool: &PgPool, user_id: &Uuid, id: &Uuid) -> Result<(), AppError> {
+    let deleted = remove_credential(pool, user_id, id).await.map_err(database_error)?;
+    if !deleted {
+        return Err(AppError::not_found(format!("Passkey not found for ID: {}", id)));
+    }
+
+    info!("Removed passkey {} of user {}", id, user_id);
+    Ok(())
+}
@@ -0,0 +1,10 @@
+use actix_web::{get, http::header, HttpResponse};
+use crate::utils::jwt_keys::JWT_KEYS;
+
+/// Public keys that verify our tokens, for downstream services.
+#[get("/jwks.json")]
+pub async fn jwks() -> HttpResponse {
+    HttpResponse::Ok()
+        .insert_header((header::CACHE_CONTROL, "public, max-age=300"))
+        .json(JWT_KEYS.jwks())
+}
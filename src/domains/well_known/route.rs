@@ -0,0 +1,9 @@
+use actix_web::web;
+use super::controller;
+
+pub fn configure(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/.well-known")
+            .service(controller::jwks)
+    );
+}
@@ -0,0 +1,4 @@
+pub mod config;
+pub mod db;
+pub mod domains;
+pub mod utils;
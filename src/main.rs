@@ -1,23 +1,33 @@
 use actix_web::{web, App, HttpServer};
 use dotenv::dotenv;
-use actix_web::middleware::Logger;
+use std::time::Duration;
+use tracing_actix_web::TracingLogger;
 
-mod domains;
-mod utils;
-mod db;
-mod config;
+use rust_rest::{config, db, utils};
 
-use crate::utils::middleware::logger::setup_logger;
-use crate::utils::middleware::logger::LoggingMiddleware;
-use crate::domains::auth::route as auth_routes;
-use crate::domains::user::route as user_routes;
-use crate::domains::health::route as health_routes;
-use crate::domains::root::controller::welcome;
+use rust_rest::utils::middleware::logger::setup_logger;
+use rust_rest::utils::middleware::logger::{AccessLogConfig, LoggingMiddleware};
+use rust_rest::utils::middleware::cors::CorsPolicy;
+use rust_rest::utils::middleware::security_headers::SecurityHeaders;
+use rust_rest::utils::middleware::rate_limit::RateLimitMiddleware;
+use rust_rest::utils::middleware::audit::ImpersonationAudit;
+use rust_rest::utils::telemetry;
+use rust_rest::utils::{mailer, rate_limiter};
+use rust_rest::domains::auth::route as auth_routes;
+use rust_rest::domains::auth::service as auth_service;
+use rust_rest::domains::user::route as user_routes;
+use rust_rest::domains::user::service as user_service;
+use rust_rest::domains::admin::route as admin_routes;
+use rust_rest::domains::organization::route as organization_routes;
+use rust_rest::domains::health::route as health_routes;
+use rust_rest::domains::well_known::route as well_known_routes;
+use rust_rest::domains::root::controller::welcome;
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
     setup_logger();
+    let tracer_provider = telemetry::init_tracing();
 
     log::info!("Starting application...");
     
@@ -29,27 +39,51 @@ async fn main() -> std::io::Result<()> {
         .expect("Failed to connect to database");
     log::info!("Database connection established");
 
+    rate_limiter::init_store(&pool)
+        .await
+        .expect("Failed to initialise rate limit store");
+
+    lazy_static::initialize(&utils::jwt_keys::JWT_KEYS);
+    lazy_static::initialize(&utils::auth::JWT_SETTINGS);
+    lazy_static::initialize(&utils::oidc::OIDC_PROVIDERS);
+    lazy_static::initialize(&utils::webauthn::RELYING_PARTY);
+    utils::auth::init_password_hasher();
+    lazy_static::initialize(&utils::password_policy::PASSWORD_POLICY);
+    lazy_static::initialize(&mailer::MAILER);
+
     let pool = web::Data::new(pool);
+    rate_limiter::start_sweeper(Duration::from_secs(config::get_rate_limit_sweep_secs()));
+    user_service::start_profile_history_pruner(pool.get_ref().clone());
+    auth_service::start_lockout_sweeper(pool.get_ref().clone());
+    let access_log = AccessLogConfig::from_env();
+    let public_cors = CorsPolicy::public();
+    let api_cors = CorsPolicy::api();
+    let security_headers = SecurityHeaders::from_env();
     let server_addr = format!("0.0.0.0:{}", port);  // Changed from 127.0.0.1 to 0.0.0.0
 
     let server = HttpServer::new(move || {
-        use actix_cors::Cors;
-        
         App::new()
-            .wrap(Cors::default()
-                .allow_any_origin()
-                .allow_any_method()
-                .allow_any_header()
-                .max_age(3600))
-            .wrap(Logger::default())
-            .wrap(LoggingMiddleware::new())
+            .wrap(security_headers.clone())
+            .wrap(LoggingMiddleware::new(access_log.clone()))
+            .wrap(TracingLogger::default())
             .app_data(pool.clone())
-            .service(welcome)  // Add this line
-            .configure(health_routes::configure)
             .service(
                 web::scope("/api")
+                    .wrap(ImpersonationAudit)
+                    .wrap(RateLimitMiddleware::per_ip("api", 60, 60))
+                    .wrap(api_cors.build())
                     .configure(auth_routes::configure)
                     .configure(user_routes::configure)
+                    .configure(admin_routes::configure)
+                    .configure(organization_routes::configure)
+            )
+            // Public endpoints; registered last since the empty scope matches every path
+            .service(
+                web::scope("")
+                    .wrap(public_cors.build())
+                    .service(welcome)
+                    .configure(health_routes::configure)
+                    .configure(well_known_routes::configure)
             )
     })
     .bind(&server_addr)?
@@ -57,5 +91,7 @@ async fn main() -> std::io::Result<()> {
 
     log::info!("Server running at http://{}", server_addr);
 
-    server.await
+    let result = server.await;
+    telemetry::shutdown_tracing(tracer_provider);
+    result
 }
\ No newline at end of file
@@ -1,48 +1,435 @@
-use bcrypt::verify;
+use bcrypt::{hash, verify};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
 use serde::{Deserialize, Serialize};
 use chrono::Utc;
-use jsonwebtoken::{encode, decode, EncodingKey, DecodingKey, Header, Validation};
+use jsonwebtoken::{encode, decode, decode_header, Algorithm, Validation};
 use uuid::Uuid;
-use std::env;
+use crate::config;
 use crate::{domains::user::entity::User, utils::error::AppError};
+use crate::utils::blocking_pool::PASSWORD_HASH_POOL;
+use crate::utils::jwt_keys::JWT_KEYS;
+
+pub const SCOPE_PROFILE_READ: &str = "profile:read";
+pub const SCOPE_PROFILE_WRITE: &str = "profile:write";
+/// Reading the organizations the user belongs to, and their members.
+pub const SCOPE_ORGS_READ: &str = "orgs:read";
+
+/// Scopes an API key can be granted. Tokens from a password login carry no
+/// scopes and are not limited by them.
+pub const GRANTABLE_SCOPES: &[&str] = &[SCOPE_PROFILE_READ, SCOPE_PROFILE_WRITE, SCOPE_ORGS_READ];
+
+/// Token settings read from the environment once, at boot.
+#[derive(Debug, Clone)]
+pub struct JwtSettings {
+    /// `iss` put in issued tokens and required of verified ones.
+    pub issuer: Option<String>,
+    /// `aud` put in issued tokens and required of verified ones.
+    pub audience: Option<String>,
+    /// Clock skew tolerated when checking `exp` and `nbf`.
+    pub leeway_secs: u64,
+    pub access_token_ttl: chrono::Duration,
+}
+
+impl JwtSettings {
+    pub fn from_env() -> Self {
+        Self {
+            issuer: config::get_jwt_issuer(),
+            audience: config::get_jwt_audience(),
+            leeway_secs: config::get_jwt_leeway_secs(),
+            access_token_ttl: chrono::Duration::minutes(config::get_jwt_access_token_ttl_mins()),
+        }
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref JWT_SETTINGS: JwtSettings = JwtSettings::from_env();
+}
+
+/// The `act` claim (RFC 8693): who is acting on behalf of the subject.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Actor {
+    pub sub: String,
+}
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Claims {
     pub sub: String,
     pub exp: i64,
     pub iat: i64,
+    // Tokens issued before these claims existed have neither
+    #[serde(default)]
+    pub nbf: i64,
+    #[serde(default)]
+    pub jti: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aud: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub roles: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tenant: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub scopes: Vec<String>,
+    /// Session the token was issued for; revoking it invalidates the token.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<String>,
+    /// Set on impersonation tokens to the admin using them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub act: Option<Actor>,
 }
 
-pub fn generate_token(user_id: Uuid) -> Result<String, AppError> {
-    let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-    let now = Utc::now();
-    let claims = Claims {
-        sub: user_id.to_string(),
-        exp: (now + chrono::Duration::hours(24)).timestamp(),
-        iat: now.timestamp(),
-    };
+impl Claims {
+    /// Claims for a new access token for `user_id`, valid from now for
+    /// `JWT_ACCESS_TOKEN_TTL_MINS`.
+    pub fn new(user_id: Uuid) -> Self {
+        let now = Utc::now();
+        Self {
+            sub: user_id.to_string(),
+            exp: (now + JWT_SETTINGS.access_token_ttl).timestamp(),
+            iat: now.timestamp(),
+            nbf: now.timestamp(),
+            jti: Uuid::new_v4().to_string(),
+            iss: JWT_SETTINGS.issuer.clone(),
+            aud: JWT_SETTINGS.audience.clone(),
+            roles: Vec::new(),
+            tenant: None,
+            scopes: Vec::new(),
+            sid: None,
+            act: None,
+        }
+    }
+
+    pub fn with_roles(mut self, roles: Vec<String>) -> Self {
+        self.roles = roles;
+        self
+    }
+
+    pub fn with_scopes(mut self, scopes: Vec<String>) -> Self {
+        self.scopes = scopes;
+        self
+    }
+
+    pub fn with_session(mut self, session_id: Uuid) -> Self {
+        self.sid = Some(session_id.to_string());
+        self
+    }
+
+    pub fn with_actor(mut self, actor_id: Uuid) -> Self {
+        self.act = Some(Actor { sub: actor_id.to_string() });
+        self
+    }
 
-    encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(secret.as_bytes()),
-    )
-    .map_err(|e| AppError::InternalError(format!("Token generation error: {}", e)))
+    pub fn with_tenant(mut self, org_id: Uuid) -> Self {
+        self.tenant = Some(org_id.to_string());
+        self
+    }
 }
 
+pub fn generate_token(claims: &Claims) -> Result<String, AppError> {
+    encode(&JWT_KEYS.header(), claims, JWT_KEYS.encoding_key())
+        .map_err(|e| AppError::InternalError(format!("Token generation error: {}", e)))
+}
+
+/// Checks signature, `exp` and `nbf` (with `JWT_LEEWAY_SECS` of clock skew),
+/// and `iss`/`aud` when `JWT_ISSUER`/`JWT_AUDIENCE` are set.
 pub fn verify_token(token: &str) -> Result<Claims, AppError> {
-    let secret = env::var("JWT_SECRET").expect("JWT_SECRET must be set");
-    
-    decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(secret.as_bytes()),
-        &Validation::default(),
-    )
-    .map(|token_data| token_data.claims)
-    .map_err(|_| AppError::AuthenticationError("Invalid token".to_string()))
-}
-
-pub fn verify_user_password(user: &User, password: &str) -> Result<bool, String> {
-    verify(password, &user.password_hash)
-        .map_err(|e| format!("Password verification error: {}", e))
+    let invalid = || AppError::AuthenticationError("Invalid token".to_string());
+
+    let header = decode_header(token).map_err(|_| invalid())?;
+    let (key, algorithm) = JWT_KEYS.decoding_key(&header).ok_or_else(invalid)?;
+
+    decode::<Claims>(token, key, &validation(algorithm))
+        .map(|token_data| token_data.claims)
+        .map_err(|_| invalid())
+}
+
+fn validation(algorithm: Algorithm) -> Validation {
+    let mut validation = Validation::new(algorithm);
+    validation.leeway = JWT_SETTINGS.leeway_secs;
+    validation.validate_nbf = true;
+
+    let mut required = vec!["exp", "sub"];
+    if let Some(issuer) = &JWT_SETTINGS.issuer {
+        validation.set_issuer(&[issuer]);
+        required.push("iss");
+    }
+    match &JWT_SETTINGS.audience {
+        Some(audience) => {
+            validation.set_audience(&[audience]);
+            required.push("aud");
+        },
+        None => validation.validate_aud = false,
+    }
+    validation.set_required_spec_claims(&required);
+    validation
+}
+
+/// One password hashing algorithm. Stored hashes are routed to the scheme
+/// whose prefix they carry, so several can be verified side by side.
+trait PasswordScheme: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn handles(&self, password_hash: &str) -> bool;
+
+    fn hash(&self, password: &str) -> Result<String, String>;
+
+    fn verify(&self, password: &str, password_hash: &str) -> Result<bool, String>;
+
+    /// Whether the hash was made with this scheme's current parameters
+    /// (or stronger ones).
+    fn is_current(&self, password_hash: &str) -> bool;
+}
+
+/// Legacy `$2a$`/`$2b$`/`$2y$` hashes.
+struct BcryptScheme {
+    cost: u32,
+}
+
+impl PasswordScheme for BcryptScheme {
+    fn name(&self) -> &'static str {
+        "bcrypt"
+    }
+
+    fn handles(&self, password_hash: &str) -> bool {
+        ["$2a$", "$2b$", "$2y$"].iter().any(|prefix| password_hash.starts_with(prefix))
+    }
+
+    fn hash(&self, password: &str) -> Result<String, String> {
+        hash(password.as_bytes(), self.cost).map_err(|e| e.to_string())
+    }
+
+    fn verify(&self, password: &str, password_hash: &str) -> Result<bool, String> {
+        verify(password, password_hash).map_err(|e| e.to_string())
+    }
+
+    fn is_current(&self, password_hash: &str) -> bool {
+        password_hash
+            .get(4..6)
+            .and_then(|cost| cost.parse::<u32>().ok())
+            .is_some_and(|cost| cost >= self.cost)
+    }
+}
+
+/// Argon2id hashes in PHC format (`$argon2id$v=19$m=...,t=...,p=...$...`).
+///
+/// With a pepper, the pepper is the Argon2 secret and the first bytes of its
+/// SHA-256 are stored as the hash's `keyid`, so hashes made before a pepper
+/// was set (or with a different one) can be recognised and upgraded.
+struct Argon2idScheme {
+    params: argon2::Params,
+    pepper: Option<Vec<u8>>,
+}
+
+impl Argon2idScheme {
+    fn new(memory_kib: u32, iterations: u32, parallelism: u32, pepper: Option<String>) -> Result<Self, String> {
+        let pepper = pepper.map(String::into_bytes);
+        let mut builder = argon2::ParamsBuilder::new();
+        builder.m_cost(memory_kib).t_cost(iterations).p_cost(parallelism);
+        if let Some(pepper) = &pepper {
+            builder.keyid(argon2::KeyId::new(&pepper_id(pepper)).map_err(|e| e.to_string())?);
+        }
+        let params = builder.build().map_err(|e| format!("Invalid Argon2 parameters: {}", e))?;
+        Ok(Self { params, pepper })
+    }
+
+    fn argon2(&self, params: argon2::Params) -> Result<argon2::Argon2<'_>, String> {
+        match &self.pepper {
+            Some(pepper) => argon2::Argon2::new_with_secret(
+                pepper,
+                argon2::Algorithm::Argon2id,
+                argon2::Version::V0x13,
+                params,
+            )
+            .map_err(|e| e.to_string()),
+            None => Ok(argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params)),
+        }
+    }
+}
+
+fn pepper_id(pepper: &[u8]) -> [u8; 4] {
+    let digest = Sha256::digest(pepper);
+    [digest[0], digest[1], digest[2], digest[3]]
+}
+
+impl PasswordScheme for Argon2idScheme {
+    fn name(&self) -> &'static str {
+        "argon2id"
+    }
+
+    fn handles(&self, password_hash: &str) -> bool {
+        password_hash.starts_with("$argon2")
+    }
+
+    fn hash(&self, password: &str) -> Result<String, String> {
+        use argon2::password_hash::{rand_core::OsRng, PasswordHasher as _, SaltString};
+
+        let salt = SaltString::generate(&mut OsRng);
+        self.argon2(self.params.clone())?
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|e| e.to_string())
+    }
+
+    fn verify(&self, password: &str, password_hash: &str) -> Result<bool, String> {
+        use argon2::password_hash::{PasswordHash, PasswordVerifier};
+
+        let parsed = PasswordHash::new(password_hash).map_err(|e| e.to_string())?;
+        let params = argon2::Params::try_from(&parsed).map_err(|e| e.to_string())?;
+        let argon2 = if params.keyid().is_empty() {
+            // Made before a pepper was configured
+            argon2::Argon2::new(argon2::Algorithm::Argon2id, argon2::Version::V0x13, params)
+        } else if self.pepper.as_deref().is_some_and(|pepper| pepper_id(pepper) == params.keyid()) {
+            self.argon2(params)?
+        } else {
+            return Err("Hash was made with a different pepper".to_string());
+        };
+
+        match argon2.verify_password(password.as_bytes(), &parsed) {
+            Ok(()) => Ok(true),
+            Err(argon2::password_hash::Error::Password) => Ok(false),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    fn is_current(&self, password_hash: &str) -> bool {
+        let Ok(parsed) = argon2::password_hash::PasswordHash::new(password_hash) else {
+            return false;
+        };
+        let Ok(params) = argon2::Params::try_from(&parsed) else {
+            return false;
+        };
+
+        parsed.algorithm.as_str() == "argon2id"
+            && parsed.version == Some(argon2::Version::V0x13.into())
+            && params.m_cost() >= self.params.m_cost()
+            && params.t_cost() >= self.params.t_cost()
+            && params.p_cost() >= self.params.p_cost()
+            && params.keyid() == self.params.keyid()
+    }
+}
+
+/// Hashes new passwords with the configured scheme and verifies stored
+/// hashes with whichever scheme their prefix names.
+pub struct PasswordHasher {
+    preferred: usize,
+    schemes: Vec<Box<dyn PasswordScheme>>,
+}
+
+impl PasswordHasher {
+    pub fn from_env() -> Self {
+        let argon2 = Argon2idScheme::new(
+            config::get_argon2_memory_kib(),
+            config::get_argon2_iterations(),
+            config::get_argon2_parallelism(),
+            config::get_password_pepper(),
+        )
+        .unwrap_or_else(|e| panic!("{}", e));
+        let schemes: Vec<Box<dyn PasswordScheme>> = vec![
+            Box::new(argon2),
+            Box::new(BcryptScheme { cost: config::get_bcrypt_cost() }),
+        ];
+
+        let algorithm = config::get_password_hash_algorithm();
+        let preferred = schemes
+            .iter()
+            .position(|scheme| scheme.name() == algorithm)
+            .unwrap_or_else(|| panic!("Unknown PASSWORD_HASH_ALGORITHM '{}'", algorithm));
+
+        Self { preferred, schemes }
+    }
+
+    fn scheme_for(&self, password_hash: &str) -> Result<&dyn PasswordScheme, String> {
+        self.schemes
+            .iter()
+            .find(|scheme| scheme.handles(password_hash))
+            .map(|scheme| scheme.as_ref())
+            .ok_or_else(|| "Unrecognised password hash format".to_string())
+    }
+
+    pub fn hash(&self, password: &str) -> Result<String, String> {
+        self.schemes[self.preferred].hash(password)
+    }
+
+    pub fn verify(&self, password: &str, password_hash: &str) -> Result<bool, String> {
+        self.scheme_for(password_hash)?.verify(password, password_hash)
+    }
+
+    /// True for hashes made with another algorithm or weaker parameters
+    /// than new ones would be.
+    pub fn needs_rehash(&self, password_hash: &str) -> bool {
+        let preferred = &self.schemes[self.preferred];
+        !(preferred.handles(password_hash) && preferred.is_current(password_hash))
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref PASSWORD_HASHER: PasswordHasher = PasswordHasher::from_env();
+
+    /// Hash with the same scheme and cost as new ones, verified when a login
+    /// names an unknown email so that it takes as long as a wrong password.
+    static ref DUMMY_PASSWORD_HASH: String = PASSWORD_HASHER
+        .hash("dummy-password")
+        .expect("Failed to hash dummy password");
+}
+
+/// Hashes a password with the configured scheme on the password hash pool.
+pub async fn hash_password(password: &str) -> Result<String, AppError> {
+    let span = tracing::info_span!("password.hash");
+    let password = password.to_string();
+
+    PASSWORD_HASH_POOL
+        .run(move || span.in_scope(|| PASSWORD_HASHER.hash(&password)))
+        .await?
+        .map_err(|e| AppError::internal(format!("Password hashing error: {}", e)))
+}
+
+/// Checks a password against a stored hash on the password hash pool.
+async fn verify_password(password: &str, password_hash: &str) -> Result<bool, AppError> {
+    let span = tracing::info_span!("password.verify");
+    let password = password.to_string();
+    let password_hash = password_hash.to_string();
+
+    PASSWORD_HASH_POOL
+        .run(move || span.in_scope(|| PASSWORD_HASHER.verify(&password, &password_hash)))
+        .await?
+        .map_err(|e| AppError::internal(format!("Password verification error: {}", e)))
+}
+
+/// Accounts without a password never match, but take as long as one that
+/// does not.
+pub async fn verify_user_password(user: &User, password: &str) -> Result<bool, AppError> {
+    match &user.password_hash {
+        Some(password_hash) => verify_password(password, password_hash).await,
+        None => verify_dummy_password(password).await.map(|_| false),
+    }
+}
+
+/// Burns the time of one password verification; the result is discarded.
+pub async fn verify_dummy_password(password: &str) -> Result<(), AppError> {
+    verify_password(password, &DUMMY_PASSWORD_HASH).await.map(|_| ())
+}
+
+/// Builds the hasher (failing fast on bad configuration) and computes the
+/// dummy hash up front so the first unknown-email login is not slower than
+/// the rest.
+pub fn init_password_hasher() {
+    lazy_static::initialize(&PASSWORD_HASHER);
+    lazy_static::initialize(&DUMMY_PASSWORD_HASH);
+}
+
+/// Returns a random URL-safe token and the hash to store in its place.
+pub fn generate_secret_token() -> (String, String) {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let token = hex::encode(bytes);
+    let token_hash = hash_secret_token(&token);
+    (token, token_hash)
+}
+
+/// Tokens are high-entropy, so a plain SHA-256 is enough to keep a leaked
+/// table from being usable.
+pub fn hash_secret_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
 }
\ No newline at end of file
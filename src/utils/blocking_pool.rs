@@ -0,0 +1,71 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use log::warn;
+use tokio::sync::Semaphore;
+use crate::config;
+use crate::utils::error::AppError;
+
+/// Runs CPU-heavy work (password hashing) on tokio's blocking threads so it
+/// never stalls the actix workers.
+///
+/// At most `max_concurrency` jobs run at once and at most `max_queue` more
+/// wait for a slot; beyond that jobs are rejected with a 503 rather than
+/// queueing without bound.
+pub struct BlockingPool {
+    name: &'static str,
+    semaphore: Arc<Semaphore>,
+    max_pending: usize,
+    pending: AtomicUsize,
+}
+
+/// Decrements the pending count however the job ends, including when the
+/// request is dropped while waiting for a slot.
+struct PendingGuard<'a>(&'a AtomicUsize);
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+impl BlockingPool {
+    pub fn new(name: &'static str, max_concurrency: usize, max_queue: usize) -> Self {
+        let max_concurrency = max_concurrency.max(1);
+        Self {
+            name,
+            semaphore: Arc::new(Semaphore::new(max_concurrency)),
+            max_pending: max_concurrency + max_queue,
+            pending: AtomicUsize::new(0),
+        }
+    }
+
+    pub async fn run<F, T>(&self, job: F) -> Result<T, AppError>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        if self.pending.fetch_add(1, Ordering::AcqRel) >= self.max_pending {
+            self.pending.fetch_sub(1, Ordering::AcqRel);
+            warn!("{} pool is full, shedding request", self.name);
+            return Err(AppError::unavailable("Server is busy. Please try again shortly"));
+        }
+        let _pending = PendingGuard(&self.pending);
+
+        let _permit = self.semaphore
+            .acquire()
+            .await
+            .map_err(|e| AppError::internal(format!("{} pool closed: {}", self.name, e)))?;
+
+        tokio::task::spawn_blocking(job)
+            .await
+            .map_err(|e| AppError::internal(format!("{} job failed: {}", self.name, e)))
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref PASSWORD_HASH_POOL: BlockingPool = BlockingPool::new(
+        "password_hash",
+        config::get_password_hash_concurrency(),
+        config::get_password_hash_queue(),
+    );
+}
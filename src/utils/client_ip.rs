@@ -0,0 +1,75 @@
+use actix_web::HttpRequest;
+use ipnet::IpNet;
+use log::warn;
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use crate::config;
+
+/// Proxies whose `X-Forwarded-For` entries we believe.
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies {
+    networks: Vec<IpNet>,
+}
+
+impl TrustedProxies {
+    pub fn new(networks: Vec<IpNet>) -> Self {
+        Self { networks }
+    }
+
+    /// Reads `TRUSTED_PROXIES`, a comma-separated list of IPs or CIDR ranges.
+    pub fn from_env() -> Self {
+        let networks = config::get_trusted_proxies()
+            .iter()
+            .filter_map(|entry| {
+                let parsed = IpNet::from_str(entry)
+                    .ok()
+                    .or_else(|| IpAddr::from_str(entry).ok().map(IpNet::from));
+                if parsed.is_none() {
+                    warn!("Ignoring invalid TRUSTED_PROXIES entry '{}'", entry);
+                }
+                parsed
+            })
+            .collect();
+        Self::new(networks)
+    }
+
+    pub fn contains(&self, ip: &IpAddr) -> bool {
+        self.networks.iter().any(|network| network.contains(ip))
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref TRUSTED_PROXIES: TrustedProxies = TrustedProxies::from_env();
+}
+
+/// Resolves the client address of a request.
+///
+/// The peer address is used unless it is a trusted proxy, in which case
+/// `X-Forwarded-For` is walked from the right and the first address that is
+/// not a trusted proxy wins. Headers sent by untrusted peers are ignored so
+/// clients cannot spoof their address.
+pub fn client_ip(req: &HttpRequest, trusted: &TrustedProxies) -> Option<IpAddr> {
+    let peer = req.peer_addr()?.ip();
+    if !trusted.contains(&peer) {
+        return Some(peer);
+    }
+
+    let forwarded: Vec<IpAddr> = req
+        .headers()
+        .get_all("x-forwarded-for")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|entry| IpAddr::from_str(entry.trim()).ok())
+        .collect();
+
+    Some(
+        forwarded
+            .iter()
+            .rev()
+            .find(|ip| !trusted.contains(ip))
+            .or(forwarded.first())
+            .copied()
+            .unwrap_or(peer),
+    )
+}
@@ -1,8 +1,9 @@
-use actix_web::{HttpResponse, ResponseError, http::StatusCode};
+use actix_web::{HttpResponse, ResponseError, http::{header, StatusCode}};
 use derive_more::Display;
 use sqlx;
 use log::error;
 use serde_json::json;
+use validator::ValidationErrors;
 
 #[derive(Debug, Display)]
 pub enum AppError {
@@ -12,17 +13,27 @@ pub enum AppError {
     #[display(fmt = "Validation error: {}", _0)]
     ValidationError(String),
     
+    /// Per-field failures, rendered like request validation errors.
+    #[display(fmt = "Validation failed: {}", _0)]
+    FieldValidationError(ValidationErrors),
+
     #[display(fmt = "Authentication error: {}", _0)]
     AuthenticationError(String),
     
+    #[display(fmt = "Forbidden: {}", _0)]
+    ForbiddenError(String),
+
     #[display(fmt = "Not found: {}", _0)]
     NotFoundError(String),
     
     #[display(fmt = "Database error")]
     DatabaseError(sqlx::Error),
     
-    #[display(fmt = "Rate limit exceeded: {}", _0)]
-    RateLimitExceeded(String),
+    #[display(fmt = "Service unavailable: {}", _0)]
+    ServiceUnavailableError(String),
+
+    #[display(fmt = "Rate limit exceeded: {}", message)]
+    RateLimitExceeded { message: String, retry_after: u64 },
 }
 
 impl ResponseError for AppError {
@@ -30,10 +41,13 @@ impl ResponseError for AppError {
         match self {
             AppError::InternalError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             AppError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            AppError::FieldValidationError(_) => StatusCode::BAD_REQUEST,
             AppError::AuthenticationError(_) => StatusCode::UNAUTHORIZED,
+            AppError::ForbiddenError(_) => StatusCode::FORBIDDEN,
             AppError::NotFoundError(_) => StatusCode::NOT_FOUND,
             AppError::DatabaseError(_) => StatusCode::INTERNAL_SERVER_ERROR,
-            AppError::RateLimitExceeded(_) => StatusCode::TOO_MANY_REQUESTS,
+            AppError::ServiceUnavailableError(_) => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::RateLimitExceeded { .. } => StatusCode::TOO_MANY_REQUESTS,
         }
     }
 
@@ -68,6 +82,15 @@ impl ResponseError for AppError {
                         "data": null
                     }))
             },
+            AppError::FieldValidationError(errors) => {
+                HttpResponse::build(StatusCode::BAD_REQUEST)
+                    .json(json!({
+                        "status": "error",
+                        "code": StatusCode::BAD_REQUEST.as_u16(),
+                        "message": "Validation failed",
+                        "data": field_errors(errors)
+                    }))
+            },
             AppError::AuthenticationError(msg) => {
                 HttpResponse::build(StatusCode::UNAUTHORIZED)
                     .json(json!({
@@ -77,6 +100,15 @@ impl ResponseError for AppError {
                         "data": null
                     }))
             },
+            AppError::ForbiddenError(msg) => {
+                HttpResponse::build(StatusCode::FORBIDDEN)
+                    .json(json!({
+                        "status": "error",
+                        "code": StatusCode::FORBIDDEN.as_u16(),
+                        "message": msg,
+                        "data": null
+                    }))
+            },
             AppError::NotFoundError(msg) => {
                 HttpResponse::build(StatusCode::NOT_FOUND)
                     .json(json!({
@@ -86,14 +118,25 @@ impl ResponseError for AppError {
                         "data": null
                     }))
             },
-            AppError::RateLimitExceeded(msg) => {
+            AppError::ServiceUnavailableError(msg) => {
+                HttpResponse::build(StatusCode::SERVICE_UNAVAILABLE)
+                    .insert_header((header::RETRY_AFTER, 1))
+                    .json(json!({
+                        "status": "error",
+                        "code": StatusCode::SERVICE_UNAVAILABLE.as_u16(),
+                        "message": msg,
+                        "data": null
+                    }))
+            },
+            AppError::RateLimitExceeded { message, retry_after } => {
                 HttpResponse::build(StatusCode::TOO_MANY_REQUESTS)
+                    .insert_header((header::RETRY_AFTER, *retry_after))
                     .json(json!({
                         "status": "error",
                         "code": StatusCode::TOO_MANY_REQUESTS.as_u16(),
-                        "message": msg,
+                        "message": message,
                         "data": {
-                            "retry_after": 300
+                            "retry_after": retry_after
                         }
                     }))
             },
@@ -101,6 +144,21 @@ impl ResponseError for AppError {
     }
 }
 
+/// `{ field: [message, ...] }` for each field that failed validation.
+pub fn field_errors(errors: &ValidationErrors) -> serde_json::Map<String, serde_json::Value> {
+    errors
+        .field_errors()
+        .into_iter()
+        .map(|(field, errors)| {
+            let messages: Vec<String> = errors
+                .iter()
+                .map(|error| error.message.as_ref().unwrap_or(&error.code).to_string())
+                .collect();
+            (field.to_string(), json!(messages))
+        })
+        .collect()
+}
+
 // Helper methods for easier error creation
 impl AppError {
     pub fn internal<T: ToString>(message: T) -> Self {
@@ -115,11 +173,20 @@ impl AppError {
         AppError::AuthenticationError(message.to_string())
     }
     
+    pub fn forbidden<T: ToString>(message: T) -> Self {
+        AppError::ForbiddenError(message.to_string())
+    }
+    
     pub fn not_found<T: ToString>(message: T) -> Self {
         AppError::NotFoundError(message.to_string())
     }
     
-    pub fn rate_limited<T: ToString>(message: T) -> Self {
-        AppError::RateLimitExceeded(message.to_string())
+    pub fn unavailable<T: ToString>(message: T) -> Self {
+        AppError::ServiceUnavailableError(message.to_string())
+    }
+    
+    /// `retry_after` is in seconds and is sent as both `Retry-After` and `data.retry_after`.
+    pub fn rate_limited<T: ToString>(message: T, retry_after: u64) -> Self {
+        AppError::RateLimitExceeded { message: message.to_string(), retry_after }
     }
 }
\ No newline at end of file
@@ -0,0 +1,244 @@
+use std::net::IpAddr;
+use actix_web::dev::Payload;
+use actix_web::{http::header, web, FromRequest, HttpMessage, HttpRequest};
+use futures_util::future::{ready, LocalBoxFuture, Ready};
+use log::warn;
+use sqlx::PgPool;
+use tracing_actix_web::RequestId;
+use uuid::Uuid;
+use crate::domains::organization::entity::OrgRole;
+use crate::domains::organization::repository::find_membership_role;
+use crate::utils::auth::Claims;
+use crate::utils::client_ip::{client_ip, TRUSTED_PROXIES};
+use crate::utils::error::AppError;
+use crate::utils::middleware::auth::Credential;
+
+/// The authenticated caller of a request.
+///
+/// Taking `AuthUser` as a handler argument makes the handler require a valid
+/// bearer token or API key: the claims verified by `AuthMiddleware` are used
+/// when the route is wrapped in it, otherwise the credential is verified
+/// here. Take `Option<AuthUser>` instead for endpoints that also serve
+/// anonymous callers; a missing or invalid credential then yields `None`.
+#[derive(Debug, Clone)]
+pub struct AuthUser {
+    pub id: Uuid,
+    pub roles: Vec<String>,
+    /// The sign-in session the token belongs to (its `sid`); `None` for API
+    /// keys and tokens issued before sessions were recorded.
+    pub session_id: Option<Uuid>,
+    /// Empty unless the credential is limited to some scopes, as API keys are.
+    pub scopes: Vec<String>,
+    /// The admin behind an impersonation token (its `act` claim).
+    pub impersonator: Option<Uuid>,
+    /// The organization the token is bound to (its `tenant` claim).
+    pub tenant: Option<Uuid>,
+}
+
+impl AuthUser {
+    /// Fails if the credential is scoped and lacks `scope`.
+    pub fn require_scope(&self, scope: &str) -> Result<(), AppError> {
+        if self.scopes.is_empty() || self.scopes.iter().any(|s| s == scope) {
+            return Ok(());
+        }
+        warn!("User {} lacks scope {}", self.id, scope);
+        Err(AppError::forbidden(format!("This credential lacks the '{}' scope", scope)))
+    }
+
+    /// Fails for scoped credentials and impersonation tokens, for actions
+    /// only the user themselves may take, such as changing the password or
+    /// managing API keys, sessions and passkeys.
+    pub fn require_full_access(&self) -> Result<(), AppError> {
+        if let Some(admin_id) = self.impersonator {
+            warn!("Admin {} attempted a sensitive action while impersonating user {}", admin_id, self.id);
+            return Err(AppError::forbidden("This action is not allowed while impersonating a user"));
+        }
+        if self.scopes.is_empty() {
+            return Ok(());
+        }
+        warn!("User {} attempted a sign-in-only action with a scoped credential", self.id);
+        Err(AppError::forbidden("This action requires signing in with your password"))
+    }
+}
+
+impl TryFrom<Claims> for AuthUser {
+    type Error = AppError;
+
+    fn try_from(claims: Claims) -> Result<Self, Self::Error> {
+        let id = Uuid::parse_str(&claims.sub)
+            .map_err(|_| AppError::authentication("Invalid token subject"))?;
+        let impersonator = claims.act
+            .map(|actor| Uuid::parse_str(&actor.sub))
+            .transpose()
+            .map_err(|_| AppError::authentication("Invalid token actor"))?;
+        let tenant = claims.tenant
+            .map(|tenant| Uuid::parse_str(&tenant))
+            .transpose()
+            .map_err(|_| AppError::authentication("Invalid token tenant"))?;
+        Ok(Self {
+            id,
+            roles: claims.roles,
+            session_id: claims.sid.as_deref().and_then(|sid| Uuid::parse_str(sid).ok()),
+            scopes: claims.scopes,
+            impersonator,
+            tenant,
+        })
+    }
+}
+
+impl FromRequest for AuthUser {
+    type Error = AppError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move { authenticate(&req).await })
+    }
+}
+
+async fn authenticate(req: &HttpRequest) -> Result<AuthUser, AppError> {
+    let verified = req.extensions().get::<Claims>().cloned();
+    if let Some(claims) = verified {
+        return AuthUser::try_from(claims);
+    }
+
+    let remote_addr = client_ip(req, &TRUSTED_PROXIES)
+        .map_or_else(|| "unknown".to_string(), |ip| ip.to_string());
+    let credential = Credential::from_headers(req.headers(), &remote_addr)
+        .map_err(AppError::authentication)?;
+    let pool = req.app_data::<web::Data<PgPool>>();
+    let claims = credential.verify(pool.map(|pool| pool.get_ref())).await.map_err(|e| {
+        warn!("Credential verification failed from {}: {}", remote_addr, e);
+        e
+    })?;
+
+    // Share the claims with middleware that runs after the handler
+    req.extensions_mut().insert(claims.clone());
+    AuthUser::try_from(claims)
+}
+
+/// Longest user agent kept; anything beyond is cut off.
+const MAX_USER_AGENT_CHARS: usize = 512;
+
+/// Where a request came from, as recorded with sessions and audit events.
+#[derive(Debug, Clone, Default)]
+pub struct RequestContext {
+    /// The client address, resolved through `TRUSTED_PROXIES`.
+    pub ip: Option<IpAddr>,
+    pub user_agent: Option<String>,
+    /// The id `TracingLogger` gave the request, also on its log lines.
+    pub request_id: Option<Uuid>,
+}
+
+impl RequestContext {
+    pub fn from_request(req: &HttpRequest) -> Self {
+        let user_agent = req
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.chars().take(MAX_USER_AGENT_CHARS).collect());
+        Self {
+            ip: client_ip(req, &TRUSTED_PROXIES),
+            user_agent,
+            request_id: req.extensions().get::<RequestId>().map(|id| **id),
+        }
+    }
+}
+
+impl FromRequest for RequestContext {
+    type Error = AppError;
+    type Future = Ready<Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        ready(Ok(RequestContext::from_request(req)))
+    }
+}
+
+/// Header naming the organization a request acts in.
+pub const ORG_ID_HEADER: &str = "x-org-id";
+
+/// The organization a request acts in, for a caller who is a member of it.
+///
+/// The organization comes from the token's `tenant` claim, the `X-Org-Id`
+/// header or an `{org_id}` path segment; when several are given they must
+/// agree. A `TenantScope` can only be obtained by extracting it, which
+/// checks the membership, so repository functions that take one cannot be
+/// pointed at an organization the caller does not belong to.
+#[derive(Debug, Clone)]
+pub struct TenantScope {
+    org_id: Uuid,
+    role: OrgRole,
+    user: AuthUser,
+}
+
+impl TenantScope {
+    pub fn org_id(&self) -> &Uuid {
+        &self.org_id
+    }
+
+    /// The caller's role in the organization.
+    pub fn role(&self) -> OrgRole {
+        self.role
+    }
+
+    pub fn user(&self) -> &AuthUser {
+        &self.user
+    }
+
+    /// Fails unless the caller's role is at least `role`.
+    pub fn require_role(&self, role: OrgRole) -> Result<(), AppError> {
+        if self.role >= role {
+            return Ok(());
+        }
+        warn!("User {} with role {} in organization {} needs {}", self.user.id, self.role.as_str(), self.org_id, role.as_str());
+        Err(AppError::forbidden(format!("This action requires the {} role in the organization", role.as_str())))
+    }
+}
+
+impl FromRequest for TenantScope {
+    type Error = AppError;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, _: &mut Payload) -> Self::Future {
+        let req = req.clone();
+        Box::pin(async move { resolve_tenant(&req).await })
+    }
+}
+
+async fn resolve_tenant(req: &HttpRequest) -> Result<TenantScope, AppError> {
+    let user = authenticate(req).await?;
+
+    let parse = |value: &str| Uuid::parse_str(value.trim())
+        .map_err(|_| AppError::validation("Invalid organization ID"));
+    let header = req.headers()
+        .get(ORG_ID_HEADER)
+        .map(|value| value.to_str().map_err(|_| AppError::validation("Invalid organization ID")).and_then(parse))
+        .transpose()?;
+    let path = req.match_info().get("org_id").map(parse).transpose()?;
+
+    let requested = path.or(header);
+    if let (Some(path), Some(header)) = (path, header) {
+        if path != header {
+            return Err(AppError::validation("X-Org-Id does not match the organization in the path"));
+        }
+    }
+    let org_id = match (user.tenant, requested) {
+        (Some(tenant), Some(requested)) if tenant != requested => {
+            warn!("Token of user {} bound to organization {} used for {}", user.id, tenant, requested);
+            return Err(AppError::forbidden("This token is bound to another organization"));
+        },
+        (Some(tenant), _) => tenant,
+        (None, Some(requested)) => requested,
+        (None, None) => return Err(AppError::validation("No organization selected; send the X-Org-Id header")),
+    };
+
+    let pool = req.app_data::<web::Data<PgPool>>()
+        .ok_or_else(|| AppError::internal("Database pool is not configured"))?;
+    let role = find_membership_role(pool.get_ref(), &org_id, &user.id).await
+        .map_err(|e| AppError::internal(format!("Database error: {}", e)))?
+        .and_then(|role| OrgRole::parse(&role));
+    // Organizations the caller does not belong to look the same as ones that do not exist
+    let role = role.ok_or_else(|| AppError::not_found(format!("Organization not found for ID: {}", org_id)))?;
+
+    Ok(TenantScope { org_id, role, user })
+}
@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+use std::fs;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey, Header};
+use log::info;
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use crate::config;
+
+/// Public half of a key pair: what verifies tokens and what is published
+/// in the JWKS.
+struct PublicKey {
+    algorithm: Algorithm,
+    decoding: DecodingKey,
+    /// JWK members identifying the key, without `kid`, `alg` or `use`.
+    jwk: Value,
+    /// RFC 7638 thumbprint, used as the default `kid`.
+    thumbprint: String,
+}
+
+fn b64(bytes: &[u8]) -> String {
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn thumbprint(canonical_jwk: String) -> String {
+    b64(&Sha256::digest(canonical_jwk.as_bytes()))
+}
+
+impl PublicKey {
+    fn rsa(key: &rsa::RsaPublicKey) -> Result<Self, String> {
+        use rsa::traits::PublicKeyParts;
+
+        let n = b64(&key.n().to_bytes_be());
+        let e = b64(&key.e().to_bytes_be());
+        Ok(Self {
+            algorithm: Algorithm::RS256,
+            decoding: DecodingKey::from_rsa_components(&n, &e).map_err(|e| e.to_string())?,
+            thumbprint: thumbprint(format!(r#"{{"e":"{}","kty":"RSA","n":"{}"}}"#, e, n)),
+            jwk: json!({ "kty": "RSA", "n": n, "e": e }),
+        })
+    }
+
+    fn p256(key: &p256::PublicKey) -> Result<Self, String> {
+        use p256::elliptic_curve::sec1::ToEncodedPoint;
+
+        let point = key.to_encoded_point(false);
+        let (Some(x), Some(y)) = (point.x(), point.y()) else {
+            return Err("Invalid P-256 public key".to_string());
+        };
+        let (x, y) = (b64(x), b64(y));
+        Ok(Self {
+            algorithm: Algorithm::ES256,
+            decoding: DecodingKey::from_ec_components(&x, &y).map_err(|e| e.to_string())?,
+            thumbprint: thumbprint(format!(r#"{{"crv":"P-256","kty":"EC","x":"{}","y":"{}"}}"#, x, y)),
+            jwk: json!({ "kty": "EC", "crv": "P-256", "x": x, "y": y }),
+        })
+    }
+
+    fn ed25519(key: &ed25519_dalek::VerifyingKey) -> Result<Self, String> {
+        let x = b64(key.as_bytes());
+        Ok(Self {
+            algorithm: Algorithm::EdDSA,
+            decoding: DecodingKey::from_ed_components(&x).map_err(|e| e.to_string())?,
+            thumbprint: thumbprint(format!(r#"{{"crv":"Ed25519","kty":"OKP","x":"{}"}}"#, x)),
+            jwk: json!({ "kty": "OKP", "crv": "Ed25519", "x": x }),
+        })
+    }
+
+    /// Reads an RSA, P-256 or Ed25519 public key (SPKI, or PKCS#1 for RSA).
+    fn from_pem(pem: &str) -> Result<Self, String> {
+        use rsa::pkcs1::DecodeRsaPublicKey;
+        use rsa::pkcs8::DecodePublicKey;
+
+        if let Ok(key) = rsa::RsaPublicKey::from_public_key_pem(pem)
+            .or_else(|_| rsa::RsaPublicKey::from_pkcs1_pem(pem))
+        {
+            return Self::rsa(&key);
+        }
+        if let Ok(key) = p256::PublicKey::from_public_key_pem(pem) {
+            return Self::p256(&key);
+        }
+        if let Ok(key) = ed25519_dalek::VerifyingKey::from_public_key_pem(pem) {
+            return Self::ed25519(&key);
+        }
+        Err("not an RSA, P-256 or Ed25519 public key".to_string())
+    }
+}
+
+/// Reads an RSA (PKCS#8 or PKCS#1), P-256 or Ed25519 (PKCS#8) private key.
+fn private_key_from_pem(pem: &str) -> Result<(EncodingKey, PublicKey), String> {
+    use rsa::pkcs1::DecodeRsaPrivateKey;
+    use rsa::pkcs8::DecodePrivateKey;
+
+    if let Ok(key) = rsa::RsaPrivateKey::from_pkcs8_pem(pem)
+        .or_else(|_| rsa::RsaPrivateKey::from_pkcs1_pem(pem))
+    {
+        let encoding = EncodingKey::from_rsa_pem(pem.as_bytes()).map_err(|e| e.to_string())?;
+        return Ok((encoding, PublicKey::rsa(&key.to_public_key())?));
+    }
+    if let Ok(key) = p256::SecretKey::from_pkcs8_pem(pem) {
+        let encoding = EncodingKey::from_ec_pem(pem.as_bytes()).map_err(|e| e.to_string())?;
+        return Ok((encoding, PublicKey::p256(&key.public_key())?));
+    }
+    if let Ok(key) = ed25519_dalek::SigningKey::from_pkcs8_pem(pem) {
+        let encoding = EncodingKey::from_ed_pem(pem.as_bytes()).map_err(|e| e.to_string())?;
+        return Ok((encoding, PublicKey::ed25519(&key.verifying_key())?));
+    }
+    Err("not an RSA, P-256 or Ed25519 private key in PKCS#8 (or PKCS#1 for RSA) format".to_string())
+}
+
+fn read_pem(path: &str) -> Result<String, String> {
+    fs::read_to_string(path).map_err(|e| format!("Failed to read key file {}: {}", path, e))
+}
+
+/// Keys used to sign and verify JWTs, loaded once at startup.
+///
+/// With `JWT_ALGORITHM=HS256` tokens are signed with `JWT_SECRET` and carry
+/// no `kid`. With RS256, ES256 or EdDSA they are signed with
+/// `JWT_PRIVATE_KEY_FILE` and carry its `kid`, and any key listed in
+/// `JWT_PUBLIC_KEY_FILES` is also accepted, so keys can be rotated without
+/// invalidating tokens signed by the previous one. While `JWT_SECRET` is set,
+/// tokens without a `kid` are still verified with it.
+pub struct JwtKeys {
+    algorithm: Algorithm,
+    signing_kid: Option<String>,
+    encoding: EncodingKey,
+    verification: HashMap<String, PublicKey>,
+    secret: Option<DecodingKey>,
+}
+
+impl JwtKeys {
+    pub fn from_env() -> Result<Self, String> {
+        let secret = config::get_jwt_secret();
+        let algorithm = match config::get_jwt_algorithm().as_str() {
+            "HS256" => Algorithm::HS256,
+            "RS256" => Algorithm::RS256,
+            "ES256" => Algorithm::ES256,
+            "EDDSA" => Algorithm::EdDSA,
+            other => return Err(format!("Unsupported JWT_ALGORITHM '{}'", other)),
+        };
+
+        let mut verification = HashMap::new();
+        for entry in config::get_jwt_public_key_files() {
+            let (kid, path) = match entry.split_once('=') {
+                Some((kid, path)) => (Some(kid.trim().to_string()), path.trim()),
+                None => (None, entry.as_str()),
+            };
+            let key = PublicKey::from_pem(&read_pem(path)?).map_err(|e| format!("{}: {}", path, e))?;
+            let kid = kid.unwrap_or_else(|| key.thumbprint.clone());
+            info!("Accepting JWTs signed with {:?} key {}", key.algorithm, kid);
+            verification.insert(kid, key);
+        }
+
+        let (encoding, signing_kid) = if algorithm == Algorithm::HS256 {
+            let secret = secret.as_ref().ok_or("JWT_SECRET must be set when JWT_ALGORITHM=HS256")?;
+            (EncodingKey::from_secret(secret.as_bytes()), None)
+        } else {
+            let path = config::get_jwt_private_key_file()
+                .ok_or("JWT_PRIVATE_KEY_FILE must be set for asymmetric JWT_ALGORITHM")?;
+            let (encoding, public) = private_key_from_pem(&read_pem(&path)?)
+                .map_err(|e| format!("{}: {}", path, e))?;
+            if public.algorithm != algorithm {
+                return Err(format!(
+                    "JWT_PRIVATE_KEY_FILE holds a {:?} key but JWT_ALGORITHM is {:?}",
+                    public.algorithm, algorithm
+                ));
+            }
+            let kid = config::get_jwt_key_id().unwrap_or_else(|| public.thumbprint.clone());
+            info!("Signing JWTs with {:?} key {}", algorithm, kid);
+            verification.insert(kid.clone(), public);
+            (encoding, Some(kid))
+        };
+
+        Ok(Self {
+            algorithm,
+            signing_kid,
+            encoding,
+            verification,
+            secret: secret.map(|secret| DecodingKey::from_secret(secret.as_bytes())),
+        })
+    }
+
+    pub fn header(&self) -> Header {
+        let mut header = Header::new(self.algorithm);
+        header.kid = self.signing_kid.clone();
+        header
+    }
+
+    pub fn encoding_key(&self) -> &EncodingKey {
+        &self.encoding
+    }
+
+    /// Picks the key for a token from its header. The algorithm is taken
+    /// from the key, never from the token, so a token cannot choose a
+    /// weaker algorithm than its key was registered with.
+    pub fn decoding_key(&self, header: &Header) -> Option<(&DecodingKey, Algorithm)> {
+        match &header.kid {
+            Some(kid) => self
+                .verification
+                .get(kid)
+                .map(|key| (&key.decoding, key.algorithm)),
+            None => self.secret.as_ref().map(|secret| (secret, Algorithm::HS256)),
+        }
+    }
+
+    /// Public verification keys as a JWK Set. Shared secrets are never listed.
+    pub fn jwks(&self) -> Value {
+        let mut kids: Vec<&String> = self.verification.keys().collect();
+        kids.sort();
+
+        let keys: Vec<Value> = kids
+            .into_iter()
+            .map(|kid| {
+                let key = &self.verification[kid];
+                let mut jwk = key.jwk.clone();
+                jwk["kid"] = json!(kid);
+                jwk["alg"] = json!(format!("{:?}", key.algorithm));
+                jwk["use"] = json!("sig");
+                jwk
+            })
+            .collect();
+        json!({ "keys": keys })
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref JWT_KEYS: JwtKeys = JwtKeys::from_env().unwrap_or_else(|e| panic!("Invalid JWT key configuration: {}", e));
+}
@@ -0,0 +1,95 @@
+use async_trait::async_trait;
+use log::{error, info};
+use serde::Serialize;
+use std::sync::Arc;
+use crate::config;
+use crate::utils::error::AppError;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Email {
+    pub from: String,
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+impl Email {
+    pub fn new(to: &str, subject: &str, body: String) -> Self {
+        Self {
+            from: config::get_mail_from(),
+            to: to.to_string(),
+            subject: subject.to_string(),
+            body,
+        }
+    }
+}
+
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: &Email) -> Result<(), AppError>;
+}
+
+/// Writes emails to the log instead of delivering them; for development.
+pub struct LogMailer;
+
+#[async_trait]
+impl Mailer for LogMailer {
+    async fn send(&self, email: &Email) -> Result<(), AppError> {
+        info!(target: "mailer", "To: {}\nSubject: {}\n\n{}", email.to, email.subject, email.body);
+        Ok(())
+    }
+}
+
+/// POSTs emails as JSON (`from`, `to`, `subject`, `body`) to a delivery
+/// service or relay, with an optional bearer token.
+pub struct HttpMailer {
+    client: reqwest::Client,
+    url: String,
+    token: Option<String>,
+}
+
+impl HttpMailer {
+    pub fn new(url: String, token: Option<String>) -> Self {
+        Self { client: reqwest::Client::new(), url, token }
+    }
+}
+
+#[async_trait]
+impl Mailer for HttpMailer {
+    async fn send(&self, email: &Email) -> Result<(), AppError> {
+        let mut request = self.client.post(&self.url).json(email);
+        if let Some(token) = &self.token {
+            request = request.bearer_auth(token);
+        }
+
+        request
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| AppError::internal(format!("Failed to send email: {}", e)))?;
+        Ok(())
+    }
+}
+
+fn build_mailer() -> Arc<dyn Mailer> {
+    match (config::get_mailer().as_str(), config::get_mailer_http_url()) {
+        ("http", Some(url)) => Arc::new(HttpMailer::new(url, config::get_mailer_http_token())),
+        ("http", None) => panic!("MAILER_HTTP_URL must be set when MAILER=http"),
+        ("log", _) => Arc::new(LogMailer),
+        (other, _) => panic!("Unknown MAILER '{}'", other),
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref MAILER: Arc<dyn Mailer> = build_mailer();
+}
+
+/// Sends `email` in the background so delivery time (and whether an email
+/// was sent at all) does not show in the response time.
+pub fn send_in_background(email: Email) {
+    actix_web::rt::spawn(async move {
+        if let Err(e) = MAILER.send(&email).await {
+            error!("Failed to send '{}' email: {}", email.subject, e);
+        }
+    });
+}
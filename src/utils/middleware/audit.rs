@@ -0,0 +1,89 @@
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::{web, Error, HttpMessage};
+use futures_util::future::{LocalBoxFuture, Ready, ready};
+use log::warn;
+use serde_json::json;
+use sqlx::PgPool;
+use std::rc::Rc;
+use uuid::Uuid;
+
+use crate::domains::audit::entity::{NewAuditEvent, ACTION_IMPERSONATION_REQUEST};
+use crate::domains::audit::service as audit;
+use crate::utils::auth::Claims;
+use crate::utils::extractors::RequestContext;
+
+/// Records every request made with an impersonation token in the audit log,
+/// with the status it got. Runs after the handler, since that is when the
+/// token has been verified, by either `AuthMiddleware` or the `AuthUser`
+/// extractor.
+pub struct ImpersonationAudit;
+
+impl<S, B> Transform<S, ServiceRequest> for ImpersonationAudit
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type InitError = ();
+    type Transform = ImpersonationAuditService<S>;
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(ImpersonationAuditService { service: Rc::new(service) }))
+    }
+}
+
+pub struct ImpersonationAuditService<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for ImpersonationAuditService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    S::Future: 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, cx: &mut std::task::Context<'_>) -> std::task::Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            let res = service.call(req).await?;
+
+            let request = res.request();
+            let Some(claims) = request.extensions().get::<Claims>().filter(|claims| claims.act.is_some()).cloned() else {
+                return Ok(res);
+            };
+            let Some(pool) = request.app_data::<web::Data<PgPool>>().cloned() else {
+                warn!("Database pool is not configured; impersonated request to {} was not audited", request.path());
+                return Ok(res);
+            };
+
+            let mut event = NewAuditEvent::new(ACTION_IMPERSONATION_REQUEST, &RequestContext::from_request(request))
+                .details(json!({
+                    "method": request.method().as_str(),
+                    "path": request.path(),
+                    "status": res.status().as_u16(),
+                }));
+            // Both were checked when the token was verified
+            if let Some(actor_id) = claims.act.and_then(|actor| Uuid::parse_str(&actor.sub).ok()) {
+                event = event.actor(actor_id);
+            }
+            if let Ok(subject_id) = Uuid::parse_str(&claims.sub) {
+                event = event.subject(subject_id);
+            }
+            audit::record_or_log(pool.get_ref(), event).await;
+
+            Ok(res)
+        })
+    }
+}
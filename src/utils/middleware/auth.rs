@@ -1,90 +1,21 @@
+use actix_web::body::EitherBody;
 use actix_web::dev::{ServiceRequest, ServiceResponse};
-use actix_web::Error;
-use log::{info, warn, error, debug};
+use actix_web::{web, Error};
+use log::{warn, error, debug};
 use actix_web::dev::Transform;
-use actix_web::{dev::Service, http::header, HttpMessage};
+use actix_web::{dev::Service, http::header, http::header::HeaderMap, HttpMessage};
 use futures_util::future::{LocalBoxFuture, Ready, ready};
-use serde::{Deserialize, Serialize};
-use std::task::{Context, Poll};
-use crate::utils::auth;
+use sqlx::PgPool;
+use std::rc::Rc;
+use tracing::Instrument;
+use crate::domains::api_key::service as api_key_service;
+use crate::domains::session::service as session_service;
+use crate::utils::auth::{self, Claims};
+use crate::utils::client_ip::{client_ip, TRUSTED_PROXIES};
+use crate::utils::error::AppError;
 use crate::utils::response::{Response, ResponseBuilder};
 
-
-#[derive(Default, Clone)]
-pub struct LoggingMiddleware;
-
-impl<S, B> Transform<S, ServiceRequest> for LoggingMiddleware
-where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
-    B: 'static,
-{
-    type Response = ServiceResponse<B>;
-    type Error = Error;
-    type Transform = LoggingMiddlewareService<S>;
-    type InitError = ();
-    type Future = Ready<Result<Self::Transform, Self::InitError>>;
-
-    fn new_transform(&self, service: S) -> Self::Future {
-        ready(Ok(LoggingMiddlewareService { service }))
-    }
-}
-
-pub struct LoggingMiddlewareService<S> {
-    service: S,
-}
-
-impl<S, B> Service<ServiceRequest> for LoggingMiddlewareService<S>
-where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
-    B: 'static,
-{
-    type Response = ServiceResponse<B>;
-    type Error = Error;
-    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
-
-    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
-        self.service.poll_ready(cx)
-    }
-
-    fn call(&self, req: ServiceRequest) -> Self::Future {
-        let start = std::time::Instant::now();
-        let method = req.method().clone();
-        let path = req.path().to_owned();
-        let remote_addr = req.connection_info().realip_remote_addr()
-            .unwrap_or("unknown").to_string();
-
-        debug!("Incoming request: {} {} from {}", method, path, remote_addr);
-
-        let fut = self.service.call(req);
-
-        Box::pin(async move {
-            let res = fut.await?;
-            let duration = start.elapsed();
-            let status = res.status();
-            
-            if status.is_success() {
-                info!(
-                    "Request completed: {} {} - {} - {}ms from {}",
-                    method, path, status.as_u16(), duration.as_millis(), remote_addr
-                );
-            } else {
-                warn!(
-                    "Request failed: {} {} - {} - {}ms from {}",
-                    method, path, status.as_u16(), duration.as_millis(), remote_addr
-                );
-            }
-
-            Ok(res)
-        })
-    }
-}
-
-#[derive(Debug, Serialize, Deserialize, Clone)]
-pub struct Claims {
-    pub sub: String,
-    pub exp: usize,
-}
-
+#[derive(Default)]
 pub struct AuthMiddleware;
 
 impl AuthMiddleware {
@@ -95,32 +26,32 @@ impl AuthMiddleware {
 
 impl<S, B> Transform<S, ServiceRequest> for AuthMiddleware
 where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     S::Future: 'static,
     B: 'static,
 {
-    type Response = ServiceResponse<B>;
+    type Response = ServiceResponse<EitherBody<B>>;
     type Error = Error;
     type InitError = ();
     type Transform = AuthMiddlewareService<S>;
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ready(Ok(AuthMiddlewareService { service }))
+        ready(Ok(AuthMiddlewareService { service: Rc::new(service) }))
     }
 }
 
 pub struct AuthMiddlewareService<S> {
-    service: S,
+    service: Rc<S>,
 }
 
 impl<S, B> Service<ServiceRequest> for AuthMiddlewareService<S>
 where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error>,
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
     S::Future: 'static,
     B: 'static,
 {
-    type Response = ServiceResponse<B>;
+    type Response = ServiceResponse<EitherBody<B>>;
     type Error = Error;
     type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
 
@@ -131,21 +62,90 @@ where
     fn call(&self, req: ServiceRequest) -> Self::Future {
         let path = req.path().to_owned();
         let method = req.method().clone();
-        let remote_addr = req.connection_info().realip_remote_addr()
-            .unwrap_or("unknown").to_string();
+        let remote_addr = client_ip(req.request(), &TRUSTED_PROXIES)
+            .map_or_else(|| "unknown".to_string(), |ip| ip.to_string());
 
         debug!("Checking authentication for {} {} from {}", method, path, remote_addr);
 
-        let auth_header = match req.headers().get(header::AUTHORIZATION) {
+        let span = tracing::info_span!(
+            "auth_middleware",
+            http.method = %method,
+            http.target = %path,
+            enduser.id = tracing::field::Empty,
+            enduser.role = tracing::field::Empty,
+            enduser.scope = tracing::field::Empty,
+            enduser.impersonator = tracing::field::Empty,
+            tenant.id = tracing::field::Empty,
+        );
+
+        let credential = match Credential::from_headers(req.headers(), &remote_addr) {
+            Ok(credential) => credential,
+            Err(message) => return Box::pin(ready(Ok(unauthorized(req, message)))),
+        };
+        let pool = req.app_data::<web::Data<PgPool>>().cloned();
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            let claims = match credential.verify(pool.as_ref().map(|pool| pool.get_ref())).await {
+                Ok(claims) => claims,
+                Err(e @ AppError::AuthenticationError(_)) => {
+                    error!("Credential verification failed from {}: {}", remote_addr, e);
+                    return Ok(unauthorized(req, &format!("Invalid or expired token: {}", e)));
+                },
+                Err(e) => {
+                    error!("Could not verify credential from {}: {}", remote_addr, e);
+                    return Ok(req.error_response(e).map_into_right_body());
+                },
+            };
+
+            debug!("Successfully authenticated user {} for {} {}",
+                claims.sub, method, path);
+            let span = tracing::Span::current();
+            span.record("enduser.id", claims.sub.as_str());
+            if !claims.roles.is_empty() {
+                span.record("enduser.role", claims.roles.join(",").as_str());
+            }
+            if !claims.scopes.is_empty() {
+                span.record("enduser.scope", claims.scopes.join(" ").as_str());
+            }
+            if let Some(actor) = &claims.act {
+                span.record("enduser.impersonator", actor.sub.as_str());
+            }
+            if let Some(tenant) = &claims.tenant {
+                span.record("tenant.id", tenant.as_str());
+            }
+            req.extensions_mut().insert(claims);
+
+            let res = service.call(req).await?;
+            Ok(res.map_into_left_body())
+        }.instrument(span))
+    }
+}
+
+/// A credential presented with a request: a JWT (`Authorization: Bearer`)
+/// or an API key (`Authorization: ApiKey` or `X-API-Key`).
+pub enum Credential {
+    Bearer(String),
+    ApiKey(String),
+}
+
+impl Credential {
+    pub fn from_headers(headers: &HeaderMap, remote_addr: &str) -> Result<Self, &'static str> {
+        if let Some(api_key) = headers.get("x-api-key") {
+            return match api_key.to_str() {
+                Ok(key) => Ok(Credential::ApiKey(key.trim().to_string())),
+                Err(_) => {
+                    error!("Invalid X-API-Key header format from {}", remote_addr);
+                    Err("Invalid X-API-Key header format")
+                }
+            };
+        }
+
+        let auth_header = match headers.get(header::AUTHORIZATION) {
             Some(header) => header,
             None => {
                 warn!("No authorization header in request from {}", remote_addr);
-                return Box::pin(ready(Err(
-                    actix_web::error::InternalError::from_response(
-                        "Unauthorized",
-                        Response::unauthorized("No authorization header provided")
-                    ).into()
-                )));
+                return Err("No authorization header provided");
             }
         };
 
@@ -153,47 +153,39 @@ where
             Ok(str) => str,
             Err(_) => {
                 error!("Invalid authorization header format from {}", remote_addr);
-                return Box::pin(ready(Err(
-                    actix_web::error::InternalError::from_response(
-                        "Unauthorized",
-                        Response::unauthorized("Invalid authorization header format")
-                    ).into()
-                )));
+                return Err("Invalid authorization header format");
             }
         };
 
-        if !auth_str.starts_with("Bearer ") {
-            warn!("Invalid token format from {}: missing Bearer prefix", remote_addr);
-            return Box::pin(ready(Err(
-                actix_web::error::InternalError::from_response(
-                    "Unauthorized",
-                    Response::unauthorized("Invalid authorization header format")
-                ).into()
-            )));
+        if let Some(token) = auth_str.strip_prefix("Bearer ") {
+            return Ok(Credential::Bearer(token.to_string()));
+        }
+        if let Some(key) = auth_str.strip_prefix("ApiKey ") {
+            return Ok(Credential::ApiKey(key.trim().to_string()));
         }
 
-        let token = &auth_str["Bearer ".len()..];
-        
-        match auth::verify_token(token) {
-            Ok(claims) => {
-                debug!("Successfully authenticated user {} for {} {}", 
-                    claims.sub, method, path);
-                req.extensions_mut().insert(claims);
-                let fut = self.service.call(req);
-                Box::pin(async move {
-                    let res = fut.await?;
-                    Ok(res)
-                })
-            }
-            Err(e) => {
-                error!("Token verification failed from {}: {}", remote_addr, e);
-                Box::pin(ready(Err(
-                    actix_web::error::InternalError::from_response(
-                        "Unauthorized",
-                        Response::unauthorized(&format!("Invalid or expired token: {}", e))
-                    ).into()
-                )))
-            }
+        warn!("Invalid authorization header from {}: expected Bearer or ApiKey", remote_addr);
+        Err("Invalid authorization header format")
+    }
+
+    /// Resolves the credential to claims. API keys and the sessions of
+    /// tokens are looked up in the database, so they need the pool.
+    pub async fn verify(self, pool: Option<&PgPool>) -> Result<Claims, AppError> {
+        let pool = || pool.ok_or_else(|| AppError::internal("Database pool is not configured"));
+        match self {
+            Credential::Bearer(token) => {
+                let claims = auth::verify_token(&token)?;
+                // Tokens issued before sessions were recorded have no `sid`
+                if claims.sid.is_some() {
+                    session_service::authenticate(pool()?, &claims).await?;
+                }
+                Ok(claims)
+            },
+            Credential::ApiKey(key) => api_key_service::authenticate(pool()?, &key).await,
         }
     }
-}
\ No newline at end of file
+}
+
+fn unauthorized<B>(req: ServiceRequest, message: &str) -> ServiceResponse<EitherBody<B>> {
+    req.into_response(Response::unauthorized(message)).map_into_right_body()
+}
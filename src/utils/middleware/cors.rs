@@ -0,0 +1,152 @@
+use actix_cors::Cors;
+use actix_web::http::header::HeaderName;
+use actix_web::http::Method;
+use log::{debug, error, warn};
+use std::str::FromStr;
+
+use crate::config;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OriginPattern {
+    /// `*`: any origin. Never combined with credentials.
+    Any,
+    /// An exact origin such as `https://app.example.com`.
+    Exact(String),
+    /// `https://*.example.com`: any subdomain of `example.com` over `https`,
+    /// but not `example.com` itself.
+    WildcardSubdomain { scheme: String, suffix: String },
+}
+
+impl OriginPattern {
+    pub fn parse(pattern: &str) -> Self {
+        let pattern = pattern.trim().trim_end_matches('/');
+        if pattern == "*" {
+            return Self::Any;
+        }
+        match pattern.split_once("://*.") {
+            Some((scheme, host)) => Self::WildcardSubdomain {
+                scheme: scheme.to_lowercase(),
+                suffix: format!(".{}", host.to_lowercase()),
+            },
+            None => Self::Exact(pattern.to_lowercase()),
+        }
+    }
+
+    pub fn matches(&self, origin: &str) -> bool {
+        let origin = origin.to_lowercase();
+        match self {
+            Self::Any => true,
+            Self::Exact(allowed) => *allowed == origin,
+            Self::WildcardSubdomain { scheme, suffix } => origin
+                .strip_prefix(scheme.as_str())
+                .and_then(|rest| rest.strip_prefix("://"))
+                .and_then(|host| host.strip_suffix(suffix.as_str()))
+                .is_some_and(|sub| !sub.is_empty() && sub.split('.').all(is_dns_label)),
+        }
+    }
+}
+
+fn is_dns_label(label: &str) -> bool {
+    !label.is_empty() && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+}
+
+/// A CORS policy for one scope, read from `CORS_<SCOPE>_*` variables.
+#[derive(Debug, Clone)]
+pub struct CorsPolicy {
+    pub name: String,
+    pub allowed_origins: Vec<OriginPattern>,
+    pub allowed_methods: Vec<Method>,
+    pub allowed_headers: Vec<HeaderName>,
+    pub exposed_headers: Vec<HeaderName>,
+    pub allow_credentials: bool,
+    pub max_age: Option<usize>,
+}
+
+impl CorsPolicy {
+    /// Policy for public, unauthenticated endpoints such as `/health`.
+    pub fn public() -> Self {
+        Self::from_env("PUBLIC", "*", "GET", "Content-Type")
+    }
+
+    /// Policy for `/api`. No cross-origin access unless origins are configured.
+    pub fn api() -> Self {
+        Self::from_env("API", "", "GET,POST,PUT,PATCH,DELETE", "Authorization,Content-Type,X-Org-Id")
+    }
+
+    fn from_env(scope: &str, origins: &str, methods: &str, headers: &str) -> Self {
+        let var = |key: &str, default: &str| {
+            config::get_cors_setting(scope, key).unwrap_or_else(|| default.to_string())
+        };
+
+        let mut policy = Self {
+            name: scope.to_lowercase(),
+            allowed_origins: split_list(&var("ALLOWED_ORIGINS", origins))
+                .map(OriginPattern::parse)
+                .collect(),
+            allowed_methods: split_list(&var("ALLOWED_METHODS", methods))
+                .filter_map(|m| parse_or_warn(scope, "method", m, |m| Method::from_str(&m.to_uppercase()).ok()))
+                .collect(),
+            allowed_headers: split_list(&var("ALLOWED_HEADERS", headers))
+                .filter_map(|h| parse_or_warn(scope, "header", h, |h| HeaderName::from_str(h).ok()))
+                .collect(),
+            exposed_headers: split_list(&var("EXPOSED_HEADERS", ""))
+                .filter_map(|h| parse_or_warn(scope, "header", h, |h| HeaderName::from_str(h).ok()))
+                .collect(),
+            allow_credentials: var("ALLOW_CREDENTIALS", "false").eq_ignore_ascii_case("true"),
+            max_age: var("MAX_AGE", "3600").parse().ok(),
+        };
+
+        if policy.allow_credentials && policy.allowed_origins.contains(&OriginPattern::Any) {
+            error!(
+                "CORS policy '{}' allows any origin with credentials; disabling credentials",
+                policy.name
+            );
+            policy.allow_credentials = false;
+        }
+
+        policy
+    }
+
+    pub fn is_origin_allowed(&self, origin: &str) -> bool {
+        self.allowed_origins.iter().any(|pattern| pattern.matches(origin))
+    }
+
+    pub fn build(&self) -> Cors {
+        let policy = self.clone();
+        let mut cors = Cors::default()
+            .allowed_origin_fn(move |origin, _req_head| {
+                let origin = origin.to_str().unwrap_or_default();
+                let allowed = policy.is_origin_allowed(origin);
+                if !allowed {
+                    debug!("CORS policy '{}' rejected origin {}", policy.name, origin);
+                }
+                allowed
+            })
+            .allowed_methods(self.allowed_methods.clone())
+            .max_age(self.max_age);
+
+        if !self.allowed_headers.is_empty() {
+            cors = cors.allowed_headers(self.allowed_headers.clone());
+        }
+        if !self.exposed_headers.is_empty() {
+            cors = cors.expose_headers(self.exposed_headers.clone());
+        }
+        if self.allow_credentials {
+            cors = cors.supports_credentials();
+        }
+
+        cors
+    }
+}
+
+fn split_list(value: &str) -> impl Iterator<Item = &str> {
+    value.split(',').map(str::trim).filter(|item| !item.is_empty())
+}
+
+fn parse_or_warn<T>(scope: &str, kind: &str, value: &str, parse: impl Fn(&str) -> Option<T>) -> Option<T> {
+    let parsed = parse(value);
+    if parsed.is_none() {
+        warn!("Ignoring invalid CORS {} '{}' for scope {}", kind, value, scope);
+    }
+    parsed
+}
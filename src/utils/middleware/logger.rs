@@ -1,13 +1,25 @@
+use actix_web::body::{BodySize, MessageBody};
 use actix_web::dev::{ServiceRequest, ServiceResponse};
 use actix_web::Error;
 use actix_web::dev::Transform;
 use actix_web::dev::Service;
+use actix_web::http::header;
+use actix_web::HttpMessage;
 use futures_util::future::{LocalBoxFuture, Ready, ready};
+use serde_json::json;
+use std::rc::Rc;
 use std::task::{Context, Poll};
-
+use std::time::Duration;
 
 use env_logger::{Builder, Env};
-use log::{info, LevelFilter};
+use log::{info, warn, LevelFilter};
+
+use crate::config;
+use crate::utils::auth::Claims;
+use crate::utils::client_ip::{client_ip, TRUSTED_PROXIES};
+
+/// Query parameters whose values never reach the access log.
+const REDACTED_QUERY_PARAMS: &[&str] = &["token", "password", "secret", "code", "key"];
 
 pub fn setup_logger() {
     Builder::from_env(Env::default().default_filter_or("info"))
@@ -21,20 +33,88 @@ pub fn setup_logger() {
         .init();
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccessLogFormat {
+    /// NCSA common log format.
+    Common,
+    /// Common log format plus referer and user agent.
+    Combined,
+    /// One JSON object per request.
+    Json,
+}
+
+impl AccessLogFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "common" => Some(Self::Common),
+            "combined" => Some(Self::Combined),
+            "json" => Some(Self::Json),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AccessLogConfig {
+    pub format: AccessLogFormat,
+    /// Path prefixes that are not logged, e.g. `/health`.
+    pub exclude: Vec<String>,
+    /// Requests slower than this are logged at WARN.
+    pub slow_threshold: Duration,
+}
+
+impl AccessLogConfig {
+    pub fn from_env() -> Self {
+        let format = config::get_access_log_format();
+        Self {
+            format: AccessLogFormat::parse(&format).unwrap_or_else(|| {
+                warn!("Unknown ACCESS_LOG_FORMAT '{}', falling back to combined", format);
+                AccessLogFormat::Combined
+            }),
+            exclude: config::get_access_log_exclude(),
+            slow_threshold: Duration::from_millis(config::get_access_log_slow_ms()),
+        }
+    }
+
+    fn is_excluded(&self, path: &str) -> bool {
+        self.exclude.iter().any(|prefix| {
+            path == prefix
+                || path.strip_prefix(prefix.as_str()).is_some_and(|rest| rest.starts_with('/'))
+        })
+    }
+}
+
+impl Default for AccessLogConfig {
+    fn default() -> Self {
+        Self {
+            format: AccessLogFormat::Combined,
+            exclude: Vec::new(),
+            slow_threshold: Duration::from_millis(1000),
+        }
+    }
+}
 
+/// Access log middleware.
+///
+/// Only request metadata is logged: the `Authorization` header and request
+/// bodies are never read, and sensitive query parameters are redacted.
+/// Wrap it outside `AuthMiddleware` so the authenticated user ID is known by
+/// the time the response is logged.
 #[derive(Default, Clone)]
-pub struct LoggingMiddleware;
+pub struct LoggingMiddleware {
+    config: Rc<AccessLogConfig>,
+}
 
 impl LoggingMiddleware {
-    pub fn new() -> Self {
-        LoggingMiddleware
+    pub fn new(config: AccessLogConfig) -> Self {
+        LoggingMiddleware { config: Rc::new(config) }
     }
 }
 
 impl<S, B> Transform<S, ServiceRequest> for LoggingMiddleware
 where
     S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
-    B: 'static,
+    B: MessageBody + 'static,
 {
     type Response = ServiceResponse<B>;
     type Error = Error;
@@ -43,18 +123,96 @@ where
     type Future = Ready<Result<Self::Transform, Self::InitError>>;
 
     fn new_transform(&self, service: S) -> Self::Future {
-        ready(Ok(LoggingMiddlewareService { service }))
+        ready(Ok(LoggingMiddlewareService { service, config: self.config.clone() }))
     }
 }
 
 pub struct LoggingMiddlewareService<S> {
     service: S,
+    config: Rc<AccessLogConfig>,
+}
+
+struct AccessLogEntry {
+    remote_addr: String,
+    user_id: Option<String>,
+    time: chrono::DateTime<chrono::Utc>,
+    method: String,
+    target: String,
+    version: String,
+    status: u16,
+    bytes: Option<u64>,
+    referer: Option<String>,
+    user_agent: Option<String>,
+    duration: Duration,
+}
+
+impl AccessLogEntry {
+    fn format(&self, format: AccessLogFormat) -> String {
+        let user = self.user_id.as_deref().unwrap_or("-");
+        let bytes = self.bytes.map_or_else(|| "-".to_string(), |b| b.to_string());
+        let common = format!(
+            "{} - {} [{}] \"{} {} {}\" {} {} {}ms",
+            self.remote_addr,
+            user,
+            self.time.format("%d/%b/%Y:%H:%M:%S %z"),
+            self.method,
+            self.target,
+            self.version,
+            self.status,
+            bytes,
+            self.duration.as_millis()
+        );
+
+        match format {
+            AccessLogFormat::Common => common,
+            AccessLogFormat::Combined => format!(
+                "{} \"{}\" \"{}\"",
+                common,
+                self.referer.as_deref().unwrap_or("-"),
+                self.user_agent.as_deref().unwrap_or("-")
+            ),
+            AccessLogFormat::Json => json!({
+                "remote_addr": self.remote_addr,
+                "user_id": self.user_id,
+                "time": self.time.to_rfc3339(),
+                "method": self.method,
+                "target": self.target,
+                "version": self.version,
+                "status": self.status,
+                "bytes": self.bytes,
+                "referer": self.referer,
+                "user_agent": self.user_agent,
+                "duration_ms": self.duration.as_millis() as u64,
+            })
+            .to_string(),
+        }
+    }
+}
+
+fn redact_query(query: &str) -> String {
+    query
+        .split('&')
+        .map(|pair| match pair.split_once('=') {
+            Some((name, _)) if REDACTED_QUERY_PARAMS.contains(&name.to_lowercase().as_str()) => {
+                format!("{}=[REDACTED]", name)
+            },
+            _ => pair.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+fn header_value(req: &ServiceRequest, name: header::HeaderName) -> Option<String> {
+    req.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(String::from)
 }
 
 impl<S, B> Service<ServiceRequest> for LoggingMiddlewareService<S>
 where
     S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
-    B: 'static,
+    B: MessageBody + 'static,
 {
     type Response = ServiceResponse<B>;
     type Error = Error;
@@ -65,25 +223,67 @@ where
     }
 
     fn call(&self, req: ServiceRequest) -> Self::Future {
+        if self.config.is_excluded(req.path()) {
+            return Box::pin(self.service.call(req));
+        }
+
         let start = std::time::Instant::now();
-        let method = req.method().clone();
-        let path = req.path().to_owned();
+        let time = chrono::Utc::now();
+        let method = req.method().to_string();
+        let target = match req.query_string() {
+            "" => req.path().to_owned(),
+            query => format!("{}?{}", req.path(), redact_query(query)),
+        };
+        let version = format!("{:?}", req.version());
+        let remote_addr = client_ip(req.request(), &TRUSTED_PROXIES)
+            .map_or_else(|| "unknown".to_string(), |ip| ip.to_string());
+        let referer = header_value(&req, header::REFERER);
+        let user_agent = header_value(&req, header::USER_AGENT);
+        let config = self.config.clone();
 
         let fut = self.service.call(req);
 
         Box::pin(async move {
-            let res = fut.await?;
+            let result = fut.await;
             let duration = start.elapsed();
-            
-            info!(
-                "{} {} - {} - {}ms",
+
+            // Errors from inner middleware (e.g. a rejected token) have not
+            // been rendered yet, so take the status from the error itself.
+            let (status, bytes, user_id) = match &result {
+                Ok(res) => {
+                    let bytes = match res.response().body().size() {
+                        BodySize::Sized(size) => Some(size),
+                        BodySize::None => Some(0),
+                        BodySize::Stream => None,
+                    };
+                    let user_id = res.request().extensions().get::<Claims>().map(|c| c.sub.clone());
+                    (res.status().as_u16(), bytes, user_id)
+                },
+                Err(e) => (e.as_response_error().status_code().as_u16(), None, None),
+            };
+
+            let entry = AccessLogEntry {
+                remote_addr,
+                user_id,
+                time,
                 method,
-                path,
-                res.status().as_u16(),
-                duration.as_millis()
-            );
+                target,
+                version,
+                status,
+                bytes,
+                referer,
+                user_agent,
+                duration,
+            };
+            let line = entry.format(config.format);
+
+            if duration >= config.slow_threshold {
+                warn!(target: "access_log", "Slow request ({}ms): {}", duration.as_millis(), line);
+            } else {
+                info!(target: "access_log", "{}", line);
+            }
 
-            Ok(res)
+            result
         })
     }
 }
@@ -1,2 +1,6 @@
 pub mod auth;
-pub mod logger;
\ No newline at end of file
+pub mod logger;
+pub mod cors;
+pub mod security_headers;
+pub mod rate_limit;
+pub mod audit;
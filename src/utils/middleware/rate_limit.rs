@@ -0,0 +1,168 @@
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue, RETRY_AFTER};
+use actix_web::http::StatusCode;
+use actix_web::{Error, HttpMessage};
+use futures_util::future::{LocalBoxFuture, Ready, ready};
+use log::warn;
+use serde_json::json;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use crate::utils::auth::Claims;
+use crate::utils::client_ip::{client_ip, TRUSTED_PROXIES};
+use crate::utils::rate_limiter::{scope_limiter, RateLimitDecision, RateLimiter};
+use crate::utils::response::ApiResponse;
+
+const RATELIMIT_LIMIT: HeaderName = HeaderName::from_static("ratelimit-limit");
+const RATELIMIT_REMAINING: HeaderName = HeaderName::from_static("ratelimit-remaining");
+const RATELIMIT_RESET: HeaderName = HeaderName::from_static("ratelimit-reset");
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitKey {
+    /// One bucket per client IP.
+    ClientIp,
+    /// One bucket per authenticated `sub`; falls back to the client IP for
+    /// anonymous requests. Must be wrapped inside `AuthMiddleware`.
+    User,
+    /// A single bucket shared by every request to the scope.
+    RouteGroup,
+}
+
+/// Rate limits a scope using the shared limiter for `scope`.
+///
+/// Limits come from `RATE_LIMIT_<SCOPE>` (`<max>/<window_secs>`), so the same
+/// middleware can be tuned per scope without code changes.
+pub struct RateLimitMiddleware {
+    key: RateLimitKey,
+    limiter: RateLimiter,
+}
+
+impl RateLimitMiddleware {
+    pub fn new(scope: &str, key: RateLimitKey, max_requests: usize, window_secs: u64) -> Self {
+        Self {
+            key,
+            limiter: scope_limiter(&scope.to_uppercase(), max_requests, window_secs),
+        }
+    }
+
+    pub fn per_ip(scope: &str, max_requests: usize, window_secs: u64) -> Self {
+        Self::new(scope, RateLimitKey::ClientIp, max_requests, window_secs)
+    }
+
+    pub fn per_user(scope: &str, max_requests: usize, window_secs: u64) -> Self {
+        Self::new(scope, RateLimitKey::User, max_requests, window_secs)
+    }
+
+    pub fn per_route_group(scope: &str, max_requests: usize, window_secs: u64) -> Self {
+        Self::new(scope, RateLimitKey::RouteGroup, max_requests, window_secs)
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for RateLimitMiddleware
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Transform = RateLimitMiddlewareService<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RateLimitMiddlewareService {
+            service: Rc::new(service),
+            key: self.key,
+            limiter: self.limiter.clone(),
+        }))
+    }
+}
+
+pub struct RateLimitMiddlewareService<S> {
+    service: Rc<S>,
+    key: RateLimitKey,
+    limiter: RateLimiter,
+}
+
+impl<S> RateLimitMiddlewareService<S> {
+    fn bucket_key(&self, req: &ServiceRequest) -> String {
+        let ip = || {
+            client_ip(req.request(), &TRUSTED_PROXIES)
+                .map_or_else(|| "unknown".to_string(), |ip| ip.to_string())
+        };
+
+        match self.key {
+            RateLimitKey::ClientIp => format!("ip:{}", ip()),
+            RateLimitKey::User => match req.extensions().get::<Claims>() {
+                Some(claims) => format!("user:{}", claims.sub),
+                None => format!("ip:{}", ip()),
+            },
+            RateLimitKey::RouteGroup => "group".to_string(),
+        }
+    }
+}
+
+fn ceil_secs(duration: Duration) -> u64 {
+    duration.as_secs_f64().ceil() as u64
+}
+
+/// Nested scopes each set the headers; the most restrictive bucket wins.
+fn set_rate_limit_headers(headers: &mut HeaderMap, decision: &RateLimitDecision) {
+    let existing_remaining = headers
+        .get(&RATELIMIT_REMAINING)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<usize>().ok());
+    if existing_remaining.is_some_and(|remaining| remaining <= decision.remaining) {
+        return;
+    }
+
+    headers.insert(RATELIMIT_LIMIT, HeaderValue::from(decision.limit));
+    headers.insert(RATELIMIT_REMAINING, HeaderValue::from(decision.remaining));
+    headers.insert(RATELIMIT_RESET, HeaderValue::from(ceil_secs(decision.reset_after)));
+    if !decision.allowed {
+        headers.insert(RETRY_AFTER, HeaderValue::from(ceil_secs(decision.retry_after)));
+    }
+}
+
+impl<S, B> Service<ServiceRequest> for RateLimitMiddlewareService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let key = self.bucket_key(&req);
+        let limiter = self.limiter.clone();
+        let service = self.service.clone();
+
+        Box::pin(async move {
+            let decision = limiter.check(&key).await;
+
+            if !decision.allowed {
+                warn!("Rate limit exceeded for {}", key);
+                let retry_after = ceil_secs(decision.retry_after);
+                let mut response = ApiResponse::error_with_data(
+                    StatusCode::TOO_MANY_REQUESTS,
+                    &format!("Too many requests. Please try again after {} seconds", retry_after),
+                    json!({ "retry_after": retry_after }),
+                )
+                .into_response();
+                set_rate_limit_headers(response.headers_mut(), &decision);
+                return Ok(req.into_response(response).map_into_right_body());
+            }
+
+            let mut res = service.call(req).await?;
+            set_rate_limit_headers(res.headers_mut(), &decision);
+            Ok(res.map_into_left_body())
+        })
+    }
+}
@@ -0,0 +1,263 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{Service, ServiceRequest, ServiceResponse, Transform};
+use actix_web::http::header::{self, HeaderMap, HeaderName, HeaderValue};
+use actix_web::{Error, HttpResponse, ResponseError};
+use futures_util::future::{LocalBoxFuture, Ready, ready};
+use log::warn;
+use std::fmt;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use crate::config;
+
+/// Header values for one route. `None` leaves the header unset.
+#[derive(Debug, Clone)]
+pub struct SecurityHeadersConfig {
+    pub strict_transport_security: Option<String>,
+    pub content_type_nosniff: bool,
+    pub frame_options: Option<String>,
+    pub referrer_policy: Option<String>,
+    pub content_security_policy: Option<String>,
+    /// Sends `Cache-Control: no-store` so intermediaries never cache the response.
+    pub no_store: bool,
+}
+
+impl Default for SecurityHeadersConfig {
+    fn default() -> Self {
+        Self {
+            strict_transport_security: Some("max-age=31536000; includeSubDomains".to_string()),
+            content_type_nosniff: true,
+            frame_options: Some("DENY".to_string()),
+            referrer_policy: Some("no-referrer".to_string()),
+            content_security_policy: Some("default-src 'none'; frame-ancestors 'none'".to_string()),
+            no_store: false,
+        }
+    }
+}
+
+impl SecurityHeadersConfig {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            strict_transport_security: config::get_security_header("HSTS")
+                .map_or(defaults.strict_transport_security, disabled_if_off),
+            content_type_nosniff: defaults.content_type_nosniff,
+            frame_options: config::get_security_header("FRAME_OPTIONS")
+                .map_or(defaults.frame_options, disabled_if_off),
+            referrer_policy: config::get_security_header("REFERRER_POLICY")
+                .map_or(defaults.referrer_policy, disabled_if_off),
+            content_security_policy: config::get_security_header("CSP")
+                .map_or(defaults.content_security_policy, disabled_if_off),
+            no_store: defaults.no_store,
+        }
+    }
+
+    fn apply(&self, headers: &mut HeaderMap) {
+        set_default(headers, header::STRICT_TRANSPORT_SECURITY, self.strict_transport_security.as_deref());
+        if self.content_type_nosniff {
+            set_default(headers, header::X_CONTENT_TYPE_OPTIONS, Some("nosniff"));
+        }
+        set_default(headers, header::X_FRAME_OPTIONS, self.frame_options.as_deref());
+        set_default(headers, header::REFERRER_POLICY, self.referrer_policy.as_deref());
+        set_default(headers, header::CONTENT_SECURITY_POLICY, self.content_security_policy.as_deref());
+        if self.no_store {
+            headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+            headers.insert(header::PRAGMA, HeaderValue::from_static("no-cache"));
+        }
+    }
+}
+
+fn disabled_if_off(value: String) -> Option<String> {
+    if value.eq_ignore_ascii_case("off") { None } else { Some(value) }
+}
+
+/// Handlers can still set their own value; only missing headers are filled in.
+fn set_default(headers: &mut HeaderMap, name: HeaderName, value: Option<&str>) {
+    let Some(value) = value else { return };
+    if headers.contains_key(&name) {
+        return;
+    }
+    match HeaderValue::from_str(value) {
+        Ok(value) => {
+            headers.insert(name, value);
+        },
+        Err(_) => warn!("Invalid value for security header {}: {}", name, value),
+    }
+}
+
+/// Adds hardening headers to every response.
+///
+/// Per-route overrides are matched on path prefix; the longest match wins.
+/// By default `SECURITY_NO_STORE_PATHS` (`/api/auth` and `/api/users`) get
+/// `Cache-Control: no-store`.
+#[derive(Clone)]
+pub struct SecurityHeaders {
+    default: Arc<SecurityHeadersConfig>,
+    overrides: Arc<Vec<(String, SecurityHeadersConfig)>>,
+}
+
+impl SecurityHeaders {
+    pub fn new(default: SecurityHeadersConfig) -> Self {
+        Self { default: Arc::new(default), overrides: Arc::new(Vec::new()) }
+    }
+
+    pub fn from_env() -> Self {
+        config::get_security_no_store_paths()
+            .into_iter()
+            .fold(Self::new(SecurityHeadersConfig::from_env()), |headers, path| {
+                headers.with_override(&path, |config| SecurityHeadersConfig { no_store: true, ..config })
+            })
+    }
+
+    /// Overrides the configuration for paths under `prefix`, starting from
+    /// the default configuration.
+    pub fn with_override<F>(mut self, prefix: &str, f: F) -> Self
+    where
+        F: FnOnce(SecurityHeadersConfig) -> SecurityHeadersConfig,
+    {
+        let prefix = prefix.trim_end_matches('/').to_string();
+        let config = f((*self.default).clone());
+        let overrides = Arc::make_mut(&mut self.overrides);
+        overrides.retain(|(existing, _)| *existing != prefix);
+        overrides.push((prefix, config));
+        self
+    }
+
+    fn config_for(&self, path: &str) -> &SecurityHeadersConfig {
+        self.overrides
+            .iter()
+            .filter(|(prefix, _)| {
+                path == prefix
+                    || path.strip_prefix(prefix.as_str()).is_some_and(|rest| rest.starts_with('/'))
+            })
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map_or(&self.default, |(_, config)| config)
+    }
+}
+
+impl<S, B> Transform<S, ServiceRequest> for SecurityHeaders
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Transform = SecurityHeadersService<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(SecurityHeadersService { service, headers: self.clone() }))
+    }
+}
+
+pub struct SecurityHeadersService<S> {
+    service: S,
+    headers: SecurityHeaders,
+}
+
+impl<S, B> Service<ServiceRequest> for SecurityHeadersService<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = Error> + 'static,
+    B: MessageBody + 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = Error;
+    type Future = LocalBoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let headers = self.headers.clone();
+        let path = req.path().to_owned();
+
+        let fut = self.service.call(req);
+
+        Box::pin(async move {
+            let mut res = fut.await.map_err(|error| {
+                Error::from(WithSecurityHeaders { error, config: headers.config_for(&path).clone() })
+            })?;
+
+            headers
+                .config_for(res.request().path())
+                .apply(res.headers_mut());
+
+            Ok(res)
+        })
+    }
+}
+
+/// An error from an inner service, such as a rejected credential, which
+/// actix renders after it has passed this middleware. Rendering it through
+/// this wrapper adds the headers its route would have had.
+#[derive(Debug)]
+struct WithSecurityHeaders {
+    error: Error,
+    config: SecurityHeadersConfig,
+}
+
+impl fmt::Display for WithSecurityHeaders {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl ResponseError for WithSecurityHeaders {
+    fn status_code(&self) -> actix_web::http::StatusCode {
+        self.error.as_response_error().status_code()
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut res = self.error.error_response();
+        self.config.apply(res.headers_mut());
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::error::ErrorTooManyRequests;
+    use actix_web::{test, web, App, HttpResponse};
+
+    fn headers() -> SecurityHeaders {
+        SecurityHeaders::new(SecurityHeadersConfig::default())
+            .with_override("/api", |config| SecurityHeadersConfig { no_store: true, ..config })
+    }
+
+    #[actix_web::test]
+    async fn adds_headers_to_responses() {
+        let app = test::init_service(
+            App::new()
+                .wrap(headers())
+                .route("/api/ok", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let res = test::call_service(&app, test::TestRequest::get().uri("/api/ok").to_request()).await;
+        assert_eq!(res.headers().get(header::X_FRAME_OPTIONS).unwrap(), "DENY");
+        assert_eq!(res.headers().get(header::CACHE_CONTROL).unwrap(), "no-store");
+    }
+
+    #[actix_web::test]
+    async fn adds_headers_to_errors_from_inner_middleware() {
+        let app = test::init_service(
+            App::new()
+                .wrap_fn(|_, _| async { Err::<ServiceResponse, _>(ErrorTooManyRequests("slow down")) })
+                .wrap(headers())
+                .route("/api/limited", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let err = app
+            .call(test::TestRequest::get().uri("/api/limited").to_request())
+            .await
+            .expect_err("inner middleware error");
+        let res = err.error_response();
+        assert_eq!(res.status(), 429);
+        assert_eq!(res.headers().get(header::X_CONTENT_TYPE_OPTIONS).unwrap(), "nosniff");
+        assert_eq!(res.headers().get(header::CACHE_CONTROL).unwrap(), "no-store");
+    }
+}
@@ -2,4 +2,13 @@ pub mod error;
 pub mod auth;
 pub mod middleware;
 pub mod response;
-pub mod rate_limiter;
\ No newline at end of file
+pub mod rate_limiter;
+pub mod telemetry;
+pub mod client_ip;
+pub mod blocking_pool;
+pub mod password_policy;
+pub mod jwt_keys;
+pub mod extractors;
+pub mod oidc;
+pub mod webauthn;
+pub mod mailer;
@@ -0,0 +1,264 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{decode, decode_header, Algorithm, DecodingKey, Validation};
+use log::{info, warn};
+use rand::RngCore;
+use reqwest::Url;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tokio::sync::RwLock;
+use crate::config;
+use crate::utils::error::AppError;
+use crate::utils::auth::JWT_SETTINGS;
+
+/// Signature algorithms accepted on ID tokens. Symmetric algorithms are
+/// refused: they would make the client secret a signing key.
+const ID_TOKEN_ALGORITHMS: &[Algorithm] = &[
+    Algorithm::RS256,
+    Algorithm::RS384,
+    Algorithm::RS512,
+    Algorithm::PS256,
+    Algorithm::PS384,
+    Algorithm::PS512,
+    Algorithm::ES256,
+    Algorithm::ES384,
+    Algorithm::EdDSA,
+];
+
+/// How often an unknown `kid` may trigger a JWKS refresh.
+const JWKS_REFRESH_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The parts of the provider's discovery document we use.
+#[derive(Debug, Clone, Deserialize)]
+struct ProviderMetadata {
+    issuer: String,
+    authorization_endpoint: String,
+    token_endpoint: String,
+    jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    id_token: Option<String>,
+}
+
+/// Claims read from a verified ID token.
+#[derive(Debug, Deserialize)]
+pub struct IdTokenClaims {
+    pub sub: String,
+    pub nonce: Option<String>,
+    pub email: Option<String>,
+    pub email_verified: Option<bool>,
+    pub name: Option<String>,
+}
+
+/// An OpenID Connect provider we act as a relying party for, configured by
+/// `OIDC_<NAME>_*`. Its discovery document and keys are fetched on first use.
+pub struct OidcProvider {
+    pub name: String,
+    issuer: String,
+    client_id: String,
+    client_secret: Option<String>,
+    scopes: String,
+    http: reqwest::Client,
+    metadata: RwLock<Option<ProviderMetadata>>,
+    jwks: RwLock<Option<(JwkSet, Instant)>>,
+}
+
+impl OidcProvider {
+    pub fn from_env(name: &str) -> Result<Self, String> {
+        let setting = |key: &str| config::get_oidc_provider_setting(name, key);
+        let required = |key: &str| {
+            setting(key).ok_or_else(|| format!("OIDC_{}_{} must be set", name.to_uppercase(), key))
+        };
+
+        Ok(Self {
+            name: name.to_lowercase(),
+            issuer: required("ISSUER")?.trim_end_matches('/').to_string(),
+            client_id: required("CLIENT_ID")?,
+            client_secret: setting("CLIENT_SECRET"),
+            scopes: setting("SCOPES").unwrap_or_else(|| "openid email profile".to_string()),
+            http: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .map_err(|e| e.to_string())?,
+            metadata: RwLock::new(None),
+            jwks: RwLock::new(None),
+        })
+    }
+
+    /// Where the provider sends the browser back to.
+    pub fn redirect_uri(&self) -> String {
+        format!("{}/api/auth/oidc/{}/callback", config::get_public_url(), self.name)
+    }
+
+    async fn metadata(&self) -> Result<ProviderMetadata, AppError> {
+        if let Some(metadata) = self.metadata.read().await.as_ref() {
+            return Ok(metadata.clone());
+        }
+
+        let url = format!("{}/.well-known/openid-configuration", self.issuer);
+        let metadata: ProviderMetadata = self.get_json(&url).await?;
+        if metadata.issuer.trim_end_matches('/') != self.issuer {
+            return Err(AppError::internal(format!(
+                "OIDC provider {} reports issuer {} instead of {}",
+                self.name, metadata.issuer, self.issuer
+            )));
+        }
+
+        info!("Loaded OIDC discovery document for {}", self.name);
+        *self.metadata.write().await = Some(metadata.clone());
+        Ok(metadata)
+    }
+
+    async fn get_json<T: serde::de::DeserializeOwned>(&self, url: &str) -> Result<T, AppError> {
+        self.http
+            .get(url)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| AppError::internal(format!("OIDC request to {} failed: {}", url, e)))?
+            .json()
+            .await
+            .map_err(|e| AppError::internal(format!("Invalid OIDC response from {}: {}", url, e)))
+    }
+
+    /// URL of the provider's login page for an authorization-code request
+    /// protected by `state`, `nonce` and the PKCE `code_challenge`.
+    pub async fn authorization_url(&self, state: &str, nonce: &str, code_challenge: &str) -> Result<String, AppError> {
+        let metadata = self.metadata().await?;
+        let mut url = Url::parse(&metadata.authorization_endpoint)
+            .map_err(|e| AppError::internal(format!("Invalid authorization endpoint: {}", e)))?;
+        url.query_pairs_mut()
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &self.client_id)
+            .append_pair("redirect_uri", &self.redirect_uri())
+            .append_pair("scope", &self.scopes)
+            .append_pair("state", state)
+            .append_pair("nonce", nonce)
+            .append_pair("code_challenge", code_challenge)
+            .append_pair("code_challenge_method", "S256");
+        Ok(url.into())
+    }
+
+    /// Redeems an authorization code and returns the verified ID token claims.
+    pub async fn exchange_code(&self, code: &str, code_verifier: &str, nonce: &str) -> Result<IdTokenClaims, AppError> {
+        let metadata = self.metadata().await?;
+        let redirect_uri = self.redirect_uri();
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", redirect_uri.as_str()),
+            ("client_id", self.client_id.as_str()),
+            ("code_verifier", code_verifier),
+        ];
+        if let Some(secret) = &self.client_secret {
+            form.push(("client_secret", secret.as_str()));
+        }
+
+        let response = self.http
+            .post(&metadata.token_endpoint)
+            .form(&form)
+            .send()
+            .await
+            .map_err(|e| AppError::internal(format!("OIDC token request failed: {}", e)))?;
+        if !response.status().is_success() {
+            warn!("OIDC provider {} rejected the authorization code: {}", self.name, response.status());
+            return Err(AppError::authentication("Sign-in with the identity provider failed"));
+        }
+
+        let tokens: TokenResponse = response
+            .json()
+            .await
+            .map_err(|e| AppError::internal(format!("Invalid OIDC token response: {}", e)))?;
+        let id_token = tokens.id_token
+            .ok_or_else(|| AppError::internal("OIDC token response has no id_token"))?;
+
+        self.verify_id_token(&id_token, nonce).await
+    }
+
+    async fn verify_id_token(&self, id_token: &str, nonce: &str) -> Result<IdTokenClaims, AppError> {
+        let invalid = |reason: String| {
+            warn!("Rejected ID token from {}: {}", self.name, reason);
+            AppError::authentication("Invalid ID token")
+        };
+
+        let header = decode_header(id_token).map_err(|e| invalid(e.to_string()))?;
+        if !ID_TOKEN_ALGORITHMS.contains(&header.alg) {
+            return Err(invalid(format!("unsupported algorithm {:?}", header.alg)));
+        }
+        let key = self.decoding_key(header.kid.as_deref()).await?
+            .ok_or_else(|| invalid(format!("unknown key {:?}", header.kid)))?;
+
+        let mut validation = Validation::new(header.alg);
+        validation.leeway = JWT_SETTINGS.leeway_secs;
+        validation.set_issuer(&[&self.issuer]);
+        validation.set_audience(&[&self.client_id]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+
+        let claims = decode::<IdTokenClaims>(id_token, &key, &validation)
+            .map_err(|e| invalid(e.to_string()))?
+            .claims;
+        if claims.nonce.as_deref() != Some(nonce) {
+            return Err(invalid("nonce mismatch".to_string()));
+        }
+        Ok(claims)
+    }
+
+    /// Finds the provider key for `kid`, refetching the JWKS when the key is
+    /// unknown so provider key rotation is picked up.
+    async fn decoding_key(&self, kid: Option<&str>) -> Result<Option<DecodingKey>, AppError> {
+        let find = |jwks: &JwkSet| -> Result<Option<DecodingKey>, AppError> {
+            let jwk = match kid {
+                Some(kid) => jwks.find(kid),
+                None if jwks.keys.len() == 1 => jwks.keys.first(),
+                None => None,
+            };
+            jwk.map(DecodingKey::from_jwk)
+                .transpose()
+                .map_err(|e| AppError::internal(format!("Invalid provider JWK: {}", e)))
+        };
+
+        if let Some((jwks, fetched_at)) = self.jwks.read().await.as_ref() {
+            if let Some(key) = find(jwks)? {
+                return Ok(Some(key));
+            }
+            if fetched_at.elapsed() < JWKS_REFRESH_INTERVAL {
+                return Ok(None);
+            }
+        }
+
+        let metadata = self.metadata().await?;
+        let jwks: JwkSet = self.get_json(&metadata.jwks_uri).await?;
+        let key = find(&jwks)?;
+        *self.jwks.write().await = Some((jwks, Instant::now()));
+        Ok(key)
+    }
+}
+
+/// Returns a random URL-safe value for `state`, `nonce` or a PKCE verifier.
+pub fn random_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// The S256 PKCE challenge for `verifier`.
+pub fn pkce_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(Sha256::digest(verifier.as_bytes()))
+}
+
+lazy_static::lazy_static! {
+    /// Providers named in `OIDC_PROVIDERS`, by lowercase name.
+    pub static ref OIDC_PROVIDERS: HashMap<String, OidcProvider> = config::get_oidc_providers()
+        .iter()
+        .map(|name| {
+            let provider = OidcProvider::from_env(name)
+                .unwrap_or_else(|e| panic!("Invalid OIDC provider '{}': {}", name, e));
+            (provider.name.clone(), provider)
+        })
+        .collect();
+}
@@ -0,0 +1,310 @@
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use log::info;
+use sha1::{Digest, Sha1};
+use validator::{ValidationError, ValidationErrors};
+use crate::config;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CharacterClass {
+    Lower,
+    Upper,
+    Letter,
+    Digit,
+    Symbol,
+}
+
+impl CharacterClass {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "lower" => Some(Self::Lower),
+            "upper" => Some(Self::Upper),
+            "letter" => Some(Self::Letter),
+            "digit" => Some(Self::Digit),
+            "symbol" => Some(Self::Symbol),
+            _ => None,
+        }
+    }
+
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Self::Lower => c.is_lowercase(),
+            Self::Upper => c.is_uppercase(),
+            Self::Letter => c.is_alphabetic(),
+            Self::Digit => c.is_numeric(),
+            Self::Symbol => !c.is_alphanumeric() && !c.is_whitespace(),
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            Self::Lower => "a lowercase letter",
+            Self::Upper => "an uppercase letter",
+            Self::Letter => "a letter",
+            Self::Digit => "a number",
+            Self::Symbol => "a symbol",
+        }
+    }
+}
+
+/// SHA-1 hashes (or hash prefixes) of known-breached passwords, loaded from
+/// a local file so checks never leave the server.
+///
+/// One uppercase or lowercase hex entry per line, 5 to 40 characters long.
+/// The Have I Been Pwned `HASH:count` format is accepted, and `#` starts a
+/// comment. A prefix matches every password whose SHA-1 starts with it, so
+/// shorter prefixes trade false positives for a smaller file.
+pub struct BreachedPasswords {
+    prefixes: HashSet<String>,
+    lengths: BTreeSet<usize>,
+}
+
+impl BreachedPasswords {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read breached password list {}: {}", path, e))?;
+
+        let mut prefixes = HashSet::new();
+        let mut lengths = BTreeSet::new();
+        for (number, line) in contents.lines().enumerate() {
+            let entry = line.split(['#', ':']).next().unwrap_or("").trim();
+            if entry.is_empty() {
+                continue;
+            }
+            if !(5..=40).contains(&entry.len()) || !entry.chars().all(|c| c.is_ascii_hexdigit()) {
+                return Err(format!("Invalid SHA-1 prefix on line {} of {}", number + 1, path));
+            }
+            lengths.insert(entry.len());
+            prefixes.insert(entry.to_ascii_uppercase());
+        }
+
+        info!("Loaded {} breached password prefixes from {}", prefixes.len(), path);
+        Ok(Self { prefixes, lengths })
+    }
+
+    pub fn contains(&self, password: &str) -> bool {
+        let digest = hex::encode_upper(Sha1::digest(password.as_bytes()));
+        self.lengths
+            .iter()
+            .any(|length| self.prefixes.contains(&digest[..*length]))
+    }
+}
+
+/// What the policy knows about the account the password is for.
+pub struct PasswordContext<'a> {
+    pub email: &'a str,
+    pub name: Option<&'a str>,
+}
+
+impl PasswordContext<'_> {
+    /// Pieces of personal information a password must not contain.
+    fn personal_terms(&self) -> Vec<String> {
+        let email = self.email.trim().to_lowercase();
+        let local_part = email.split('@').next().unwrap_or("").to_string();
+        let name_parts = self.name
+            .unwrap_or("")
+            .split_whitespace()
+            .map(str::to_lowercase);
+
+        std::iter::once(email.clone())
+            .chain(std::iter::once(local_part))
+            .chain(name_parts)
+            .filter(|term| term.chars().count() >= 3)
+            .collect()
+    }
+}
+
+pub struct PasswordPolicy {
+    pub min_length: usize,
+    pub max_length: usize,
+    pub required_classes: Vec<CharacterClass>,
+    pub reject_personal_info: bool,
+    /// zxcvbn score (0-4) the password must reach.
+    pub min_strength: u8,
+    pub breached: Option<BreachedPasswords>,
+}
+
+impl PasswordPolicy {
+    pub fn from_env() -> Self {
+        let required_classes = config::get_password_required_classes()
+            .iter()
+            .map(|class| {
+                CharacterClass::parse(class)
+                    .unwrap_or_else(|| panic!("Unknown PASSWORD_REQUIRED_CLASSES entry '{}'", class))
+            })
+            .collect();
+
+        Self {
+            min_length: config::get_password_min_length(),
+            max_length: config::get_password_max_length(),
+            required_classes,
+            reject_personal_info: config::get_password_reject_personal_info(),
+            min_strength: config::get_password_min_strength(),
+            breached: config::get_password_breached_list()
+                .map(|path| BreachedPasswords::load(&path).unwrap_or_else(|e| panic!("{}", e))),
+        }
+    }
+
+    /// Returns every rule the password breaks.
+    pub fn check(&self, password: &str, context: &PasswordContext) -> Vec<ValidationError> {
+        let length = password.chars().count();
+        if length < self.min_length {
+            return vec![error("length", format!("Password must be at least {} characters", self.min_length))];
+        }
+        if length > self.max_length {
+            // Too long to bother estimating strength
+            return vec![error("length", format!("Password must be at most {} characters", self.max_length))];
+        }
+
+        let mut errors = Vec::new();
+
+        for class in &self.required_classes {
+            if !password.chars().any(|c| class.matches(c)) {
+                errors.push(error("character_class", format!("Password must contain {}", class.describe())));
+            }
+        }
+
+        let personal_terms = context.personal_terms();
+        if self.reject_personal_info {
+            let lowered = password.to_lowercase();
+            if personal_terms.iter().any(|term| lowered.contains(term.as_str())) {
+                errors.push(error("personal_info", "Password must not contain your email or name".to_string()));
+            }
+        }
+
+        if self.min_strength > 0 {
+            let user_inputs: Vec<&str> = personal_terms.iter().map(String::as_str).collect();
+            let estimate = zxcvbn::zxcvbn(password, &user_inputs);
+            if u8::from(estimate.score()) < self.min_strength {
+                let hint = estimate
+                    .feedback()
+                    .and_then(|feedback| feedback.warning())
+                    .map_or_else(String::new, |warning| format!(": {}", warning));
+                errors.push(error("strength", format!("Password is too easy to guess{}", hint)));
+            }
+        }
+
+        if self.breached.as_ref().is_some_and(|breached| breached.contains(password)) {
+            errors.push(error(
+                "breached",
+                "Password has appeared in a data breach; please choose another".to_string(),
+            ));
+        }
+
+        errors
+    }
+
+    /// Checks `password` and reports failures against `field`, in the same
+    /// shape as request validation errors.
+    pub fn validate(&self, field: &'static str, password: &str, context: &PasswordContext) -> Result<(), ValidationErrors> {
+        let failures = self.check(password, context);
+        if failures.is_empty() {
+            return Ok(());
+        }
+
+        let mut errors = ValidationErrors::new();
+        for failure in failures {
+            errors.add(field, failure);
+        }
+        Err(errors)
+    }
+}
+
+fn error(code: &'static str, message: String) -> ValidationError {
+    let mut error = ValidationError::new(code);
+    error.message = Some(Cow::Owned(message));
+    error
+}
+
+lazy_static::lazy_static! {
+    pub static ref PASSWORD_POLICY: PasswordPolicy = PasswordPolicy::from_env();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTEXT: PasswordContext = PasswordContext { email: "ada.lovelace@example.com", name: Some("Ada Lovelace") };
+
+    /// Only the rules a test turns on apply.
+    fn base_policy() -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: 8,
+            max_length: 32,
+            required_classes: Vec::new(),
+            reject_personal_info: false,
+            min_strength: 0,
+            breached: None,
+        }
+    }
+
+    fn codes(policy: &PasswordPolicy, password: &str) -> Vec<String> {
+        policy.check(password, &CONTEXT).iter().map(|error| error.code.to_string()).collect()
+    }
+
+    #[test]
+    fn enforces_length_limits_in_characters() {
+        let policy = base_policy();
+        assert_eq!(codes(&policy, "short1"), ["length"]);
+        assert!(codes(&policy, "eight ch").is_empty());
+        assert!(codes(&policy, &"x".repeat(32)).is_empty());
+        assert_eq!(codes(&policy, &"x".repeat(33)), ["length"]);
+        // Counted in characters, not bytes
+        assert!(codes(&policy, "ééééééé1").is_empty());
+    }
+
+    #[test]
+    fn requires_each_character_class() {
+        let policy = PasswordPolicy {
+            required_classes: vec![CharacterClass::Lower, CharacterClass::Upper, CharacterClass::Digit, CharacterClass::Symbol],
+            ..base_policy()
+        };
+        assert!(codes(&policy, "Abcdefg1!").is_empty());
+        assert_eq!(codes(&policy, "abcdefgh"), ["character_class"; 3]);
+
+        let messages: Vec<String> = policy.check("ABCDEFG1", &CONTEXT)
+            .iter()
+            .filter_map(|error| error.message.as_ref().map(|message| message.to_string()))
+            .collect();
+        assert_eq!(messages, ["Password must contain a lowercase letter", "Password must contain a symbol"]);
+    }
+
+    #[test]
+    fn rejects_email_and_name() {
+        let policy = PasswordPolicy { reject_personal_info: true, ..base_policy() };
+        assert_eq!(codes(&policy, "x-Ada.Lovelace@Example.com"), ["personal_info"]);
+        assert_eq!(codes(&policy, "my ada.lovelace 1"), ["personal_info"]);
+        assert_eq!(codes(&policy, "LOVELACE-1234"), ["personal_info"]);
+        // Name parts under three characters are too common to reject
+        let short_name = PasswordContext { email: "x@example.com", name: Some("Al Lu") };
+        assert!(policy.check("always-lucky-42", &short_name).is_empty());
+
+        let allowed = PasswordPolicy { reject_personal_info: false, ..base_policy() };
+        assert!(codes(&allowed, "lovelace-1234").is_empty());
+    }
+
+    #[test]
+    fn requires_zxcvbn_strength() {
+        let policy = PasswordPolicy { min_strength: 3, ..base_policy() };
+        assert_eq!(codes(&policy, "password1"), ["strength"]);
+        assert_eq!(codes(&policy, "qwertyuiop"), ["strength"]);
+        assert!(codes(&policy, "Glacier-ferret-orbit-42").is_empty());
+        // Personal information counts against the score
+        assert_eq!(codes(&policy, "ada.lovelace42"), ["strength"]);
+        let stranger = PasswordContext { email: "x@example.com", name: None };
+        assert!(policy.check("ada.lovelace42", &stranger).is_empty());
+    }
+
+    #[test]
+    fn rejects_passwords_matching_a_breached_prefix() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/password_policy/breached.txt");
+        let policy = PasswordPolicy { breached: Some(BreachedPasswords::load(path).unwrap()), ..base_policy() };
+
+        // Matched by a five-character prefix, and by a full lowercase hash in
+        // `HASH:count` form
+        assert_eq!(codes(&policy, "Glacier-ferret-orbit-42"), ["breached"]);
+        assert_eq!(codes(&policy, "Tundra-velvet-quarry-77"), ["breached"]);
+        assert!(codes(&policy, "Harbor-lantern-sierra-19").is_empty());
+    }
+}
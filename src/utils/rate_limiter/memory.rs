@@ -0,0 +1,101 @@
+use async_trait::async_trait;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Instant;
+use log::debug;
+use crate::utils::error::AppError;
+use super::{gcra, Quota, RateLimitDecision, RateLimitStore};
+
+const SHARD_COUNT: usize = 16;
+
+/// Per-key state is the GCRA theoretical arrival time, in milliseconds since
+/// the store was created.
+type Shard = Mutex<HashMap<String, u64>>;
+
+/// In-process store. Limits are per replica and lost on restart.
+///
+/// Keys are spread over independently locked shards, and the store tracks at
+/// most `max_keys` keys; idle keys are removed by the sweeper.
+pub struct MemoryStore {
+    shards: Vec<Shard>,
+    max_keys_per_shard: usize,
+    started: Instant,
+}
+
+impl MemoryStore {
+    pub fn new(max_keys: usize) -> Self {
+        Self {
+            shards: (0..SHARD_COUNT).map(|_| Mutex::new(HashMap::new())).collect(),
+            max_keys_per_shard: max_keys.div_ceil(SHARD_COUNT).max(1),
+            started: Instant::now(),
+        }
+    }
+
+    fn now_ms(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64
+    }
+
+    fn shard(&self, key: &str) -> MutexGuard<'_, HashMap<String, u64>> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let shard = &self.shards[hasher.finish() as usize % SHARD_COUNT];
+        shard.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for MemoryStore {
+    async fn check(&self, key: &str, quota: &Quota) -> Result<RateLimitDecision, AppError> {
+        let now = self.now_ms();
+        let mut shard = self.shard(key);
+        let (decision, new_tat) = gcra(now, shard.get(key).copied(), quota);
+
+        if let Some(new_tat) = new_tat {
+            if !shard.contains_key(key) && shard.len() >= self.max_keys_per_shard {
+                make_room(&mut shard, now, self.max_keys_per_shard);
+            }
+            shard.insert(key.to_string(), new_tat);
+        }
+
+        Ok(decision)
+    }
+
+    async fn reset(&self, key: &str) -> Result<(), AppError> {
+        self.shard(key).remove(key);
+        Ok(())
+    }
+
+    async fn sweep(&self) -> Result<usize, AppError> {
+        let now = self.now_ms();
+        Ok(self
+            .shards
+            .iter()
+            .map(|shard| {
+                let mut shard = shard.lock().unwrap_or_else(|e| e.into_inner());
+                let before = shard.len();
+                shard.retain(|_, tat| *tat > now);
+                before - shard.len()
+            })
+            .sum())
+    }
+}
+
+/// Makes room in a full shard. Keys whose bucket has refilled go first; if
+/// that is not enough, the tenth of keys closest to refilled (which loses the
+/// least state) is dropped in one pass so the cost is amortised over inserts.
+fn make_room(shard: &mut HashMap<String, u64>, now: u64, max_keys: usize) {
+    shard.retain(|_, tat| *tat > now);
+    if shard.len() < max_keys {
+        return;
+    }
+
+    let mut tats: Vec<u64> = shard.values().copied().collect();
+    let cutoff_index = (tats.len() / 10).max(1) - 1;
+    let (_, cutoff, _) = tats.select_nth_unstable(cutoff_index);
+    let cutoff = *cutoff;
+    let before = shard.len();
+    shard.retain(|_, tat| *tat > cutoff);
+    debug!("Rate limiter shard full, evicted {} keys", before - shard.len());
+}
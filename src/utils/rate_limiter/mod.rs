@@ -0,0 +1,253 @@
+use async_trait::async_trait;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+use log::{debug, error, info, warn};
+use sqlx::PgPool;
+use crate::config;
+use crate::utils::error::AppError;
+
+pub mod memory;
+pub mod postgres;
+pub mod redis;
+
+pub use self::memory::MemoryStore;
+pub use self::postgres::PostgresStore;
+pub use self::redis::RedisStore;
+
+/// Outcome of a rate limit check, used to fill the `RateLimit-*` headers.
+#[derive(Debug, Clone, Copy)]
+pub struct RateLimitDecision {
+    pub allowed: bool,
+    pub limit: usize,
+    pub remaining: usize,
+    /// Time until the full quota is available again.
+    pub reset_after: Duration,
+    /// Time until the next request will be allowed; zero when allowed.
+    pub retry_after: Duration,
+}
+
+/// A burst of `max_requests`, refilled evenly over `window`.
+#[derive(Debug, Clone, Copy)]
+pub struct Quota {
+    pub max_requests: usize,
+    pub window: Duration,
+}
+
+impl Quota {
+    pub fn new(max_requests: usize, window_secs: u64) -> Self {
+        Self { max_requests: max_requests.max(1), window: Duration::from_secs(window_secs) }
+    }
+
+    pub fn window_ms(&self) -> u64 {
+        self.window.as_millis() as u64
+    }
+
+    /// Time for one request to be refilled.
+    pub fn interval_ms(&self) -> u64 {
+        (self.window_ms() / self.max_requests as u64).max(1)
+    }
+
+    /// Builds the decision from the time still "used" in the bucket
+    /// (theoretical arrival time minus now) after the check.
+    pub fn decision(&self, allowed: bool, used_ms: u64, retry_ms: u64) -> RateLimitDecision {
+        let remaining = if allowed {
+            (self.window_ms().saturating_sub(used_ms) / self.interval_ms()) as usize
+        } else {
+            0
+        };
+        RateLimitDecision {
+            allowed,
+            limit: self.max_requests,
+            remaining,
+            reset_after: Duration::from_millis(used_ms),
+            retry_after: Duration::from_millis(retry_ms),
+        }
+    }
+}
+
+/// GCRA (generic cell rate algorithm) step shared by the stores.
+///
+/// `tat` is the key's theoretical arrival time in milliseconds: the instant
+/// its bucket would be completely refilled. Returns the decision and, when
+/// the request is allowed, the TAT to store.
+pub fn gcra(now_ms: u64, tat: Option<u64>, quota: &Quota) -> (RateLimitDecision, Option<u64>) {
+    let tat = tat.map_or(now_ms, |tat| tat.max(now_ms));
+    let new_tat = tat + quota.interval_ms();
+    let allow_at = new_tat.saturating_sub(quota.window_ms());
+
+    if now_ms < allow_at {
+        return (quota.decision(false, tat - now_ms, allow_at - now_ms), None);
+    }
+
+    (quota.decision(true, new_tat - now_ms, 0), Some(new_tat))
+}
+
+/// Where rate limit state lives. Stores must apply [`gcra`] atomically per
+/// key so that replicas sharing a store share the limit.
+#[async_trait]
+pub trait RateLimitStore: Send + Sync {
+    async fn check(&self, key: &str, quota: &Quota) -> Result<RateLimitDecision, AppError>;
+
+    async fn reset(&self, key: &str) -> Result<(), AppError>;
+
+    /// Drops keys whose bucket has fully refilled; returns how many.
+    async fn sweep(&self) -> Result<usize, AppError> {
+        Ok(0)
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimiter {
+    store: Arc<dyn RateLimitStore>,
+    namespace: String,
+    quota: Quota,
+    /// What is being counted, for the 429 message.
+    label: String,
+}
+
+impl RateLimiter {
+    pub fn new(namespace: &str, max_attempts: usize, window_secs: u64) -> Self {
+        Self::with_store(store(), namespace, Quota::new(max_attempts, window_secs))
+    }
+
+    pub fn with_store(store: Arc<dyn RateLimitStore>, namespace: &str, quota: Quota) -> Self {
+        Self { store, namespace: namespace.to_lowercase(), quota, label: "requests".to_string() }
+    }
+
+    /// Names what the limiter counts in its error message ("Too many
+    /// <label>"); defaults to "requests".
+    pub fn with_label(mut self, label: &str) -> Self {
+        self.label = label.to_string();
+        self
+    }
+
+    /// Builds a limiter from `RATE_LIMIT_<SCOPE>` (`<max>/<window_secs>`),
+    /// falling back to the given defaults.
+    pub fn from_env(scope: &str, max_attempts: usize, window_secs: u64) -> Self {
+        let (max_attempts, window_secs) = config::get_rate_limit(scope)
+            .unwrap_or((max_attempts, window_secs));
+        Self::new(scope, max_attempts, window_secs)
+    }
+
+    fn store_key(&self, key: &str) -> String {
+        format!("{}:{}", self.namespace, key)
+    }
+
+    /// Records an attempt for `key` if it is within the limit.
+    ///
+    /// Fails open: if the store is unreachable the request is allowed and
+    /// the error logged, so a Redis or database outage does not take the
+    /// API down with it.
+    pub async fn check(&self, key: &str) -> RateLimitDecision {
+        match self.store.check(&self.store_key(key), &self.quota).await {
+            Ok(decision) => decision,
+            Err(e) => {
+                error!("Rate limit store error for {}: {}", self.namespace, e);
+                self.quota.decision(true, 0, 0)
+            }
+        }
+    }
+
+    pub async fn check_rate_limit(&self, key: &str) -> Result<(), AppError> {
+        let decision = self.check(key).await;
+        if !decision.allowed {
+            warn!("Rate limit exceeded for {}", key);
+            let retry_after = decision.retry_after.as_secs_f64().ceil() as u64;
+            return Err(AppError::rate_limited(
+                format!("Too many {}. Please try again after {} seconds", self.label, retry_after),
+                retry_after,
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub async fn reset(&self, key: &str) {
+        if let Err(e) = self.store.reset(&self.store_key(key)).await {
+            error!("Failed to reset rate limit for {}: {}", key, e);
+        }
+    }
+}
+
+static STORE: OnceLock<Arc<dyn RateLimitStore>> = OnceLock::new();
+
+/// Selects the store from `RATE_LIMIT_BACKEND` (`memory`, `postgres` or
+/// `redis`). Must run before any limiter is created.
+pub async fn init_store(pool: &PgPool) -> Result<(), AppError> {
+    let store: Arc<dyn RateLimitStore> = match config::get_rate_limit_backend().as_str() {
+        "memory" => Arc::new(MemoryStore::new(config::get_rate_limit_max_keys())),
+        "postgres" => Arc::new(PostgresStore::new(pool.clone())),
+        "redis" => Arc::new(RedisStore::connect(&config::get_redis_url()).await?),
+        other => return Err(AppError::internal(format!("Unknown RATE_LIMIT_BACKEND '{}'", other))),
+    };
+    info!("Using {} rate limit backend", config::get_rate_limit_backend());
+
+    if STORE.set(store).is_err() {
+        warn!("Rate limit store already initialised");
+    }
+    Ok(())
+}
+
+fn store() -> Arc<dyn RateLimitStore> {
+    STORE
+        .get_or_init(|| Arc::new(MemoryStore::new(config::get_rate_limit_max_keys())))
+        .clone()
+}
+
+/// Periodically evicts idle keys (whose bucket has fully refilled). An
+/// evicted key behaves exactly like one never seen before.
+pub fn start_sweeper(interval: Duration) {
+    actix_web::rt::spawn(async move {
+        let mut ticker = actix_web::rt::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match store().sweep().await {
+                Ok(0) => {},
+                Ok(evicted) => debug!("Rate limiter sweeper evicted {} idle keys", evicted),
+                Err(e) => error!("Rate limiter sweep failed: {}", e),
+            }
+        }
+    });
+}
+
+/// Returns the limiter shared by every worker for `scope`, creating it from
+/// `RATE_LIMIT_<SCOPE>` on first use.
+pub fn scope_limiter(scope: &str, max_attempts: usize, window_secs: u64) -> RateLimiter {
+    let mut limiters = SCOPE_LIMITERS.lock().unwrap_or_else(|e| e.into_inner());
+    limiters
+        .entry(scope.to_string())
+        .or_insert_with(|| RateLimiter::from_env(scope, max_attempts, window_secs))
+        .clone()
+}
+
+// Create a static rate limiter for login
+lazy_static::lazy_static! {
+    pub static ref LOGIN_LIMITER: RateLimiter = RateLimiter::from_env("LOGIN", 5, 300) // 5 attempts per 5 minutes
+        .with_label("login attempts");
+    pub static ref MAGIC_LINK_LIMITER: RateLimiter = RateLimiter::from_env("MAGIC_LINK", 3, 900) // 3 emails per 15 minutes
+        .with_label("sign-in link requests");
+    pub static ref UNLOCK_LIMITER: RateLimiter = RateLimiter::from_env("UNLOCK", 3, 900) // 3 emails per 15 minutes
+        .with_label("unlock link requests");
+    static ref SCOPE_LIMITERS: Mutex<HashMap<String, RateLimiter>> = Mutex::new(HashMap::new());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[actix_web::test]
+    async fn error_names_what_the_limiter_counts() {
+        let limiter = RateLimiter::with_store(Arc::new(MemoryStore::new(10)), "test", Quota::new(1, 60))
+            .with_label("widget orders");
+
+        limiter.check_rate_limit("key").await.unwrap();
+        match limiter.check_rate_limit("key").await {
+            Err(AppError::RateLimitExceeded { message, retry_after }) => {
+                assert!(message.starts_with("Too many widget orders."), "{}", message);
+                assert_eq!(retry_after, 60);
+            },
+            other => panic!("expected a rate limit error, got {:?}", other),
+        }
+    }
+}
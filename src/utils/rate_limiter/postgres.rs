@@ -0,0 +1,80 @@
+use async_trait::async_trait;
+use sqlx::PgPool;
+use tracing::instrument;
+use crate::utils::error::AppError;
+use super::{gcra, Quota, RateLimitDecision, RateLimitStore};
+
+/// Stores limits in the `rate_limits` table so every replica shares them.
+///
+/// Each check locks the key's row for the duration of a short transaction
+/// and uses the database clock, so replicas with skewed clocks still agree.
+pub struct PostgresStore {
+    pool: PgPool,
+}
+
+impl PostgresStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for PostgresStore {
+    #[instrument(name = "db.check_rate_limit", skip_all, fields(db.system = "postgresql", db.operation = "UPDATE"))]
+    async fn check(&self, key: &str, quota: &Quota) -> Result<RateLimitDecision, AppError> {
+        let mut tx = self.pool.begin().await.map_err(AppError::DatabaseError)?;
+
+        sqlx::query!(
+            "INSERT INTO rate_limits (key, tat_ms) VALUES ($1, 0) ON CONFLICT (key) DO NOTHING",
+            key
+        )
+        .execute(&mut *tx)
+        .await
+        .map_err(AppError::DatabaseError)?;
+
+        let row = sqlx::query!(
+            r#"
+            SELECT tat_ms, (EXTRACT(EPOCH FROM clock_timestamp()) * 1000)::BIGINT AS "now_ms!"
+            FROM rate_limits
+            WHERE key = $1
+            FOR UPDATE
+            "#,
+            key
+        )
+        .fetch_one(&mut *tx)
+        .await
+        .map_err(AppError::DatabaseError)?;
+
+        let (decision, new_tat) = gcra(row.now_ms as u64, Some(row.tat_ms as u64), quota);
+
+        if let Some(new_tat) = new_tat {
+            sqlx::query!("UPDATE rate_limits SET tat_ms = $2 WHERE key = $1", key, new_tat as i64)
+                .execute(&mut *tx)
+                .await
+                .map_err(AppError::DatabaseError)?;
+        }
+
+        tx.commit().await.map_err(AppError::DatabaseError)?;
+        Ok(decision)
+    }
+
+    #[instrument(name = "db.reset_rate_limit", skip_all, fields(db.system = "postgresql", db.operation = "DELETE"))]
+    async fn reset(&self, key: &str) -> Result<(), AppError> {
+        sqlx::query!("DELETE FROM rate_limits WHERE key = $1", key)
+            .execute(&self.pool)
+            .await
+            .map_err(AppError::DatabaseError)?;
+        Ok(())
+    }
+
+    #[instrument(name = "db.sweep_rate_limits", skip_all, fields(db.system = "postgresql", db.operation = "DELETE"))]
+    async fn sweep(&self) -> Result<usize, AppError> {
+        let result = sqlx::query!(
+            "DELETE FROM rate_limits WHERE tat_ms <= (EXTRACT(EPOCH FROM clock_timestamp()) * 1000)::BIGINT"
+        )
+        .execute(&self.pool)
+        .await
+        .map_err(AppError::DatabaseError)?;
+        Ok(result.rows_affected() as usize)
+    }
+}
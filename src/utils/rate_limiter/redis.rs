@@ -0,0 +1,69 @@
+use async_trait::async_trait;
+use redis::aio::ConnectionManager;
+use redis::{AsyncCommands, Script};
+use tracing::instrument;
+use crate::utils::error::AppError;
+use super::{Quota, RateLimitDecision, RateLimitStore};
+
+/// The GCRA step from [`super::gcra`], run server side so concurrent checks
+/// from different replicas are atomic. Uses the server clock, and expires the
+/// key once its bucket has refilled so no sweeping is needed.
+///
+/// Returns `{allowed, used_ms, retry_ms}`.
+const GCRA_SCRIPT: &str = r#"
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local interval = tonumber(ARGV[1])
+local window = tonumber(ARGV[2])
+
+local tat = tonumber(redis.call('GET', KEYS[1])) or now
+if tat < now then tat = now end
+local new_tat = tat + interval
+local allow_at = new_tat - window
+
+if now < allow_at then
+    return {0, tat - now, allow_at - now}
+end
+
+redis.call('SET', KEYS[1], new_tat, 'PX', new_tat - now)
+return {1, new_tat - now, 0}
+"#;
+
+/// Stores limits in Redis (or anything speaking the Redis protocol, such as
+/// Valkey or KeyDB) so every replica shares them.
+pub struct RedisStore {
+    connection: ConnectionManager,
+    script: Script,
+}
+
+impl RedisStore {
+    pub async fn connect(url: &str) -> Result<Self, AppError> {
+        let client = redis::Client::open(url).map_err(AppError::internal)?;
+        let connection = ConnectionManager::new(client).await.map_err(AppError::internal)?;
+        Ok(Self { connection, script: Script::new(GCRA_SCRIPT) })
+    }
+}
+
+#[async_trait]
+impl RateLimitStore for RedisStore {
+    #[instrument(name = "redis.check_rate_limit", skip_all, fields(db.system = "redis", db.operation = "EVALSHA"))]
+    async fn check(&self, key: &str, quota: &Quota) -> Result<RateLimitDecision, AppError> {
+        let mut connection = self.connection.clone();
+        let (allowed, used_ms, retry_ms): (i64, i64, i64) = self
+            .script
+            .key(key)
+            .arg(quota.interval_ms())
+            .arg(quota.window_ms())
+            .invoke_async(&mut connection)
+            .await
+            .map_err(AppError::internal)?;
+
+        Ok(quota.decision(allowed == 1, used_ms.max(0) as u64, retry_ms.max(0) as u64))
+    }
+
+    #[instrument(name = "redis.reset_rate_limit", skip_all, fields(db.system = "redis", db.operation = "DEL"))]
+    async fn reset(&self, key: &str) -> Result<(), AppError> {
+        let mut connection = self.connection.clone();
+        connection.del::<_, ()>(key).await.map_err(AppError::internal)
+    }
+}
@@ -60,6 +60,15 @@ pub trait ResponseBuilder {
         }.into_response()
     }
 
+    fn accepted<T: Serialize>(data: T) -> HttpResponse {
+        ApiResponse {
+            status: "success".to_string(),
+            code: StatusCode::ACCEPTED.as_u16(),
+            message: None,
+            data: Some(data),
+        }.into_response()
+    }
+
     fn bad_request(message: &str) -> HttpResponse {
         ApiResponse::<()>::error(StatusCode::BAD_REQUEST, message).into_response()
     }
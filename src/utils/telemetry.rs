@@ -0,0 +1,124 @@
+use std::io::Write;
+use std::time::UNIX_EPOCH;
+
+use opentelemetry::global;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_sdk::error::OTelSdkResult;
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{SdkTracerProvider, SpanData, SpanExporter};
+use opentelemetry_sdk::Resource;
+use opentelemetry_otlp::WithExportConfig;
+use serde_json::json;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::EnvFilter;
+
+use crate::config;
+
+/// Writes each finished span to stdout as a single JSON line.
+#[derive(Debug, Default)]
+pub struct StdoutSpanExporter;
+
+impl SpanExporter for StdoutSpanExporter {
+    async fn export(&self, batch: Vec<SpanData>) -> OTelSdkResult {
+        let mut stdout = std::io::stdout().lock();
+        for span in batch {
+            let start = span.start_time.duration_since(UNIX_EPOCH).unwrap_or_default();
+            let end = span.end_time.duration_since(UNIX_EPOCH).unwrap_or_default();
+            let attributes: serde_json::Map<String, serde_json::Value> = span
+                .attributes
+                .iter()
+                .map(|kv| (kv.key.to_string(), json!(kv.value.to_string())))
+                .collect();
+
+            let line = json!({
+                "trace_id": span.span_context.trace_id().to_string(),
+                "span_id": span.span_context.span_id().to_string(),
+                "parent_span_id": span.parent_span_id.to_string(),
+                "name": span.name,
+                "duration_us": end.saturating_sub(start).as_micros() as u64,
+                "status": format!("{:?}", span.status),
+                "attributes": attributes,
+            });
+            let _ = writeln!(stdout, "{}", line);
+        }
+        Ok(())
+    }
+}
+
+/// Installs the global tracing subscriber and OpenTelemetry pipeline.
+///
+/// Returns the tracer provider so `main` can flush it on shutdown, or `None`
+/// when tracing is disabled (`OTEL_TRACES_EXPORTER=none`).
+pub fn init_tracing() -> Option<SdkTracerProvider> {
+    let provider = build_tracer_provider();
+    if provider.is_none() {
+        // Without any subscriber `tracing` falls back to emitting spans as
+        // `log` records, which would duplicate the access log.
+        let _ = tracing::subscriber::set_global_default(tracing::subscriber::NoSubscriber::default());
+    }
+    provider
+}
+
+fn build_tracer_provider() -> Option<SdkTracerProvider> {
+    global::set_text_map_propagator(TraceContextPropagator::new());
+
+    let resource = Resource::builder()
+        .with_service_name(config::get_service_name())
+        .build();
+
+    let provider = match config::get_traces_exporter().as_str() {
+        "otlp" => {
+            let endpoint = config::get_otlp_endpoint();
+            let exporter = match opentelemetry_otlp::SpanExporter::builder()
+                .with_http()
+                .with_endpoint(endpoint.clone())
+                .build()
+            {
+                Ok(exporter) => exporter,
+                Err(e) => {
+                    log::error!("Failed to build OTLP exporter for {}: {}", endpoint, e);
+                    return None;
+                }
+            };
+            log::info!("Exporting traces via OTLP to {}", endpoint);
+            SdkTracerProvider::builder()
+                .with_batch_exporter(exporter)
+                .with_resource(resource)
+                .build()
+        },
+        "stdout" => {
+            log::info!("Exporting traces to stdout");
+            SdkTracerProvider::builder()
+                .with_simple_exporter(StdoutSpanExporter)
+                .with_resource(resource)
+                .build()
+        },
+        "none" => return None,
+        other => {
+            log::warn!("Unknown OTEL_TRACES_EXPORTER '{}', tracing disabled", other);
+            return None;
+        }
+    };
+
+    let tracer = provider.tracer(config::get_service_name());
+    global::set_tracer_provider(provider.clone());
+
+    // `log` records keep going through env_logger; the subscriber only
+    // carries spans to the OpenTelemetry layer.
+    let subscriber = tracing_subscriber::registry()
+        .with(EnvFilter::new(config::get_traces_filter()))
+        .with(tracing_opentelemetry::layer().with_tracer(tracer));
+    if let Err(e) = tracing::subscriber::set_global_default(subscriber) {
+        log::error!("Failed to install tracing subscriber: {}", e);
+    }
+
+    Some(provider)
+}
+
+pub fn shutdown_tracing(provider: Option<SdkTracerProvider>) {
+    if let Some(provider) = provider {
+        if let Err(e) = provider.shutdown() {
+            log::error!("Failed to flush traces on shutdown: {}", e);
+        }
+    }
+}
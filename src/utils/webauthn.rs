@@ -0,0 +1,595 @@
+use std::io::Cursor;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ciborium::Value;
+use log::debug;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use crate::config;
+
+/// COSE algorithm identifiers we accept, in order of preference.
+pub const COSE_ES256: i64 = -7;
+pub const COSE_EDDSA: i64 = -8;
+pub const COSE_RS256: i64 = -257;
+pub const SUPPORTED_ALGORITHMS: &[i64] = &[COSE_ES256, COSE_EDDSA, COSE_RS256];
+
+const FLAG_USER_PRESENT: u8 = 0x01;
+const FLAG_USER_VERIFIED: u8 = 0x04;
+const FLAG_ATTESTED_CREDENTIAL: u8 = 0x40;
+
+/// Longest credential ID the spec allows.
+const MAX_CREDENTIAL_ID_LEN: usize = 1023;
+
+/// Smallest RSA modulus accepted, in bits.
+const MIN_RSA_MODULUS_BITS: usize = 2048;
+/// The only RSA public exponent accepted (F4), as authenticators use.
+const RSA_EXPONENT: u32 = 65537;
+
+pub fn b64(bytes: &[u8]) -> String {
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Decodes base64url, with or without padding, as browsers send it.
+pub fn decode_b64(value: &str) -> Result<Vec<u8>, String> {
+    URL_SAFE_NO_PAD
+        .decode(value.trim_end_matches('='))
+        .map_err(|e| format!("invalid base64url: {}", e))
+}
+
+/// `CollectedClientData`, the JSON the browser signs over.
+#[derive(Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    kind: String,
+    challenge: String,
+    origin: String,
+    #[serde(default, rename = "crossOrigin")]
+    cross_origin: bool,
+}
+
+/// The parts of `authenticatorData` we use.
+struct AuthenticatorData {
+    sign_count: u32,
+    attested: Option<AttestedCredential>,
+}
+
+struct AttestedCredential {
+    credential_id: Vec<u8>,
+    /// The credential public key as a CBOR-encoded COSE_Key.
+    public_key: Vec<u8>,
+}
+
+/// A credential whose registration verified.
+pub struct NewCredential {
+    pub credential_id: Vec<u8>,
+    pub public_key: Vec<u8>,
+    pub sign_count: u32,
+}
+
+/// What was stored of a credential when it was registered.
+pub struct StoredCredential<'a> {
+    /// The CBOR-encoded COSE_Key.
+    pub public_key: &'a [u8],
+    /// The signature counter from its last use.
+    pub sign_count: u32,
+}
+
+/// A public key from a COSE_Key, ready to check assertion signatures.
+enum CoseKey {
+    Es256(p256::ecdsa::VerifyingKey),
+    EdDsa(ed25519_dalek::VerifyingKey),
+    Rs256(rsa::pkcs1v15::VerifyingKey<Sha256>),
+}
+
+fn map_entry(map: &[(Value, Value)], label: i64) -> Option<&Value> {
+    map.iter()
+        .find(|(key, _)| key.as_integer().map(i128::from) == Some(i128::from(label)))
+        .map(|(_, value)| value)
+}
+
+impl CoseKey {
+    fn from_cbor(bytes: &[u8]) -> Result<Self, String> {
+        let value: Value = ciborium::de::from_reader(bytes).map_err(|e| format!("invalid COSE key: {}", e))?;
+        let map = value.as_map().ok_or("COSE key is not a map")?;
+        let int = |label| map_entry(map, label).and_then(Value::as_integer).map(i128::from);
+        let bytes = |label| {
+            map_entry(map, label)
+                .and_then(Value::as_bytes)
+                .map(Vec::as_slice)
+                .ok_or_else(|| format!("COSE key lacks parameter {}", label))
+        };
+
+        // kty 2 = EC2, 1 = OKP, 3 = RSA
+        match (int(1), int(3)) {
+            (Some(2), Some(alg)) if alg == i128::from(COSE_ES256) => {
+                if int(-1) != Some(1) {
+                    return Err("ES256 key is not on P-256".to_string());
+                }
+                let (x, y) = (bytes(-2)?, bytes(-3)?);
+                if x.len() != 32 || y.len() != 32 {
+                    return Err("invalid P-256 coordinates".to_string());
+                }
+                let point = p256::EncodedPoint::from_affine_coordinates(
+                    p256::FieldBytes::from_slice(x),
+                    p256::FieldBytes::from_slice(y),
+                    false,
+                );
+                p256::ecdsa::VerifyingKey::from_encoded_point(&point)
+                    .map(CoseKey::Es256)
+                    .map_err(|e| format!("invalid P-256 key: {}", e))
+            },
+            (Some(1), Some(alg)) if alg == i128::from(COSE_EDDSA) => {
+                if int(-1) != Some(6) {
+                    return Err("EdDSA key is not Ed25519".to_string());
+                }
+                let x: [u8; 32] = bytes(-2)?.try_into().map_err(|_| "invalid Ed25519 key length")?;
+                ed25519_dalek::VerifyingKey::from_bytes(&x)
+                    .map(CoseKey::EdDsa)
+                    .map_err(|e| format!("invalid Ed25519 key: {}", e))
+            },
+            (Some(3), Some(alg)) if alg == i128::from(COSE_RS256) => {
+                let (n, e) = (rsa::BigUint::from_bytes_be(bytes(-1)?), rsa::BigUint::from_bytes_be(bytes(-2)?));
+                if n.bits() < MIN_RSA_MODULUS_BITS {
+                    return Err(format!("RSA modulus is shorter than {} bits", MIN_RSA_MODULUS_BITS));
+                }
+                if e != rsa::BigUint::from(RSA_EXPONENT) {
+                    return Err("RSA public exponent is not 65537".to_string());
+                }
+                let key = rsa::RsaPublicKey::new(n, e).map_err(|e| format!("invalid RSA key: {}", e))?;
+                Ok(CoseKey::Rs256(rsa::pkcs1v15::VerifyingKey::new(key)))
+            },
+            (kty, alg) => Err(format!("unsupported COSE key type {:?} with algorithm {:?}", kty, alg)),
+        }
+    }
+
+    fn verify(&self, message: &[u8], signature: &[u8]) -> Result<(), String> {
+        use p256::ecdsa::signature::Verifier;
+
+        let verified = match self {
+            CoseKey::Es256(key) => p256::ecdsa::Signature::from_der(signature)
+                .and_then(|signature| key.verify(message, &signature)),
+            CoseKey::EdDsa(key) => ed25519_dalek::Signature::from_slice(signature)
+                .and_then(|signature| key.verify_strict(message, &signature)),
+            CoseKey::Rs256(key) => rsa::pkcs1v15::Signature::try_from(signature)
+                .and_then(|signature| key.verify(message, &signature)),
+        };
+        verified.map_err(|_| "signature does not verify".to_string())
+    }
+}
+
+/// This service as a WebAuthn relying party, configured by `WEBAUTHN_*`.
+///
+/// Attestation is not evaluated: we ask for none, so a credential is trusted
+/// because the signed-in user registered it, not because of its make.
+pub struct RelyingParty {
+    pub id: String,
+    pub name: String,
+    origins: Vec<String>,
+}
+
+impl RelyingParty {
+    pub fn from_env() -> Self {
+        Self {
+            id: config::get_webauthn_rp_id(),
+            name: config::get_webauthn_rp_name(),
+            origins: config::get_webauthn_origins(),
+        }
+    }
+
+    fn check_client_data(&self, client_data_json: &[u8], kind: &str, challenge: &[u8]) -> Result<(), String> {
+        let client_data: ClientData = serde_json::from_slice(client_data_json)
+            .map_err(|e| format!("invalid clientDataJSON: {}", e))?;
+        if client_data.kind != kind {
+            return Err(format!("expected {} but got {}", kind, client_data.kind));
+        }
+        if decode_b64(&client_data.challenge)? != challenge {
+            return Err("challenge mismatch".to_string());
+        }
+        if !self.origins.contains(&client_data.origin) {
+            return Err(format!("unexpected origin {}", client_data.origin));
+        }
+        if client_data.cross_origin {
+            return Err("cross-origin ceremonies are not allowed".to_string());
+        }
+        Ok(())
+    }
+
+    fn parse_authenticator_data(&self, data: &[u8], require_user_verification: bool) -> Result<AuthenticatorData, String> {
+        if data.len() < 37 {
+            return Err("authenticator data is too short".to_string());
+        }
+        if data[..32] != *Sha256::digest(self.id.as_bytes()) {
+            return Err("RP ID hash mismatch".to_string());
+        }
+        let flags = data[32];
+        if flags & FLAG_USER_PRESENT == 0 {
+            return Err("user was not present".to_string());
+        }
+        if require_user_verification && flags & FLAG_USER_VERIFIED == 0 {
+            return Err("user was not verified".to_string());
+        }
+        let sign_count = u32::from_be_bytes([data[33], data[34], data[35], data[36]]);
+
+        let attested = if flags & FLAG_ATTESTED_CREDENTIAL != 0 {
+            // AAGUID (16 bytes), credential ID length (2), ID, COSE_Key
+            let rest = &data[37..];
+            if rest.len() < 18 {
+                return Err("attested credential data is too short".to_string());
+            }
+            let id_len = usize::from(u16::from_be_bytes([rest[16], rest[17]]));
+            if id_len > MAX_CREDENTIAL_ID_LEN || rest.len() < 18 + id_len {
+                return Err("invalid credential ID length".to_string());
+            }
+            let key_bytes = &rest[18 + id_len..];
+            let mut cursor = Cursor::new(key_bytes);
+            ciborium::de::from_reader::<Value, _>(&mut cursor)
+                .map_err(|e| format!("invalid credential public key: {}", e))?;
+            Some(AttestedCredential {
+                credential_id: rest[18..18 + id_len].to_vec(),
+                public_key: key_bytes[..cursor.position() as usize].to_vec(),
+            })
+        } else {
+            None
+        };
+
+        Ok(AuthenticatorData { sign_count, attested })
+    }
+
+    /// Verifies the response to `navigator.credentials.create()` and returns
+    /// the new credential.
+    pub fn verify_registration(
+        &self,
+        client_data_json: &[u8],
+        attestation_object: &[u8],
+        challenge: &[u8],
+        require_user_verification: bool,
+    ) -> Result<NewCredential, String> {
+        self.check_client_data(client_data_json, "webauthn.create", challenge)?;
+
+        let attestation: Value = ciborium::de::from_reader(attestation_object)
+            .map_err(|e| format!("invalid attestation object: {}", e))?;
+        let attestation = attestation.as_map().ok_or("attestation object is not a map")?;
+        let field = |name: &str| {
+            attestation.iter()
+                .find(|(key, _)| key.as_text() == Some(name))
+                .map(|(_, value)| value)
+        };
+        let auth_data = field("authData")
+            .and_then(Value::as_bytes)
+            .ok_or("attestation object lacks authData")?;
+        debug!("WebAuthn attestation format: {:?}", field("fmt").and_then(Value::as_text));
+
+        let auth_data = self.parse_authenticator_data(auth_data, require_user_verification)?;
+        let credential = auth_data.attested.ok_or("no attested credential data")?;
+        // Fails early on keys we could not verify assertions with
+        CoseKey::from_cbor(&credential.public_key)?;
+
+        Ok(NewCredential {
+            credential_id: credential.credential_id,
+            public_key: credential.public_key,
+            sign_count: auth_data.sign_count,
+        })
+    }
+
+    /// Verifies the response to `navigator.credentials.get()` against the
+    /// stored public key and signature counter, and returns the
+    /// authenticator's new counter.
+    pub fn verify_assertion(
+        &self,
+        client_data_json: &[u8],
+        authenticator_data: &[u8],
+        signature: &[u8],
+        challenge: &[u8],
+        credential: StoredCredential<'_>,
+        require_user_verification: bool,
+    ) -> Result<u32, String> {
+        self.check_client_data(client_data_json, "webauthn.get", challenge)?;
+        let auth_data = self.parse_authenticator_data(authenticator_data, require_user_verification)?;
+
+        let mut signed = authenticator_data.to_vec();
+        signed.extend_from_slice(&Sha256::digest(client_data_json));
+        CoseKey::from_cbor(credential.public_key)?.verify(&signed, signature)?;
+
+        // Authenticators without a counter always report 0
+        let sign_count = auth_data.sign_count;
+        if (sign_count != 0 || credential.sign_count != 0) && sign_count <= credential.sign_count {
+            return Err("signature counter did not increase; the authenticator may have been cloned".to_string());
+        }
+
+        Ok(sign_count)
+    }
+}
+
+lazy_static::lazy_static! {
+    pub static ref RELYING_PARTY: RelyingParty = RelyingParty::from_env();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rsa::pkcs8::DecodePrivateKey;
+    use rsa::signature::{SignatureEncoding, Signer};
+    use rsa::traits::PublicKeyParts;
+
+    const RP_ID: &str = "example.com";
+    const ORIGIN: &str = "https://example.com";
+    const CHALLENGE: &[u8] = b"0123456789abcdef0123456789abcdef";
+    const CREDENTIAL_ID: &[u8] = b"software-credential";
+
+    /// A software authenticator's key, loaded from the committed fixtures.
+    enum TestKey {
+        Es256(p256::ecdsa::SigningKey),
+        EdDsa(ed25519_dalek::SigningKey),
+        Rs256(rsa::RsaPrivateKey),
+    }
+
+    impl TestKey {
+        fn es256() -> Self {
+            let key = p256::SecretKey::from_pkcs8_pem(include_str!("../../tests/fixtures/webauthn/es256.pem")).unwrap();
+            TestKey::Es256(key.into())
+        }
+
+        fn eddsa() -> Self {
+            let pem = include_str!("../../tests/fixtures/webauthn/eddsa.pem");
+            TestKey::EdDsa(ed25519_dalek::SigningKey::from_pkcs8_pem(pem).unwrap())
+        }
+
+        fn rs256() -> Self {
+            let pem = include_str!("../../tests/fixtures/webauthn/rs256.pem");
+            TestKey::Rs256(rsa::RsaPrivateKey::from_pkcs8_pem(pem).unwrap())
+        }
+
+        fn cose(&self) -> Vec<u8> {
+            let int = |value: i64| Value::Integer(value.into());
+            let entries = match self {
+                TestKey::Es256(key) => {
+                    let point = key.verifying_key().to_encoded_point(false);
+                    vec![
+                        (int(1), int(2)),
+                        (int(3), int(COSE_ES256)),
+                        (int(-1), int(1)),
+                        (int(-2), Value::Bytes(point.x().unwrap().to_vec())),
+                        (int(-3), Value::Bytes(point.y().unwrap().to_vec())),
+                    ]
+                },
+                TestKey::EdDsa(key) => vec![
+                    (int(1), int(1)),
+                    (int(3), int(COSE_EDDSA)),
+                    (int(-1), int(6)),
+                    (int(-2), Value::Bytes(key.verifying_key().to_bytes().to_vec())),
+                ],
+                TestKey::Rs256(key) => rsa_cose(&key.n().to_bytes_be(), &key.e().to_bytes_be()),
+            };
+            cbor(&Value::Map(entries))
+        }
+
+        fn sign(&self, message: &[u8]) -> Vec<u8> {
+            match self {
+                TestKey::Es256(key) => {
+                    let signature: p256::ecdsa::Signature = key.sign(message);
+                    signature.to_der().as_bytes().to_vec()
+                },
+                TestKey::EdDsa(key) => key.sign(message).to_bytes().to_vec(),
+                TestKey::Rs256(key) => rsa::pkcs1v15::SigningKey::<Sha256>::new(key.clone()).sign(message).to_vec(),
+            }
+        }
+    }
+
+    fn rsa_cose(n: &[u8], e: &[u8]) -> Vec<(Value, Value)> {
+        let int = |value: i64| Value::Integer(value.into());
+        vec![
+            (int(1), int(3)),
+            (int(3), int(COSE_RS256)),
+            (int(-1), Value::Bytes(n.to_vec())),
+            (int(-2), Value::Bytes(e.to_vec())),
+        ]
+    }
+
+    fn cbor(value: &Value) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(value, &mut bytes).unwrap();
+        bytes
+    }
+
+    fn relying_party() -> RelyingParty {
+        RelyingParty { id: RP_ID.to_string(), name: "Test".to_string(), origins: vec![ORIGIN.to_string()] }
+    }
+
+    fn client_data(kind: &str, challenge: &[u8], origin: &str) -> Vec<u8> {
+        serde_json::to_vec(&serde_json::json!({
+            "type": kind,
+            "challenge": b64(challenge),
+            "origin": origin,
+        }))
+        .unwrap()
+    }
+
+    fn authenticator_data(rp_id: &str, flags: u8, sign_count: u32, attested_key: Option<&[u8]>) -> Vec<u8> {
+        let mut data = Sha256::digest(rp_id.as_bytes()).to_vec();
+        data.push(flags | if attested_key.is_some() { FLAG_ATTESTED_CREDENTIAL } else { 0 });
+        data.extend_from_slice(&sign_count.to_be_bytes());
+        if let Some(key) = attested_key {
+            data.extend_from_slice(&[0; 16]);
+            data.extend_from_slice(&(CREDENTIAL_ID.len() as u16).to_be_bytes());
+            data.extend_from_slice(CREDENTIAL_ID);
+            data.extend_from_slice(key);
+        }
+        data
+    }
+
+    fn attestation_object(auth_data: Vec<u8>) -> Vec<u8> {
+        let text = |value: &str| Value::Text(value.to_string());
+        cbor(&Value::Map(vec![
+            (text("fmt"), text("none")),
+            (text("attStmt"), Value::Map(Vec::new())),
+            (text("authData"), Value::Bytes(auth_data)),
+        ]))
+    }
+
+    /// An assertion as the authenticator would produce it, with the parts a
+    /// test may tamper with.
+    struct Assertion {
+        client_data: Vec<u8>,
+        authenticator_data: Vec<u8>,
+        signature: Vec<u8>,
+    }
+
+    impl Assertion {
+        fn new(key: &TestKey, client_data: Vec<u8>, authenticator_data: Vec<u8>) -> Self {
+            let mut signed = authenticator_data.clone();
+            signed.extend_from_slice(&Sha256::digest(&client_data));
+            Self { signature: key.sign(&signed), client_data, authenticator_data }
+        }
+
+        fn valid(key: &TestKey, sign_count: u32) -> Self {
+            Self::new(
+                key,
+                client_data("webauthn.get", CHALLENGE, ORIGIN),
+                authenticator_data(RP_ID, FLAG_USER_PRESENT | FLAG_USER_VERIFIED, sign_count, None),
+            )
+        }
+
+        fn verify(&self, public_key: &[u8], stored_sign_count: u32) -> Result<u32, String> {
+            relying_party().verify_assertion(
+                &self.client_data,
+                &self.authenticator_data,
+                &self.signature,
+                CHALLENGE,
+                StoredCredential { public_key, sign_count: stored_sign_count },
+                true,
+            )
+        }
+    }
+
+    fn register(key: &TestKey) -> Result<NewCredential, String> {
+        relying_party().verify_registration(
+            &client_data("webauthn.create", CHALLENGE, ORIGIN),
+            &attestation_object(authenticator_data(RP_ID, FLAG_USER_PRESENT | FLAG_USER_VERIFIED, 0, Some(&key.cose()))),
+            CHALLENGE,
+            true,
+        )
+    }
+
+    fn registers_and_signs_in(key: TestKey) {
+        let credential = register(&key).unwrap();
+        assert_eq!(credential.credential_id, CREDENTIAL_ID);
+        assert_eq!(credential.public_key, key.cose());
+
+        assert_eq!(Assertion::valid(&key, 1).verify(&credential.public_key, 0), Ok(1));
+        assert_eq!(Assertion::valid(&key, 2).verify(&credential.public_key, 1), Ok(2));
+    }
+
+    #[test]
+    fn registers_and_signs_in_with_es256() {
+        registers_and_signs_in(TestKey::es256());
+    }
+
+    #[test]
+    fn registers_and_signs_in_with_eddsa() {
+        registers_and_signs_in(TestKey::eddsa());
+    }
+
+    #[test]
+    fn registers_and_signs_in_with_rs256() {
+        registers_and_signs_in(TestKey::rs256());
+    }
+
+    #[test]
+    fn accepts_authenticators_without_a_counter() {
+        let key = TestKey::es256();
+        assert_eq!(Assertion::valid(&key, 0).verify(&key.cose(), 0), Ok(0));
+    }
+
+    #[test]
+    fn rejects_wrong_origin() {
+        let key = TestKey::es256();
+        let assertion = Assertion::new(
+            &key,
+            client_data("webauthn.get", CHALLENGE, "https://evil.example"),
+            authenticator_data(RP_ID, FLAG_USER_PRESENT | FLAG_USER_VERIFIED, 1, None),
+        );
+        assert_eq!(assertion.verify(&key.cose(), 0), Err("unexpected origin https://evil.example".to_string()));
+    }
+
+    #[test]
+    fn rejects_wrong_challenge() {
+        let key = TestKey::es256();
+        let assertion = Assertion::new(
+            &key,
+            client_data("webauthn.get", b"another challenge", ORIGIN),
+            authenticator_data(RP_ID, FLAG_USER_PRESENT | FLAG_USER_VERIFIED, 1, None),
+        );
+        assert_eq!(assertion.verify(&key.cose(), 0), Err("challenge mismatch".to_string()));
+    }
+
+    #[test]
+    fn rejects_wrong_rp_id_hash() {
+        let key = TestKey::eddsa();
+        let assertion = Assertion::new(
+            &key,
+            client_data("webauthn.get", CHALLENGE, ORIGIN),
+            authenticator_data("evil.example", FLAG_USER_PRESENT | FLAG_USER_VERIFIED, 1, None),
+        );
+        assert_eq!(assertion.verify(&key.cose(), 0), Err("RP ID hash mismatch".to_string()));
+    }
+
+    #[test]
+    fn rejects_missing_user_verification() {
+        let key = TestKey::eddsa();
+        let assertion = Assertion::new(
+            &key,
+            client_data("webauthn.get", CHALLENGE, ORIGIN),
+            authenticator_data(RP_ID, FLAG_USER_PRESENT, 1, None),
+        );
+        assert_eq!(assertion.verify(&key.cose(), 0), Err("user was not verified".to_string()));
+    }
+
+    #[test]
+    fn rejects_regressed_counter() {
+        let key = TestKey::rs256();
+        let error = Assertion::valid(&key, 4).verify(&key.cose(), 5).unwrap_err();
+        assert!(error.starts_with("signature counter did not increase"), "{}", error);
+        let error = Assertion::valid(&key, 5).verify(&key.cose(), 5).unwrap_err();
+        assert!(error.starts_with("signature counter did not increase"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_bad_signature() {
+        for key in [TestKey::es256(), TestKey::eddsa(), TestKey::rs256()] {
+            let mut assertion = Assertion::valid(&key, 1);
+            // Signed by the authenticator, then altered in transit
+            assertion.authenticator_data[33..37].copy_from_slice(&2u32.to_be_bytes());
+            assert_eq!(assertion.verify(&key.cose(), 0), Err("signature does not verify".to_string()));
+        }
+    }
+
+    #[test]
+    fn rejects_signature_from_another_key() {
+        let assertion = Assertion::valid(&TestKey::es256(), 1);
+        let other = p256::ecdsa::SigningKey::from_slice(&[7; 32]).unwrap();
+        assert_eq!(
+            assertion.verify(&TestKey::Es256(other).cose(), 0),
+            Err("signature does not verify".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_short_rsa_modulus() {
+        let pem = include_str!("../../tests/fixtures/webauthn/rs256-1024.pem");
+        let key = TestKey::Rs256(rsa::RsaPrivateKey::from_pkcs8_pem(pem).unwrap());
+        assert_eq!(
+            register(&key).err(),
+            Some("RSA modulus is shorter than 2048 bits".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_non_standard_rsa_exponent() {
+        let TestKey::Rs256(key) = TestKey::rs256() else { unreachable!() };
+        let cose = cbor(&Value::Map(rsa_cose(&key.n().to_bytes_be(), &[3])));
+        assert_eq!(
+            CoseKey::from_cbor(&cose).err(),
+            Some("RSA public exponent is not 65537".to_string())
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use std::future::Future;
+use std::sync::Once;
+use std::time::{Duration, Instant};
+use sqlx::PgPool;
+use uuid::Uuid;
+use rust_rest::domains::auth::service::{login_user, register_user, register_user_uniform, request_magic_link};
+use rust_rest::utils::auth::init_password_hasher;
+use rust_rest::utils::extractors::RequestContext;
+
+/// Login, uniform registration and magic links must not reveal whether an
+/// email is registered through their response time. These run against the
+/// database and are skipped unless `DATABASE_URL` is set.
+const SAMPLES: usize = 7;
+
+/// How far apart the two medians may be before the difference counts as a
+/// leak: `fraction` of the slower one, but at least `floor`.
+struct Tolerance {
+    fraction: f64,
+    floor: Duration,
+}
+
+/// Both paths hash a password, which dominates; the rest is a query or two.
+/// Below the floor the difference is scheduler noise rather than a second hash.
+const HASHED: Tolerance = Tolerance { fraction: 0.25, floor: Duration::from_millis(25) };
+/// Paths that only touch the database, where a skipped query is most of the
+/// time and shows as a multiple rather than a fraction. These take more
+/// samples since each is quick and noisier.
+const DATABASE_ONLY: Tolerance = Tolerance { fraction: 0.5, floor: Duration::from_micros(500) };
+const DATABASE_ONLY_SAMPLES: usize = 40;
+
+const PASSWORD: &str = "correct-horse-battery-9";
+
+static INIT: Once = Once::new();
+
+async fn pool() -> Option<PgPool> {
+    let Ok(url) = std::env::var("DATABASE_URL") else {
+        eprintln!("DATABASE_URL is not set; skipping");
+        return None;
+    };
+    INIT.call_once(|| {
+        // Every sample is a failed attempt; keep them clear of the limiter
+        // and the lockout so both paths run to the end.
+        std::env::set_var("RATE_LIMIT_LOGIN", "10000/60");
+        std::env::set_var("RATE_LIMIT_MAGIC_LINK", "10000/60");
+        std::env::set_var("LOCKOUT_THRESHOLD", "10000");
+        init_password_hasher();
+    });
+    Some(PgPool::connect(&url).await.expect("connect to DATABASE_URL"))
+}
+
+fn unique_email() -> String {
+    format!("timing-{}@example.test", Uuid::new_v4().simple())
+}
+
+async fn elapsed<F: Future>(future: F) -> Duration {
+    let start = Instant::now();
+    future.await;
+    start.elapsed()
+}
+
+fn median(mut samples: Vec<Duration>) -> Duration {
+    samples.sort();
+    samples[samples.len() / 2]
+}
+
+fn assert_indistinguishable(known: Vec<Duration>, unknown: Vec<Duration>, tolerance: Tolerance) {
+    let (known, unknown) = (median(known), median(unknown));
+    let slower = known.max(unknown);
+    let difference = slower - known.min(unknown);
+    let tolerance = slower.mul_f64(tolerance.fraction).max(tolerance.floor);
+    assert!(
+        difference <= tolerance,
+        "known email took {:?} and unknown {:?}; more than {:?} apart",
+        known, unknown, tolerance
+    );
+}
+
+async fn register(pool: &PgPool, email: &str) {
+    register_user(pool, email.to_string(), None, None, None, PASSWORD, &RequestContext::default())
+        .await
+        .unwrap();
+}
+
+async fn cleanup(pool: &PgPool, emails: &[String]) {
+    sqlx::query("DELETE FROM users WHERE email = ANY($1)").bind(emails).execute(pool).await.unwrap();
+    sqlx::query("DELETE FROM login_lockouts WHERE email = ANY($1)").bind(emails).execute(pool).await.unwrap();
+}
+
+#[actix_web::test]
+async fn login_takes_as_long_for_unknown_emails() {
+    let Some(pool) = pool().await else { return };
+    let context = RequestContext::default();
+    let known = unique_email();
+    register(&pool, &known).await;
+    let mut emails = vec![known.clone()];
+
+    // Alternate the two so drift in machine load affects both alike
+    let (mut known_times, mut unknown_times) = (Vec::new(), Vec::new());
+    for _ in 0..SAMPLES {
+        known_times.push(elapsed(login_user(&pool, &known, "wrong-password-1", &context)).await);
+        let unknown = unique_email();
+        unknown_times.push(elapsed(login_user(&pool, &unknown, "wrong-password-1", &context)).await);
+        emails.push(unknown);
+    }
+
+    cleanup(&pool, &emails).await;
+    assert_indistinguishable(known_times, unknown_times, HASHED);
+}
+
+#[actix_web::test]
+async fn uniform_registration_takes_as_long_for_taken_emails() {
+    let Some(pool) = pool().await else { return };
+    let context = RequestContext::default();
+    let known = unique_email();
+    register(&pool, &known).await;
+    let mut emails = vec![known.clone()];
+
+    let (mut known_times, mut unknown_times) = (Vec::new(), Vec::new());
+    for _ in 0..SAMPLES {
+        let result = register_user_uniform(&pool, known.clone(), None, None, None, PASSWORD, &context);
+        known_times.push(elapsed(async { result.await.unwrap() }).await);
+        let unknown = unique_email();
+        let result = register_user_uniform(&pool, unknown.clone(), None, None, None, PASSWORD, &context);
+        unknown_times.push(elapsed(async { result.await.unwrap() }).await);
+        emails.push(unknown);
+    }
+
+    cleanup(&pool, &emails).await;
+    assert_indistinguishable(known_times, unknown_times, HASHED);
+}
+
+#[actix_web::test]
+async fn magic_link_takes_as_long_for_unknown_emails() {
+    let Some(pool) = pool().await else { return };
+    let known = unique_email();
+    register(&pool, &known).await;
+    let mut emails = vec![known.clone()];
+
+    let (mut known_times, mut unknown_times) = (Vec::new(), Vec::new());
+    for _ in 0..DATABASE_ONLY_SAMPLES {
+        known_times.push(elapsed(async { request_magic_link(&pool, &known).await.unwrap() }).await);
+        let unknown = unique_email();
+        unknown_times.push(elapsed(async { request_magic_link(&pool, &unknown).await.unwrap() }).await);
+        emails.push(unknown);
+    }
+
+    cleanup(&pool, &emails).await;
+    assert_indistinguishable(known_times, unknown_times, DATABASE_ONLY);
+}
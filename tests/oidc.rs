@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use actix_web::cookie::SameSite;
+use actix_web::http::{header, StatusCode};
+use actix_web::{get, post, test, web, App, HttpResponse, HttpServer};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::Utc;
+use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
+use reqwest::Url;
+use rsa::pkcs8::DecodePrivateKey;
+use rsa::traits::PublicKeyParts;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use uuid::Uuid;
+use rust_rest::domains::identity::route as identity_routes;
+use rust_rest::utils::error::AppError;
+use rust_rest::utils::oidc::{pkce_challenge, random_token, OidcProvider};
+
+const KEY_PEM: &str = include_str!("fixtures/oidc/idp.pem");
+const KEY_ID: &str = "mock-key";
+const CLIENT_ID: &str = "rust-rest";
+
+/// An authorization the mock provider granted, waiting to be redeemed.
+struct Grant {
+    code_challenge: String,
+    id_token_claims: Value,
+}
+
+struct MockIdp {
+    issuer: String,
+    grants: Mutex<HashMap<String, Grant>>,
+}
+
+#[get("/.well-known/openid-configuration")]
+async fn discovery(idp: web::Data<MockIdp>) -> HttpResponse {
+    HttpResponse::Ok().json(json!({
+        "issuer": idp.issuer,
+        "authorization_endpoint": format!("{}/authorize", idp.issuer),
+        "token_endpoint": format!("{}/token", idp.issuer),
+        "jwks_uri": format!("{}/jwks", idp.issuer),
+    }))
+}
+
+#[get("/jwks")]
+async fn jwks() -> HttpResponse {
+    let key = rsa::RsaPrivateKey::from_pkcs8_pem(KEY_PEM).unwrap();
+    HttpResponse::Ok().json(json!({
+        "keys": [{
+            "kty": "RSA",
+            "kid": KEY_ID,
+            "use": "sig",
+            "alg": "RS256",
+            "n": URL_SAFE_NO_PAD.encode(key.n().to_bytes_be()),
+            "e": URL_SAFE_NO_PAD.encode(key.e().to_bytes_be()),
+        }]
+    }))
+}
+
+#[derive(Deserialize)]
+struct TokenRequest {
+    grant_type: String,
+    code: String,
+    code_verifier: String,
+    client_id: String,
+}
+
+#[post("/token")]
+async fn token(idp: web::Data<MockIdp>, form: web::Form<TokenRequest>) -> HttpResponse {
+    let grant = idp.grants.lock().unwrap().remove(&form.code);
+    let Some(grant) = grant else {
+        return HttpResponse::BadRequest().json(json!({ "error": "invalid_grant" }));
+    };
+    if form.grant_type != "authorization_code"
+        || form.client_id != CLIENT_ID
+        || pkce_challenge(&form.code_verifier) != grant.code_challenge
+    {
+        return HttpResponse::BadRequest().json(json!({ "error": "invalid_grant" }));
+    }
+
+    let mut header = Header::new(Algorithm::RS256);
+    header.kid = Some(KEY_ID.to_string());
+    let key = EncodingKey::from_rsa_pem(KEY_PEM.as_bytes()).unwrap();
+    let id_token = encode(&header, &grant.id_token_claims, &key).unwrap();
+    HttpResponse::Ok().json(json!({ "access_token": "opaque", "token_type": "Bearer", "id_token": id_token }))
+}
+
+/// Starts a mock provider on a free port and returns a client configured
+/// for it under `name`.
+async fn start(name: &str) -> (OidcProvider, web::Data<MockIdp>) {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let issuer = format!("http://{}", listener.local_addr().unwrap());
+    let idp = web::Data::new(MockIdp { issuer: issuer.clone(), grants: Mutex::new(HashMap::new()) });
+
+    let data = idp.clone();
+    let server = HttpServer::new(move || {
+        App::new().app_data(data.clone()).service(discovery).service(jwks).service(token)
+    })
+    .workers(1)
+    .listen(listener)
+    .unwrap()
+    .run();
+    actix_web::rt::spawn(server);
+
+    // Each test uses its own provider name, so they can set these concurrently
+    std::env::set_var(format!("OIDC_{}_ISSUER", name.to_uppercase()), &issuer);
+    std::env::set_var(format!("OIDC_{}_CLIENT_ID", name.to_uppercase()), CLIENT_ID);
+    (OidcProvider::from_env(name).unwrap(), idp)
+}
+
+/// A sign-in in progress: the values our side keeps while the browser is at
+/// the provider.
+struct SignIn {
+    nonce: String,
+    code_verifier: String,
+    code: String,
+}
+
+/// Starts a sign-in and plays the provider's part: checks the authorization
+/// URL and grants a code for a valid ID token, whose claims `tweak` may alter.
+async fn sign_in(provider: &OidcProvider, idp: &MockIdp, tweak: impl FnOnce(&mut Value)) -> SignIn {
+    let (state, nonce, code_verifier) = (random_token(), random_token(), random_token());
+    let url = provider.authorization_url(&state, &nonce, &pkce_challenge(&code_verifier)).await.unwrap();
+
+    let (granted_state, code) = authorize(provider, idp, &url, tweak);
+    assert_eq!(granted_state, state);
+    SignIn { nonce, code_verifier, code }
+}
+
+/// The provider's side of the authorization endpoint: checks the URL the
+/// browser was sent to and grants a code. Returns the `state` and the code.
+fn authorize(provider: &OidcProvider, idp: &MockIdp, url: &str, tweak: impl FnOnce(&mut Value)) -> (String, String) {
+    let url = Url::parse(url).unwrap();
+    assert_eq!(url.path(), "/authorize");
+    let params: HashMap<String, String> = url.query_pairs().into_owned().collect();
+    assert_eq!(params["response_type"], "code");
+    assert_eq!(params["client_id"], CLIENT_ID);
+    assert_eq!(params["code_challenge_method"], "S256");
+    assert!(params["redirect_uri"].ends_with(&format!("/api/auth/oidc/{}/callback", provider.name)));
+
+    let now = Utc::now().timestamp();
+    let mut claims = json!({
+        "iss": idp.issuer,
+        "aud": CLIENT_ID,
+        "sub": "user-1",
+        "email": "user@example.com",
+        "email_verified": true,
+        "nonce": params["nonce"],
+        "iat": now,
+        "exp": now + 300,
+    });
+    tweak(&mut claims);
+
+    let code = random_token();
+    idp.grants.lock().unwrap().insert(
+        code.clone(),
+        Grant { code_challenge: params["code_challenge"].clone(), id_token_claims: claims },
+    );
+    (params["state"].clone(), code)
+}
+
+fn assert_rejected<T: std::fmt::Debug>(result: Result<T, AppError>) {
+    match result {
+        Err(AppError::AuthenticationError(_)) => {},
+        other => panic!("expected the sign-in to be rejected, got {:?}", other),
+    }
+}
+
+#[actix_web::test]
+async fn completes_pkce_round_trip() {
+    let (provider, idp) = start("pkce").await;
+    let sign_in = sign_in(&provider, &idp, |_| {}).await;
+
+    let claims = provider.exchange_code(&sign_in.code, &sign_in.code_verifier, &sign_in.nonce).await.unwrap();
+    assert_eq!(claims.sub, "user-1");
+    assert_eq!(claims.email.as_deref(), Some("user@example.com"));
+    assert_eq!(claims.email_verified, Some(true));
+}
+
+#[actix_web::test]
+async fn rejects_wrong_code_verifier() {
+    let (provider, idp) = start("verifier").await;
+    let sign_in = sign_in(&provider, &idp, |_| {}).await;
+
+    assert_rejected(provider.exchange_code(&sign_in.code, &random_token(), &sign_in.nonce).await);
+}
+
+#[actix_web::test]
+async fn rejects_wrong_nonce() {
+    let (provider, idp) = start("nonce").await;
+    let sign_in = sign_in(&provider, &idp, |claims| claims["nonce"] = json!("replayed")).await;
+
+    assert_rejected(provider.exchange_code(&sign_in.code, &sign_in.code_verifier, &sign_in.nonce).await);
+}
+
+#[actix_web::test]
+async fn rejects_wrong_issuer() {
+    let (provider, idp) = start("issuer").await;
+    let sign_in = sign_in(&provider, &idp, |claims| claims["iss"] = json!("https://evil.example")).await;
+
+    assert_rejected(provider.exchange_code(&sign_in.code, &sign_in.code_verifier, &sign_in.nonce).await);
+}
+
+#[actix_web::test]
+async fn rejects_wrong_audience() {
+    let (provider, idp) = start("audience").await;
+    let sign_in = sign_in(&provider, &idp, |claims| claims["aud"] = json!("another-client")).await;
+
+    assert_rejected(provider.exchange_code(&sign_in.code, &sign_in.code_verifier, &sign_in.nonce).await);
+}
+
+#[actix_web::test]
+async fn rejects_expired_id_token() {
+    let (provider, idp) = start("expired").await;
+    // Past the default 60 seconds of leeway
+    let sign_in = sign_in(&provider, &idp, |claims| claims["exp"] = json!(Utc::now().timestamp() - 120)).await;
+
+    assert_rejected(provider.exchange_code(&sign_in.code, &sign_in.code_verifier, &sign_in.nonce).await);
+}
+
+/// Runs the callback through the HTTP layer, so it needs the database and is
+/// skipped unless `DATABASE_URL` is set.
+#[actix_web::test]
+async fn callback_requires_the_browser_that_started_it() {
+    let Ok(database_url) = std::env::var("DATABASE_URL") else {
+        eprintln!("DATABASE_URL is not set; skipping");
+        return;
+    };
+    let (provider, idp) = start("binding").await;
+    // Only this test goes through the configured providers
+    std::env::set_var("OIDC_PROVIDERS", "binding");
+    if std::env::var("JWT_SECRET").is_err() {
+        std::env::set_var("JWT_SECRET", random_token());
+    }
+    let pool = PgPool::connect(&database_url).await.unwrap();
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(pool.clone()))
+            .service(web::scope("/api/auth").configure(identity_routes::configure_sign_in))
+    ).await;
+
+    let email = format!("oidc-{}@example.test", Uuid::new_v4().simple());
+    let subject = Uuid::new_v4().to_string();
+    let start_sign_in = || async {
+        let request = test::TestRequest::get().uri("/api/auth/oidc/binding/authorize").to_request();
+        let response = test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        let cookie = response.response().cookies().find(|cookie| cookie.name() == "oidc_binding").unwrap().into_owned();
+        assert_eq!(cookie.http_only(), Some(true));
+        assert_eq!(cookie.same_site(), Some(SameSite::Lax));
+        assert_eq!(cookie.path(), Some("/api/auth/oidc"));
+
+        let location = response.headers().get(header::LOCATION).unwrap().to_str().unwrap();
+        let (state, code) = authorize(&provider, &idp, location, |claims| {
+            claims["sub"] = json!(subject);
+            claims["email"] = json!(email);
+        });
+        (format!("/api/auth/oidc/binding/callback?code={}&state={}", code, state), cookie)
+    };
+
+    // Someone else's browser, e.g. a victim sent a link URL started on the
+    // sender's account, has no cookie or a different one
+    let (callback, _) = start_sign_in().await;
+    let response = test::call_service(&app, test::TestRequest::get().uri(&callback).to_request()).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let (callback, _) = start_sign_in().await;
+    let (_, other_cookie) = start_sign_in().await;
+    let request = test::TestRequest::get().uri(&callback).cookie(other_cookie).to_request();
+    assert_eq!(test::call_service(&app, request).await.status(), StatusCode::BAD_REQUEST);
+
+    let (callback, cookie) = start_sign_in().await;
+    let request = test::TestRequest::get().uri(&callback).cookie(cookie).to_request();
+    let response = test::call_service(&app, request).await;
+    let status = response.status();
+    let removal = response.response().cookies().find(|cookie| cookie.name() == "oidc_binding").map(|cookie| cookie.value().to_string());
+
+    sqlx::query("DELETE FROM users WHERE email = $1").bind(&email).execute(&pool).await.unwrap();
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(removal.as_deref(), Some(""));
+}
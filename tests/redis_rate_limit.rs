@@ -0,0 +1,94 @@
+use std::time::Duration;
+use redis::AsyncCommands;
+use rust_rest::utils::oidc::random_token;
+use rust_rest::utils::rate_limiter::{Quota, RateLimitStore, RedisStore};
+
+/// These run the GCRA script against a real server and are skipped unless
+/// `REDIS_URL` is set, e.g. `REDIS_URL=redis://localhost:6379 cargo test`.
+async fn store() -> Option<(RedisStore, redis::aio::MultiplexedConnection)> {
+    let Ok(url) = std::env::var("REDIS_URL") else {
+        eprintln!("REDIS_URL is not set; skipping");
+        return None;
+    };
+    let store = RedisStore::connect(&url).await.expect("connect to REDIS_URL");
+    let connection = redis::Client::open(url.as_str())
+        .unwrap()
+        .get_multiplexed_async_connection()
+        .await
+        .unwrap();
+    Some((store, connection))
+}
+
+fn key() -> String {
+    format!("test:rate_limit:{}", random_token())
+}
+
+#[actix_web::test]
+async fn allows_the_burst_then_denies() {
+    let Some((store, _)) = store().await else { return };
+    let (key, quota) = (key(), Quota::new(3, 60));
+
+    for remaining in [2, 1, 0] {
+        let decision = store.check(&key, &quota).await.unwrap();
+        assert!(decision.allowed);
+        assert_eq!(decision.remaining, remaining);
+        assert_eq!(decision.retry_after, Duration::ZERO);
+    }
+
+    let decision = store.check(&key, &quota).await.unwrap();
+    assert!(!decision.allowed);
+    assert_eq!(decision.remaining, 0);
+}
+
+#[actix_web::test]
+async fn reports_when_to_retry() {
+    let Some((store, _)) = store().await else { return };
+    let (key, quota) = (key(), Quota::new(2, 60));
+
+    store.check(&key, &quota).await.unwrap();
+    store.check(&key, &quota).await.unwrap();
+    let decision = store.check(&key, &quota).await.unwrap();
+
+    // One request is refilled every 30 seconds; allow for the time the calls took
+    assert!(!decision.allowed);
+    assert!(decision.retry_after <= Duration::from_secs(30), "{:?}", decision.retry_after);
+    assert!(decision.retry_after > Duration::from_secs(29), "{:?}", decision.retry_after);
+    assert!(decision.reset_after <= Duration::from_secs(60), "{:?}", decision.reset_after);
+    assert!(decision.reset_after > Duration::from_secs(59), "{:?}", decision.reset_after);
+}
+
+#[actix_web::test]
+async fn expires_the_key_once_refilled() {
+    let Some((store, mut connection)) = store().await else { return };
+    let (key, quota) = (key(), Quota::new(2, 1));
+
+    store.check(&key, &quota).await.unwrap();
+    let ttl: i64 = connection.pttl(&key).await.unwrap();
+    assert!(ttl > 0 && ttl <= 500, "ttl {}", ttl);
+
+    store.check(&key, &quota).await.unwrap();
+    assert!(!store.check(&key, &quota).await.unwrap().allowed);
+    let ttl: i64 = connection.pttl(&key).await.unwrap();
+    assert!(ttl > 500 && ttl <= 1000, "ttl {}", ttl);
+
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    let exists: bool = connection.exists(&key).await.unwrap();
+    assert!(!exists);
+    let decision = store.check(&key, &quota).await.unwrap();
+    assert!(decision.allowed);
+    assert_eq!(decision.remaining, 1);
+}
+
+#[actix_web::test]
+async fn reset_forgets_the_key() {
+    let Some((store, mut connection)) = store().await else { return };
+    let (key, quota) = (key(), Quota::new(1, 60));
+
+    store.check(&key, &quota).await.unwrap();
+    assert!(!store.check(&key, &quota).await.unwrap().allowed);
+
+    store.reset(&key).await.unwrap();
+    let exists: bool = connection.exists(&key).await.unwrap();
+    assert!(!exists);
+    assert!(store.check(&key, &quota).await.unwrap().allowed);
+}